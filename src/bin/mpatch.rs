@@ -1,22 +1,41 @@
 use std::{env, path::PathBuf};
 
 use clap::Parser;
-use mpatch::{filtering::DistanceFilter, patch::PatchPaths, LCSMatcher};
+use mpatch::{DuplicateTargetPolicy, FinalNewline, PatchOptions, RunConfig, WhitespaceMode};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let matcher = LCSMatcher;
-    let filter = DistanceFilter::new(2);
+    let options = PatchOptions {
+        dryrun: cli.dryrun,
+        dump_matching_dir: cli.dump_matching.map(PathBuf::from),
+        create_target_root: cli.create_target_root,
+        lenient_removes: cli.lenient_removes,
+        sort_files: cli.sort_files,
+        whitespace: cli.whitespace,
+        max_in_flight: cli.max_in_flight,
+        report_file: cli.report.map(PathBuf::from),
+        duplicate_targets: cli.duplicate_targets,
+        case_insensitive_paths: cli.case_insensitive_paths,
+        normalize_inserted_endings: cli.normalize_endings,
+        skip_already_applied: cli.skip_already_applied,
+        expected_source_hashes: cli.expected_source_hashes.into_iter().collect(),
+        max_total_edits: cli.max_total_edits,
+        group_rejects: cli.group_rejects,
+        comment_prefixes: cli.comment_prefixes,
+        skip_whitespace_hunks: cli.skip_whitespace_hunks,
+        final_newline: cli.final_newline,
+        ..Default::default()
+    };
 
-    let patch_paths = PatchPaths::new(
-        cli.source_dir.into(),
-        env::current_dir()?,
-        PathBuf::from(cli.patch_file),
-        cli.rejects_file.map(PathBuf::from),
-    );
+    let config = RunConfig {
+        rejects_file: cli.rejects_file.map(PathBuf::from),
+        strip: cli.strip,
+        options,
+        ..RunConfig::new(cli.source_dir, env::current_dir()?, cli.patch_file)
+    };
 
-    if let Err(error) = mpatch::apply_all(patch_paths, cli.strip, cli.dryrun, matcher, filter) {
+    if let Err(error) = mpatch::run(config) {
         eprintln!("{}", error);
         return Err(Box::new(error));
     }
@@ -36,4 +55,46 @@ struct Cli {
     strip: usize,
     #[arg(long = "dryrun", default_value_t = false)]
     dryrun: bool,
+    #[arg(long = "dump-matching")]
+    dump_matching: Option<String>,
+    #[arg(long = "create-target-root", default_value_t = false)]
+    create_target_root: bool,
+    #[arg(long = "lenient-removes", default_value_t = false)]
+    lenient_removes: bool,
+    #[arg(long = "sort-files", default_value_t = false)]
+    sort_files: bool,
+    #[arg(long = "whitespace", value_enum, default_value_t = WhitespaceMode::NoWarn)]
+    whitespace: WhitespaceMode,
+    #[arg(long = "max-in-flight")]
+    max_in_flight: Option<usize>,
+    #[arg(long = "report")]
+    report: Option<String>,
+    #[arg(long = "duplicate-targets", value_enum, default_value_t = DuplicateTargetPolicy::Allow)]
+    duplicate_targets: DuplicateTargetPolicy,
+    #[arg(long = "case-insensitive-paths", default_value_t = false)]
+    case_insensitive_paths: bool,
+    #[arg(long = "normalize-endings", default_value_t = false)]
+    normalize_endings: bool,
+    #[arg(long = "skip-already-applied", default_value_t = false)]
+    skip_already_applied: bool,
+    #[arg(long = "expected-source-hash", value_name = "PATH=HASH", value_parser = parse_source_hash)]
+    expected_source_hashes: Vec<(PathBuf, String)>,
+    #[arg(long = "max-total-edits")]
+    max_total_edits: Option<usize>,
+    #[arg(long = "group-rejects", default_value_t = false)]
+    group_rejects: bool,
+    #[arg(long = "comment-prefix")]
+    comment_prefixes: Vec<String>,
+    #[arg(long = "skip-whitespace-hunks", default_value_t = false)]
+    skip_whitespace_hunks: bool,
+    #[arg(long = "final-newline", value_enum, default_value_t = FinalNewline::Never)]
+    final_newline: FinalNewline,
+}
+
+/// Parses the expected hash of a source file in the form PATH=HASH.
+fn parse_source_hash(entry: &str) -> Result<(PathBuf, String), String> {
+    entry
+        .split_once('=')
+        .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+        .ok_or(format!("expected PATH=HASH, found '{entry}'"))
 }
@@ -1,10 +1,15 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Display,
+    ops::Range,
     path::{Path, PathBuf},
     vec::IntoIter,
 };
 
-use crate::{Error, ErrorKind};
+use crate::{
+    patch::{Change, FileChangeType, LineChangeType},
+    Error, ErrorKind, LineEnding,
+};
 
 /// A VersionDiff represents a diff between two versions of a project or parts of a projects.
 /// A VersionDiff comprises one or more FileDiffs which in turn represent diffs for individual
@@ -25,6 +30,63 @@ impl VersionDiff {
         VersionDiff::try_from(content)
     }
 
+    /// Parses the given content into a VersionDiff like `VersionDiff::try_from`, but skips each
+    /// FileDiff that cannot be parsed instead of failing, e.g., to salvage a partially corrupted
+    /// diff.
+    ///
+    /// ## Returns
+    /// Returns the VersionDiff with all FileDiffs that have been parsed, together with the errors
+    /// of the skipped FileDiffs in the order in which they appear in the content. In contrast to
+    /// `VersionDiff::try_from`, the returned VersionDiff may be empty.
+    pub fn try_from_lenient(content: String) -> (VersionDiff, Vec<Error>) {
+        let mut file_diffs = vec![];
+        let mut errors = vec![];
+        for file_diff in split_file_diffs(&content) {
+            match FileDiff::try_from(file_diff) {
+                Ok(file_diff) => file_diffs.push(file_diff),
+                Err(error) => errors.push(error),
+            }
+        }
+        (VersionDiff { file_diffs }, errors)
+    }
+
+    /// Parses the given content of a diff in the normal format of `diff` (i.e., created without
+    /// `-u`) into a VersionDiff. The hunks of the normal format consist of a command such as `4c4`,
+    /// `5a6,8`, or `1,3d0` that is followed by the removed lines (`< line`) and the added lines
+    /// (`> line`). They are converted into unified hunks without context lines, so that the
+    /// VersionDiff can be patched like any other VersionDiff.
+    ///
+    /// Each FileDiff must start with the diff command that created it (e.g., as written by
+    /// `diff -r`), because the normal format has no file headers; the source and target paths are
+    /// taken from the last two arguments of the command. As the normal format cannot express the
+    /// creation or removal of files, all FileDiffs modify their file.
+    ///
+    /// # Error
+    /// Returns an Error of kind DiffParseError if the content is empty, if a FileDiff does not
+    /// start with a diff command, or if a command is malformed or does not match the number of
+    /// removed and added lines that follow it.
+    pub fn from_normal(content: String) -> Result<VersionDiff, Error> {
+        let file_diffs = split_file_diffs(&content)
+            .into_iter()
+            .map(|lines| {
+                let mut file_diff = FileDiff::try_from(normal_to_unified(lines)?)?;
+                // Hunks that start at line 0 only add lines at the start of the file or remove
+                // lines from it; they do not create or remove the file as in a unified diff
+                file_diff.modification_only = true;
+                Ok(file_diff)
+            })
+            .collect::<Result<Vec<FileDiff>, Error>>()?;
+
+        if file_diffs.is_empty() {
+            Err(Error::new(
+                "the given diff is empty",
+                ErrorKind::DiffParseError,
+            ))
+        } else {
+            Ok(Self { file_diffs })
+        }
+    }
+
     /// Returns a reference to the slice of FileDiffs in this VersionDiff.
     pub fn file_diffs(&self) -> &[FileDiff] {
         self.file_diffs.as_slice()
@@ -39,6 +101,45 @@ impl VersionDiff {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns true if this VersionDiff contains more than one FileDiff that changes the same file
+    /// (see `FileDiff::changed_file_path`), e.g., because several diffs have been concatenated.
+    /// Applying such FileDiffs one after another may apply changes twice or cause conflicts.
+    /// Diffs of binary files are not considered.
+    pub fn has_duplicate_targets(&self) -> bool {
+        let mut paths = HashSet::new();
+        self.file_diffs
+            .iter()
+            .filter(|d| !d.is_binary())
+            .any(|d| !paths.insert(d.changed_file_path()))
+    }
+
+    /// Serializes this VersionDiff like its `Display` implementation, but terminates the lines
+    /// with the given line ending instead of LF, e.g., for tools that expect CRLF-terminated
+    /// diffs. Like with `Display`, the last line is not terminated.
+    pub fn to_string_with(&self, line_ending: LineEnding) -> String {
+        let text = self.to_string();
+        match line_ending {
+            LineEnding::Lf => text,
+            LineEnding::Crlf => text.replace('\n', line_ending.as_str()),
+        }
+    }
+
+    /// Retains only the hunks for which the predicate returns true in each FileDiff. FileDiffs
+    /// that no longer contain any hunk are removed; diffs of binary files, which have no hunks,
+    /// and FileDiffs that change the mode of a file are kept.
+    ///
+    /// The locations of the retained hunks are not updated. As patches are located by the lines
+    /// in the source file, the retained hunks can still be applied.
+    pub fn retain_hunks(&mut self, f: impl Fn(&Hunk) -> bool) {
+        self.file_diffs.retain_mut(|file_diff| {
+            if file_diff.is_binary() {
+                return true;
+            }
+            file_diff.hunks.retain(&f);
+            !file_diff.hunks.is_empty() || file_diff.mode_change().is_some()
+        });
+    }
 }
 
 impl IntoIterator for VersionDiff {
@@ -69,24 +170,10 @@ impl TryFrom<String> for VersionDiff {
     type Error = crate::Error;
 
     fn try_from(content: String) -> Result<Self, Self::Error> {
-        let mut file_diffs = vec![];
-
-        let mut file_diff_content = vec![];
-        for line in content.lines() {
-            // Collect lines until the next FileDiff header
-            if line.starts_with("diff ") {
-                if !file_diff_content.is_empty() {
-                    file_diffs.push(FileDiff::try_from(file_diff_content)?);
-                }
-                file_diff_content = vec![];
-            }
-            file_diff_content.push(line.to_string());
-        }
-
-        // push the last FileDiff
-        if !file_diff_content.is_empty() {
-            file_diffs.push(FileDiff::try_from(file_diff_content)?);
-        }
+        let file_diffs = split_file_diffs(&content)
+            .into_iter()
+            .map(FileDiff::try_from)
+            .collect::<Result<Vec<FileDiff>, Error>>()?;
 
         if file_diffs.is_empty() {
             Err(Error::new(
@@ -99,33 +186,249 @@ impl TryFrom<String> for VersionDiff {
     }
 }
 
+/// Splits the content of a diff into the lines of its FileDiffs.
+fn split_file_diffs(content: &str) -> Vec<Vec<String>> {
+    let mut file_diffs = vec![];
+
+    let mut file_diff_content: Vec<String> = vec![];
+    // A byte order mark and blank lines before the first header are skipped
+    let lines = content
+        .strip_prefix('\u{feff}')
+        .unwrap_or(content)
+        .lines()
+        .skip_while(|line| line.trim().is_empty());
+    for line in lines {
+        // Collect lines until the next FileDiff header. A binary file marker is a header of
+        // its own, unless it directly follows the diff command that produced it.
+        let starts_file_diff = line.starts_with("diff ")
+            || line.starts_with(SVN_INDEX_PREFIX)
+            || (line.starts_with(BINARY_MARKER_PREFIX)
+                && !(file_diff_content.len() == 1 && file_diff_content[0].starts_with("diff ")));
+        if starts_file_diff {
+            if !file_diff_content.is_empty() {
+                file_diffs.push(file_diff_content);
+            }
+            file_diff_content = vec![];
+        }
+        file_diff_content.push(line.to_string());
+    }
+
+    // push the last FileDiff
+    if !file_diff_content.is_empty() {
+        file_diffs.push(file_diff_content);
+    }
+    file_diffs
+}
+
+/// Converts the lines of a FileDiff in the normal format of `diff` into the lines of the same
+/// FileDiff in the unified format (see `VersionDiff::from_normal`). Diffs of binary files are the
+/// same in both formats and are returned as they are.
+fn normal_to_unified(lines: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut lines = lines.into_iter().peekable();
+    let diff_command = lines
+        .next()
+        .filter(|line| line.starts_with("diff "))
+        .ok_or(Error::new(
+            "a file diff in normal format must start with a diff command",
+            ErrorKind::DiffParseError,
+        ))?;
+    if lines
+        .peek()
+        .is_some_and(|line| line.starts_with(BINARY_MARKER_PREFIX))
+    {
+        return Ok([diff_command].into_iter().chain(lines).collect());
+    }
+    let arguments: Vec<&str> = diff_command.split_whitespace().skip(1).collect();
+    let [.., source_path, target_path] = arguments.as_slice() else {
+        return Err(Error::new(
+            &format!("no source and target path in diff command: {diff_command}"),
+            ErrorKind::DiffParseError,
+        ));
+    };
+    let mut unified = vec![
+        diff_command.clone(),
+        format!("--- {source_path}"),
+        format!("+++ {target_path}"),
+    ];
+
+    while let Some(command) = lines.next() {
+        let (source_range, operation, target_range) = parse_normal_command(&command)?;
+        let source_length = match operation {
+            'a' => 0,
+            _ => source_range.end - source_range.start,
+        };
+        let target_length = match operation {
+            'd' => 0,
+            _ => target_range.end - target_range.start,
+        };
+        unified.push(format!(
+            "@@ -{},{source_length} +{},{target_length} @@",
+            source_range.start, target_range.start
+        ));
+
+        // The removed lines are followed by the added lines, separated by '---' for changes
+        let (mut removed, mut added) = (0, 0);
+        while let Some(line) = lines.next_if(|line| !is_normal_command(line)) {
+            if let Some(text) = line.strip_prefix('<') {
+                removed += 1;
+                unified.push(format!("-{}", text.strip_prefix(' ').unwrap_or(text)));
+            } else if let Some(text) = line.strip_prefix('>') {
+                added += 1;
+                unified.push(format!("+{}", text.strip_prefix(' ').unwrap_or(text)));
+            } else if line.starts_with('\\') {
+                unified.push(line);
+            } else if line != "---" || operation != 'c' {
+                return Err(Error::new(
+                    &format!("invalid line in normal diff hunk: {line}"),
+                    ErrorKind::DiffParseError,
+                ));
+            }
+        }
+        if removed != source_length || added != target_length {
+            return Err(Error::new(
+                &format!("the lines of the normal diff hunk do not match its command: {command}"),
+                ErrorKind::DiffParseError,
+            ));
+        }
+    }
+    Ok(unified)
+}
+
+/// Returns true if the line is a command of a normal diff hunk (e.g., `4c4`).
+fn is_normal_command(line: &str) -> bool {
+    line.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Parses a command of a normal diff hunk of the form `RANGE OPERATION RANGE` (e.g., `1,3d0` or
+/// `5a6,8`) into the source range, the operation (`a`, `c`, or `d`), and the target range. Each
+/// range is returned as the half-open range of its line numbers; a range of an added or deleted
+/// hunk that consists of a single number refers to the line after which the lines are added or
+/// the lines have been deleted and is returned as an empty range starting at that line.
+///
+/// ## Error
+/// Returns an Error of kind DiffParseError if the command is malformed.
+fn parse_normal_command(command: &str) -> Result<(Range<usize>, char, Range<usize>), Error> {
+    let invalid = || {
+        Error::new(
+            &format!("invalid normal diff command: {command}"),
+            ErrorKind::DiffParseError,
+        )
+    };
+    let position = command.find(['a', 'c', 'd']).ok_or_else(invalid)?;
+    let operation = command[position..].chars().next().ok_or_else(invalid)?;
+    let parse_range = |range: &str, position_only: bool| -> Result<Range<usize>, Error> {
+        let (start, end) = match range.split_once(',') {
+            Some(_) if position_only => return Err(invalid()),
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        let start: usize = start.parse().map_err(|_| invalid())?;
+        let end: usize = end.parse().map_err(|_| invalid())?;
+        if position_only {
+            Ok(start..start)
+        } else if start == 0 || end < start {
+            Err(invalid())
+        } else {
+            Ok(start..end + 1)
+        }
+    };
+    let source_range = parse_range(&command[..position], operation == 'a')?;
+    let target_range = parse_range(&command[position + 1..], operation == 'd')?;
+    Ok((source_range, operation, target_range))
+}
+
 /// A FileDiff represents a diff between two versions of a file.
 /// Each FileDiff contains a DiffCommand (i.e., its header line), a source and a target file, and
 /// one or more hunks.
 /// Hunks contain grouped changes to lines.
+///
+/// Diffs of binary files are represented by a FileDiff without hunks that holds the line
+/// reporting the difference (i.e., "Binary files X and Y differ").
+///
+/// Diffs created by git may contain extended header lines between the DiffCommand and the file
+/// headers (e.g., "index 83db48f..f735c2d 100644" or "rename from X"). These lines are kept as
+/// they are, but they have no effect on the patch application.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileDiff {
     diff_command: DiffCommand,
     source_file_header: SourceFileHeader,
     target_file_header: TargetFileHeader,
     hunks: Vec<Hunk>,
+    binary_marker: Option<String>,
+    extended_headers: Vec<String>,
+    modification_only: bool,
 }
 
+/// The path that git diffs use in the file header of a file that does not exist in the source or
+/// target, respectively.
+const DEV_NULL: &str = "/dev/null";
+
+/// The prefixes of the extended header lines that git writes between the diff command and the
+/// file headers (e.g., "new file mode 100644" or "index 3b18e51..0000000").
+const GIT_EXTENDED_HEADER_PREFIXES: [&str; 11] = [
+    "old mode ",
+    "new mode ",
+    "deleted file mode ",
+    "new file mode ",
+    "copy from ",
+    "copy to ",
+    "rename from ",
+    "rename to ",
+    "similarity index ",
+    "dissimilarity index ",
+    "index ",
+];
+
+/// The prefix of lines that report a difference between two binary files.
+const BINARY_MARKER_PREFIX: &str = "Binary files ";
+
+/// The prefix of the header line of a file diff created by git (e.g., "diff --git a/x b/x").
+const GIT_DIFF_PREFIX: &str = "diff --git ";
+
+/// The prefix of the header line of a file diff created by SVN (e.g., "Index: path/to/file").
+const SVN_INDEX_PREFIX: &str = "Index: ";
+
+/// The line that separates the `Index:` header line of an SVN file diff from its file headers.
+const SVN_SEPARATOR: &str = "===================================================================";
+
 impl Display for FileDiff {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(binary_marker) = &self.binary_marker {
+            // The diff command is only written if the marker is not a header on its own
+            if &self.diff_command.0 != binary_marker {
+                writeln!(f, "{}", self.diff_command)?;
+            }
+            for extended_header in &self.extended_headers {
+                writeln!(f, "{extended_header}")?;
+            }
+            return write!(f, "{binary_marker}");
+        }
         write!(f, "{}", self.diff_command)?;
+        if self.diff_command.index_path().is_some() {
+            write!(f, "\n{SVN_SEPARATOR}")?;
+        }
+        for extended_header in &self.extended_headers {
+            write!(f, "\n{extended_header}")?;
+        }
+        if !self.has_file_headers() {
+            return Ok(());
+        }
         write!(
             f,
-            "\n--- {}\t{}",
-            self.source_file_header.path.to_str().unwrap(),
-            self.source_file_header.timestamp
+            "\n--- {}",
+            self.source_file_header.path.to_str().unwrap()
         )?;
+        if let Some(timestamp) = &self.source_file_header.timestamp {
+            write!(f, "\t{timestamp}")?;
+        }
         write!(
             f,
-            "\n+++ {}\t{}",
-            self.target_file_header.path.to_str().unwrap(),
-            self.target_file_header.timestamp
+            "\n+++ {}",
+            self.target_file_header.path.to_str().unwrap()
         )?;
+        if let Some(timestamp) = &self.target_file_header.timestamp {
+            write!(f, "\t{timestamp}")?;
+        }
         for hunk in &self.hunks {
             // no writeln because Hunks have newline characters themselves
             write!(f, "\n{hunk}")?;
@@ -152,22 +455,115 @@ impl FileDiff {
         &self.target_file_header
     }
 
+    /// Returns the extended header lines of a git diff (e.g., "old mode 100644") in the order in
+    /// which they appear between the DiffCommand and the file headers.
+    pub fn extended_headers(&self) -> &[String] {
+        &self.extended_headers
+    }
+
+    /// Returns the permission bits of the file after the diff if the extended headers of a git
+    /// diff set its mode, i.e., if there is a "new mode" line or the file is created with a
+    /// "new file mode" line. For instance, the mode of "new mode 100755" is `0o755`.
+    pub fn mode_change(&self) -> Option<u32> {
+        self.extended_headers.iter().find_map(|line| {
+            let mode = line
+                .strip_prefix("new mode ")
+                .or_else(|| line.strip_prefix("new file mode "))?;
+            u32::from_str_radix(mode.trim(), 8)
+                .ok()
+                .map(|mode| mode & 0o7777)
+        })
+    }
+
+    /// Returns false if the file headers have not been part of the diff, which is the case for
+    /// git diffs that only change the mode of a file. The headers are then derived from the
+    /// DiffCommand.
+    fn has_file_headers(&self) -> bool {
+        !self.source_file_header.raw.is_empty()
+    }
+
     /// Returns a reference to the hunks contained in the FileDiff.
     pub fn hunks(&self) -> &[Hunk] {
         &self.hunks
     }
 
+    /// Validates that the source locations of the hunks do not overlap. Diffs with overlapping
+    /// hunks (e.g., hand-edited diffs) are parsed, but the alignment and application of their
+    /// changes is ambiguous.
+    ///
+    /// ## Error
+    /// Returns a DiffParseError if the source locations of two hunks overlap. Adjacent hunks are
+    /// valid.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_hunk_locations(&self.hunks)
+    }
+
+    /// Returns true if this FileDiff reports a difference between two binary files. Binary
+    /// FileDiffs do not contain any hunks.
+    pub fn is_binary(&self) -> bool {
+        self.binary_marker.is_some()
+    }
+
+    /// Determines whether this FileDiff creates, removes, or modifies a file. A file header with
+    /// the path `/dev/null` (as written by git) indicates that the file does not exist in the
+    /// source or target, respectively. Otherwise, the change type is determined by looking at the
+    /// first hunk: a hunk start of '0' indicates the same. FileDiffs without hunks (e.g., binary
+    /// FileDiffs) and FileDiffs in the normal format (see `VersionDiff::from_normal`) are
+    /// considered to be modifications.
+    pub fn change_type(&self) -> FileChangeType {
+        if self.modification_only {
+            return FileChangeType::Modify;
+        }
+        if self.source_file_header.is_dev_null() {
+            return FileChangeType::Create;
+        }
+        if self.target_file_header.is_dev_null() {
+            return FileChangeType::Remove;
+        }
+        match self.hunks.first() {
+            Some(hunk) if hunk.source_location().hunk_start() == 0 => FileChangeType::Create,
+            Some(hunk) if hunk.target_location().hunk_start() == 0 => FileChangeType::Remove,
+            _ => FileChangeType::Modify,
+        }
+    }
+
+    /// Returns true if this FileDiff creates a new file.
+    pub fn is_creation(&self) -> bool {
+        self.change_type() == FileChangeType::Create
+    }
+
+    /// Returns true if this FileDiff removes an existing file.
+    pub fn is_removal(&self) -> bool {
+        self.change_type() == FileChangeType::Remove
+    }
+
+    /// Returns true if this FileDiff modifies an existing file.
+    pub fn is_modification(&self) -> bool {
+        self.change_type() == FileChangeType::Modify
+    }
+
+    /// Returns the path of the file that is changed by this FileDiff. This is the path in the
+    /// target file header, unless the file is removed; the target path of a removed file may be
+    /// `/dev/null`, so that the path in the source file header is returned instead.
+    pub fn changed_file_path(&self) -> &Path {
+        if self.is_removal() {
+            self.source_file_header.path()
+        } else {
+            self.target_file_header.path()
+        }
+    }
+
     /// Collects all changes in this FileDiff and returns an iterator over their references.
     ///
     /// # Returns
     /// Returns a ChangedLines iterator that iterates all HunkLine instances containing changes.
     ///
-    pub fn changes(&self) -> ChangedLines {
+    pub fn changes(&self) -> ChangedLines<'_> {
         let changes: Vec<&HunkLine> = self
             .hunks()
             .iter()
             .flat_map(|h| h.lines.iter())
-            .filter(|l| l.line_type == LineType::Add || l.line_type == LineType::Remove)
+            .filter(|l| l.is_change())
             // reverse the order so that changes can be easily popped from the vec
             .rev()
             .collect();
@@ -185,7 +581,7 @@ impl FileDiff {
             .hunks
             .into_iter()
             .flat_map(|h| h.lines)
-            .filter(|l| l.line_type == LineType::Add || l.line_type == LineType::Remove)
+            .filter(|l| l.is_change())
             // reverse the order so that changes can be easily popped from the vec
             .rev()
             .collect();
@@ -195,6 +591,9 @@ impl FileDiff {
     /// Generates and returns the full header of this FileDiff containing the DiffCommand, the
     /// information about the source file, and the information about the target file.
     pub fn header(&self) -> String {
+        if self.is_binary() || !self.has_file_headers() {
+            return self.to_string();
+        }
         format!(
             "{}\n{}\n{}",
             self.diff_command, self.source_file_header.raw, self.target_file_header.raw,
@@ -202,6 +601,26 @@ impl FileDiff {
     }
 }
 
+impl IntoIterator for FileDiff {
+    type Item = Hunk;
+
+    type IntoIter = IntoIter<Hunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.hunks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a FileDiff {
+    type Item = &'a Hunk;
+
+    type IntoIter = std::slice::Iter<'a, Hunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.hunks.iter()
+    }
+}
+
 /// Iterator over references of HunkLines constituting line changes.
 pub struct ChangedLines<'a> {
     // In all current intatiations of ChangedLines, the changes are provided in reverse order to
@@ -243,19 +662,78 @@ impl TryFrom<Vec<String>> for FileDiff {
             "no header line for file diff",
             ErrorKind::DiffParseError,
         ))?;
-        if !diff_command.starts_with("diff ") {
+        if diff_command.starts_with(BINARY_MARKER_PREFIX) {
+            return FileDiff::parse_binary(DiffCommand(diff_command.clone()), diff_command);
+        }
+        if !diff_command.starts_with("diff ") && !diff_command.starts_with(SVN_INDEX_PREFIX) {
             return Err(Error::new(
                 &format!("invalid file diff start: {diff_command}"),
                 ErrorKind::DiffParseError,
             ));
         }
         let diff_command = DiffCommand(diff_command);
+        let mut lines = lines.peekable();
+        if diff_command.index_path().is_some() {
+            // Skip the separator between the SVN header line and the file headers
+            lines.next_if(|line| !line.is_empty() && line.chars().all(|c| c == '='));
+        }
+        // Collect the extended header lines of git diffs (e.g., "new file mode 100644")
+        let mut extended_headers = vec![];
+        while let Some(line) = lines.next_if(|line| {
+            GIT_EXTENDED_HEADER_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        }) {
+            extended_headers.push(line);
+        }
 
         // Parse the source and target file headers
-        let source_file = SourceFileHeader::try_from(lines.next().ok_or(Error::new(
-            "no header line with information about the source file",
-            ErrorKind::DiffParseError,
-        ))?)?;
+        let Some(source_line) = lines.next() else {
+            // Git omits the file headers of diffs that do not change the content (e.g., mode changes)
+            let header_value = |prefixes: [&str; 2]| {
+                extended_headers
+                    .iter()
+                    .find_map(|line| prefixes.iter().find_map(|prefix| line.strip_prefix(prefix)))
+            };
+            let renamed_paths = match (
+                header_value(["rename from ", "copy from "]),
+                header_value(["rename to ", "copy to "]),
+            ) {
+                (Some(source_name), Some(target_name)) => {
+                    diff_command.renamed_git_paths(source_name, target_name)
+                }
+                _ => None,
+            };
+            return match renamed_paths.or_else(|| diff_command.git_paths()) {
+                Some((source_path, target_path)) if !extended_headers.is_empty() => Ok(FileDiff {
+                    source_file_header: SourceFileHeader {
+                        path: source_path.to_path_buf(),
+                        timestamp: None,
+                        raw: String::new(),
+                    },
+                    target_file_header: TargetFileHeader {
+                        path: target_path.to_path_buf(),
+                        timestamp: None,
+                        raw: String::new(),
+                    },
+                    diff_command,
+                    hunks: vec![],
+                    binary_marker: None,
+                    extended_headers,
+                    modification_only: false,
+                }),
+                _ => Err(Error::new(
+                    "no header line with information about the source file",
+                    ErrorKind::DiffParseError,
+                )),
+            };
+        };
+        if source_line.starts_with(BINARY_MARKER_PREFIX) {
+            let mut file_diff = FileDiff::parse_binary(diff_command, source_line)?;
+            file_diff.extended_headers = extended_headers;
+            return Ok(file_diff);
+        }
+        let source_file = SourceFileHeader::try_from(source_line)?;
         let target_file = TargetFileHeader::try_from(lines.next().ok_or(Error::new(
             "no header line with information about the target file",
             ErrorKind::DiffParseError,
@@ -265,6 +743,9 @@ impl TryFrom<Vec<String>> for FileDiff {
         let mut hunks = vec![];
         let mut hunk_lines = vec![];
         for line in lines {
+            // Each line of a hunk starts with a marker (' ', '+', '-', or '\'). A line that
+            // starts with "@@ " is therefore always the header of a new hunk, even if the content
+            // of a changed line is a hunk header itself (e.g., in a diff of diff files).
             if line.starts_with("@@ ") {
                 if !hunk_lines.is_empty() {
                     hunks.push(Hunk::try_from(hunk_lines)?);
@@ -283,14 +764,417 @@ impl TryFrom<Vec<String>> for FileDiff {
             source_file_header: source_file,
             target_file_header: target_file,
             hunks,
+            binary_marker: None,
+            extended_headers,
+            modification_only: false,
+        })
+    }
+}
+
+impl FileDiff {
+    /// Parses a line of the form "Binary files X and Y differ" into a FileDiff without hunks.
+    fn parse_binary(diff_command: DiffCommand, binary_marker: String) -> Result<FileDiff, Error> {
+        let (source_path, target_path) = binary_marker
+            .strip_prefix(BINARY_MARKER_PREFIX)
+            .and_then(|l| l.strip_suffix(" differ"))
+            .and_then(|l| l.split_once(" and "))
+            .ok_or(Error::new(
+                &format!("invalid binary file marker: {binary_marker}"),
+                ErrorKind::DiffParseError,
+            ))?;
+
+        Ok(FileDiff {
+            diff_command,
+            source_file_header: SourceFileHeader {
+                path: PathBuf::from(source_path),
+                timestamp: None,
+                raw: String::new(),
+            },
+            target_file_header: TargetFileHeader {
+                path: PathBuf::from(target_path),
+                timestamp: None,
+                raw: String::new(),
+            },
+            hunks: vec![],
+            binary_marker: Some(binary_marker),
+            extended_headers: vec![],
+            modification_only: false,
         })
     }
 }
 
+/// The number of context lines that surround the changes in a normalized FileDiff.
+const NORMALIZED_CONTEXT: usize = 3;
+
+impl FileDiff {
+    /// Returns a normalized copy of this FileDiff with a context of three lines. See
+    /// `normalize_with_context` for more information.
+    pub fn normalize(&self) -> FileDiff {
+        self.normalize_with_context(NORMALIZED_CONTEXT)
+    }
+
+    /// Returns a normalized copy of this FileDiff in which the hunks have been recomputed with
+    /// the given number of context lines. Semantically equivalent FileDiffs that group their
+    /// changes into different hunks or that include different amounts of context have the same
+    /// normalized form, as long as their hunks contain at least the requested context.
+    ///
+    /// In the normalized form, changes that are separated by no more than twice the context size
+    /// are grouped into the same hunk, and lines that are added at the location of removed lines
+    /// are placed before the removed lines. The context of a hunk can only consist of lines that
+    /// are contained in this FileDiff; it is reduced if the FileDiff has less context.
+    pub fn normalize_with_context(&self, context: usize) -> FileDiff {
+        if self.hunks.is_empty() {
+            return self.clone();
+        }
+        FileDiff {
+            diff_command: self.diff_command.clone(),
+            source_file_header: self.source_file_header.clone(),
+            target_file_header: self.target_file_header.clone(),
+            hunks: SourceView::from(self.hunks.as_slice()).into_hunks(context),
+            binary_marker: self.binary_marker.clone(),
+            extended_headers: self.extended_headers.clone(),
+            modification_only: self.modification_only,
+        }
+    }
+}
+
+impl FileDiff {
+    /// Creates a FileDiff for the given changes to the source file with the given path and lines.
+    /// The hunks of the FileDiff are computed with the given number of context lines in the same
+    /// way as in `normalize_with_context`. Both file headers refer to the given path and have no
+    /// timestamp. Markers for missing newlines at the end of the file are not reconstructed.
+    pub(crate) fn from_changes(
+        path: &Path,
+        source_lines: &[String],
+        changes: &[Change],
+        context: usize,
+    ) -> FileDiff {
+        let hunks = SourceView::from_changes(source_lines, changes).into_hunks(context);
+        FileDiff::from_hunks(path, hunks)
+    }
+
+    /// Creates a FileDiff with the given hunks for the file with the given path. Both file headers
+    /// refer to the given path and have no timestamp.
+    pub(crate) fn from_hunks(path: &Path, hunks: Vec<Hunk>) -> FileDiff {
+        let path = path.to_string_lossy();
+        FileDiff {
+            diff_command: DiffCommand(format!("diff -Naur {path} {path}")),
+            source_file_header: SourceFileHeader::try_from(format!("--- {path}"))
+                .expect("a header with a path should always be valid"),
+            target_file_header: TargetFileHeader::try_from(format!("+++ {path}"))
+                .expect("a header with a path should always be valid"),
+            hunks,
+            binary_marker: None,
+            extended_headers: vec![],
+            modification_only: false,
+        }
+    }
+}
+
+/// A line in a SourceView after which an EOF metaline ("\ No newline at end of file") follows.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum NoNewline {
+    /// A line of the source file (i.e., a context line or a removed line).
+    Source(usize),
+    /// The added line with the given index at the given location.
+    Add(usize, usize),
+}
+
+/// A SourceView contains all information that the hunks of a FileDiff provide about the source
+/// file and the changes to it. Lines of the source file that are not part of any hunk are
+/// unknown.
+#[derive(Debug, Default)]
+struct SourceView<'a> {
+    /// The known lines of the source file by line number
+    known: BTreeMap<usize, &'a str>,
+    /// The line numbers of the removed lines
+    removed: BTreeSet<usize>,
+    /// The added lines by the source line number before which they are added
+    inserts: BTreeMap<usize, Vec<&'a str>>,
+    no_newline: HashSet<NoNewline>,
+}
+
+impl<'a> From<&'a [Hunk]> for SourceView<'a> {
+    fn from(hunks: &'a [Hunk]) -> Self {
+        let mut view = SourceView::default();
+        for hunk in hunks {
+            let mut previous = None;
+            for line in hunk.lines() {
+                // Strip the meta-symbol of the line
+                let content = line.content().get(1..).unwrap_or_default();
+                match line.line_type() {
+                    LineType::Context => {
+                        let line_number = line.source_line().real_location();
+                        view.known.insert(line_number, content);
+                        previous = Some(NoNewline::Source(line_number));
+                    }
+                    LineType::Remove => {
+                        let line_number = line.source_line().real_location();
+                        view.known.insert(line_number, content);
+                        view.removed.insert(line_number);
+                        previous = Some(NoNewline::Source(line_number));
+                    }
+                    LineType::Add => {
+                        let mut location = line.source_line().change_location();
+                        // Hunks without source lines start at the line after which the lines
+                        // are added
+                        if hunk.source_location().hunk_length() == 0 {
+                            location += 1;
+                        }
+                        previous = Some(view.insert(location, content));
+                    }
+                    LineType::EOF => {
+                        if let Some(previous) = previous.take() {
+                            view.no_newline.insert(previous);
+                        }
+                    }
+                }
+            }
+        }
+        view
+    }
+}
+
+impl<'a> SourceView<'a> {
+    /// Creates a SourceView of the given source lines with the given changes. The line number of
+    /// an added line is the number of the source line before which it is added.
+    fn from_changes(source_lines: &'a [String], changes: &'a [Change]) -> Self {
+        let mut view = SourceView::default();
+        for (index, line) in source_lines.iter().enumerate() {
+            view.known.insert(index + 1, line);
+        }
+        // Record all removed lines first, so that adds can be placed before them
+        for change in changes {
+            if change.change_type() == LineChangeType::Remove {
+                view.known.insert(change.line_number(), change.line());
+                view.removed.insert(change.line_number());
+            }
+        }
+        for change in changes {
+            if change.change_type() == LineChangeType::Add {
+                // Lines added before the first line have the line number 0
+                view.insert(change.line_number().max(1), change.line());
+            }
+        }
+        view
+    }
+
+    /// Inserts the added line before the source line with the given line number and returns
+    /// its EOF marker position. Lines added directly after removed lines are added before them.
+    fn insert(&mut self, mut location: usize, content: &'a str) -> NoNewline {
+        while location > 1 && self.removed.contains(&(location - 1)) {
+            location -= 1;
+        }
+        let adds = self.inserts.entry(location).or_default();
+        adds.push(content);
+        NoNewline::Add(location, adds.len() - 1)
+    }
+
+    /// Returns true if the given source line is at most 'context' lines away from a change.
+    fn is_near_change(&self, line_number: usize, context: usize) -> bool {
+        let near_remove = self
+            .removed
+            .range(line_number.saturating_sub(context)..=line_number + context)
+            .next()
+            .is_some();
+        // Lines are added between two source lines; thus, an add before line 'n' is one line
+        // away from line 'n' and line 'n-1'
+        let first_insert = (line_number + 1).saturating_sub(context);
+        let last_insert = line_number + context;
+        let near_insert = first_insert <= last_insert
+            && self
+                .inserts
+                .range(first_insert..=last_insert)
+                .next()
+                .is_some();
+        near_remove || near_insert
+    }
+
+    /// Recomputes the hunks for the changes in this view with the given number of context lines.
+    fn into_hunks(self, context: usize) -> Vec<Hunk> {
+        let first_change = [self.removed.first(), self.inserts.keys().next()]
+            .into_iter()
+            .flatten()
+            .min();
+        let last_change = [self.removed.last(), self.inserts.keys().next_back()]
+            .into_iter()
+            .flatten()
+            .max();
+        let (Some(&first_change), Some(&last_change)) = (first_change, last_change) else {
+            return vec![];
+        };
+
+        let mut hunks = vec![];
+        let mut current: Option<HunkBuilder> = None;
+        // The difference between the target and source line number of the current line
+        let mut offset: isize = 0;
+        for line_number in first_change.saturating_sub(context).max(1)..=last_change + context {
+            let target_line_number = (line_number as isize + offset) as usize;
+            if let Some(adds) = self.inserts.get(&line_number) {
+                let hunk = current
+                    .get_or_insert_with(|| HunkBuilder::new(line_number, target_line_number));
+                for (index, add) in adds.iter().enumerate() {
+                    hunk.push(
+                        '+',
+                        add,
+                        self.no_newline
+                            .contains(&NoNewline::Add(line_number, index)),
+                    );
+                }
+                offset += adds.len() as isize;
+            }
+
+            let no_newline = self.no_newline.contains(&NoNewline::Source(line_number));
+            let target_line_number = (line_number as isize + offset) as usize;
+            match self.known.get(&line_number) {
+                Some(line) if self.removed.contains(&line_number) => {
+                    current
+                        .get_or_insert_with(|| HunkBuilder::new(line_number, target_line_number))
+                        .push('-', line, no_newline);
+                    offset -= 1;
+                }
+                Some(line) if self.is_near_change(line_number, context) => {
+                    current
+                        .get_or_insert_with(|| HunkBuilder::new(line_number, target_line_number))
+                        .push(' ', line, no_newline);
+                }
+                _ => hunks.extend(current.take().and_then(HunkBuilder::build)),
+            }
+        }
+        hunks.extend(current.take().and_then(HunkBuilder::build));
+        hunks
+    }
+}
+
+/// Collects the lines of a hunk and tracks its location in the source and target file.
+struct HunkBuilder {
+    source_start: usize,
+    source_length: usize,
+    target_start: usize,
+    target_length: usize,
+    has_changes: bool,
+    lines: Vec<String>,
+}
+
+impl HunkBuilder {
+    fn new(source_start: usize, target_start: usize) -> HunkBuilder {
+        HunkBuilder {
+            source_start,
+            source_length: 0,
+            target_start,
+            target_length: 0,
+            has_changes: false,
+            lines: vec![],
+        }
+    }
+
+    /// Pushes a line with the given meta-symbol to the hunk.
+    fn push(&mut self, symbol: char, line: &str, no_newline: bool) {
+        match symbol {
+            '+' => self.target_length += 1,
+            '-' => self.source_length += 1,
+            _ => {
+                self.source_length += 1;
+                self.target_length += 1;
+            }
+        }
+        self.has_changes |= symbol != ' ';
+        self.lines.push(format!("{symbol}{line}"));
+        if no_newline {
+            self.lines.push("\\ No newline at end of file".to_string());
+        }
+    }
+
+    /// Builds the hunk, if it contains any changes.
+    fn build(self) -> Option<Hunk> {
+        if !self.has_changes {
+            return None;
+        }
+        // Hunks without lines in the source or target start at the line before their location
+        let location = |start: usize, length: usize| {
+            if length == 0 {
+                format!("{},0", start.saturating_sub(1))
+            } else {
+                format!("{start},{length}")
+            }
+        };
+        let mut lines = vec![format!(
+            "@@ -{} +{} @@",
+            location(self.source_start, self.source_length),
+            location(self.target_start, self.target_length)
+        )];
+        lines.extend(self.lines);
+        Some(Hunk::try_from(lines).expect("a normalized hunk should always be valid"))
+    }
+}
+
+/// Validates that the source locations of the given hunks do not overlap. Overlapping hunks make
+/// the alignment and application of their changes ambiguous. Adjacent hunks (i.e., hunks whose
+/// ranges touch without sharing a line) are valid.
+fn validate_hunk_locations(hunks: &[Hunk]) -> Result<(), Error> {
+    let mut locations: Vec<HunkLocation> = hunks
+        .iter()
+        .map(|h| h.source_location)
+        // Hunks without source lines cannot overlap with other hunks
+        .filter(|l| l.hunk_length > 0)
+        .collect();
+    locations.sort();
+
+    for pair in locations.windows(2) {
+        let (first, second) = (pair[0], pair[1]);
+        if second.hunk_start < first.hunk_start + first.hunk_length {
+            return Err(Error::new(
+                &format!("the source locations of two hunks overlap: -{first} and -{second}"),
+                ErrorKind::DiffParseError,
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// A DiffCommand holds the exact call to diff used to create a FileDiff (e.g., "diff -Naur ...").
+/// For file diffs created by SVN, it holds the `Index:` header line instead.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DiffCommand(pub String);
 
+impl DiffCommand {
+    /// Returns the path in the header line of a file diff created by SVN (i.e., a line of the
+    /// form "Index: path"). Returns None if the file diff has not been created by SVN.
+    pub fn index_path(&self) -> Option<&Path> {
+        self.0.strip_prefix(SVN_INDEX_PREFIX).map(Path::new)
+    }
+
+    /// Returns the source and target paths in the header line of a file diff created by git
+    /// (i.e., a line of the form "diff --git a/path b/path"). As the paths are separated by a
+    /// space, they are only returned if they are of equal length, e.g., because they only differ
+    /// in their prefix. Returns None otherwise, or if the file diff has not been created by git.
+    pub fn git_paths(&self) -> Option<(&Path, &Path)> {
+        let paths = self.0.strip_prefix(GIT_DIFF_PREFIX)?;
+        let middle = paths.len() / 2;
+        let (source, target) = (paths.get(..middle)?, paths.get(middle..)?);
+        let target = target.strip_prefix(' ')?;
+        (source.len() == target.len()).then(|| (Path::new(source), Path::new(target)))
+    }
+
+    /// Returns the source and target paths in the header line of a file diff created by git, if
+    /// the paths differ in more than their prefix (e.g., because the file is renamed). The given
+    /// names are the paths without prefix, as reported by the "rename from" and "rename to" (or
+    /// "copy from" and "copy to") lines. Returns None if the header line does not end with
+    /// paths to the given names, or if the file diff has not been created by git.
+    pub fn renamed_git_paths(
+        &self,
+        source_name: &str,
+        target_name: &str,
+    ) -> Option<(&Path, &Path)> {
+        let paths = self.0.strip_prefix(GIT_DIFF_PREFIX)?;
+        paths
+            .match_indices(' ')
+            .map(|(i, _)| (&paths[..i], &paths[i + 1..]))
+            .find(|(source, target)| source.ends_with(source_name) && target.ends_with(target_name))
+            .map(|(source, target)| (Path::new(source), Path::new(target)))
+    }
+}
+
 impl Display for DiffCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -363,6 +1247,53 @@ impl Hunk {
     pub fn lines(&self) -> &[HunkLine] {
         &self.lines
     }
+
+    /// Returns true if this Hunk only changes whitespace, i.e., if its removed and added lines
+    /// are equal if all whitespace is ignored (see `HunkClass::Whitespace`).
+    pub fn is_whitespace_only(&self) -> bool {
+        self.classify() == HunkClass::Whitespace
+    }
+
+    /// Classifies this Hunk by the kind of changes it contains (see HunkClass).
+    pub fn classify(&self) -> HunkClass {
+        let changed_text = |change_type: LineChangeType| -> String {
+            self.lines
+                .iter()
+                .filter(|l| l.change_type() == Some(change_type))
+                // Skip the meta-symbol that defines the change type
+                .flat_map(|l| l.text().chars().filter(|c| !c.is_whitespace()))
+                .collect()
+        };
+        let has_changes = |change_type: LineChangeType| {
+            self.lines
+                .iter()
+                .any(|l| l.change_type() == Some(change_type))
+        };
+
+        if changed_text(LineChangeType::Remove) == changed_text(LineChangeType::Add) {
+            HunkClass::Whitespace
+        } else if !has_changes(LineChangeType::Remove) {
+            HunkClass::Addition
+        } else if !has_changes(LineChangeType::Add) {
+            HunkClass::Removal
+        } else {
+            HunkClass::Modification
+        }
+    }
+}
+
+/// The kind of changes in a Hunk, as determined by `Hunk::classify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HunkClass {
+    /// The hunk only changes whitespace, i.e., the removed and added lines are equal if all
+    /// whitespace is ignored. This includes hunks that only add or remove blank lines.
+    Whitespace,
+    /// The hunk only adds lines.
+    Addition,
+    /// The hunk only removes lines.
+    Removal,
+    /// The hunk removes and adds lines.
+    Modification,
 }
 
 impl Display for Hunk {
@@ -399,9 +1330,9 @@ impl TryFrom<Vec<String>> for Hunk {
         // Parse the hunk lines
         let mut hunk_lines = vec![];
         // Tracks the last processed line number of the source file
-        let mut source_id = source_location.hunk_start;
+        let mut source_id = source_location.first_line();
         // Tracks the last processed line number of the target file
-        let mut target_id = target_location.hunk_start;
+        let mut target_id = target_location.first_line();
         for line in lines {
             // We have to handle the lines based on their line type, because the change type
             // determines in which versions of the file the line exists.
@@ -454,6 +1385,18 @@ pub struct HunkLocation {
 }
 
 impl HunkLocation {
+    /// Returns the number of the first line in the range of this location. Unlike the start of
+    /// the hunk, it is the line after the start for an empty range in a non-empty file, because
+    /// the start of an empty range is the line after which lines are added (e.g., "-5,0" for lines
+    /// that are added after line 5 in a diff without context).
+    fn first_line(&self) -> usize {
+        if self.hunk_length == 0 && self.hunk_start > 0 {
+            self.hunk_start + 1
+        } else {
+            self.hunk_start
+        }
+    }
+
     /// Returns the start line number of this hunk. The first line has line number '1'.
     pub fn hunk_start(&self) -> usize {
         self.hunk_start
@@ -463,6 +1406,19 @@ impl HunkLocation {
     pub fn hunk_length(&self) -> usize {
         self.hunk_length
     }
+
+    /// Returns the range of line numbers that this hunk covers, i.e.,
+    /// `hunk_start..hunk_start + hunk_length`. The range is empty for hunks without lines (e.g.,
+    /// the source location `0,0` of a created file).
+    pub fn range(&self) -> Range<usize> {
+        self.hunk_start..self.hunk_start + self.hunk_length
+    }
+
+    /// Returns true if the line with the given line number lies within this hunk. Hunks without
+    /// lines contain no line.
+    pub fn contains(&self, line: usize) -> bool {
+        self.range().contains(&line)
+    }
 }
 
 impl Display for HunkLocation {
@@ -569,11 +1525,46 @@ impl HunkLine {
         &self.line
     }
 
+    /// Returns the text of this line without the meta-symbol that defines the change type. An EOF
+    /// metaline has no text, and neither has a line that consists of the meta-symbol only.
+    pub fn text(&self) -> &str {
+        if self.is_eof() {
+            return "";
+        }
+        let mut chars = self.line.chars();
+        chars.next();
+        chars.as_str()
+    }
+
     /// Returns the line type of this line.
     pub fn line_type(&self) -> LineType {
         self.line_type
     }
 
+    /// Returns true if this line is a change (i.e., an Add or a Remove).
+    pub fn is_change(&self) -> bool {
+        matches!(self.line_type, LineType::Add | LineType::Remove)
+    }
+
+    /// Returns true if this line is an unchanged context line.
+    pub fn is_context(&self) -> bool {
+        self.line_type == LineType::Context
+    }
+
+    /// Returns true if this line is an EOF metaline (i.e., "\ No newline at end of file").
+    pub fn is_eof(&self) -> bool {
+        self.line_type == LineType::EOF
+    }
+
+    /// Returns the LineChangeType of this line if it is a change; otherwise, returns None.
+    pub fn change_type(&self) -> Option<LineChangeType> {
+        match self.line_type {
+            LineType::Add => Some(LineChangeType::Add),
+            LineType::Remove => Some(LineChangeType::Remove),
+            LineType::Context | LineType::EOF => None,
+        }
+    }
+
     /// Constructs a new HunkLine from the given locations, type, and text.
     pub fn new(
         source_line: LineLocation,
@@ -655,12 +1646,14 @@ impl LineType {
 }
 
 /// A source file header holds the path to the source file and the timestamp of when it was read for
-/// diffing.
+/// diffing. The timestamp is optional, because diffs with custom labels (e.g., created with
+/// `diff --label`) only have a label instead of a path and timestamp. In this case, the label is
+/// stored as path.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SourceFileHeader {
     path: PathBuf,
     // TODO: Use actual time value
-    timestamp: String,
+    timestamp: Option<String>,
     raw: String,
 }
 
@@ -675,9 +1668,15 @@ impl SourceFileHeader {
         self.path.clone()
     }
 
-    /// Returns the text of the timestamp of the time when this file was diffed.
-    pub fn timestamp(&self) -> &str {
-        &self.timestamp
+    /// Returns the text of the timestamp of the time when this file was diffed, if the header
+    /// has a timestamp.
+    pub fn timestamp(&self) -> Option<&str> {
+        self.timestamp.as_deref()
+    }
+
+    /// Returns true if the path is `/dev/null`, i.e., the source file does not exist.
+    pub fn is_dev_null(&self) -> bool {
+        self.path == Path::new(DEV_NULL)
     }
 }
 
@@ -709,12 +1708,14 @@ impl TryFrom<&str> for SourceFileHeader {
 }
 
 /// A target file header holds the path to the target file and the timestamp of when it was read for
-/// diffing.
+/// diffing. The timestamp is optional, because diffs with custom labels (e.g., created with
+/// `diff --label`) only have a label instead of a path and timestamp. In this case, the label is
+/// stored as path.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TargetFileHeader {
     path: PathBuf,
     // TODO: Use actual time value
-    timestamp: String,
+    timestamp: Option<String>,
     raw: String,
 }
 
@@ -729,9 +1730,15 @@ impl TargetFileHeader {
         self.path.clone()
     }
 
-    /// Returns the text of the timestamp of the time when this file was diffed.
-    pub fn timestamp(&self) -> &str {
-        &self.timestamp
+    /// Returns the text of the timestamp of the time when this file was diffed, if the header
+    /// has a timestamp.
+    pub fn timestamp(&self) -> Option<&str> {
+        self.timestamp.as_deref()
+    }
+
+    /// Returns true if the path is `/dev/null`, i.e., the target file does not exist.
+    pub fn is_dev_null(&self) -> bool {
+        self.path == Path::new(DEV_NULL)
     }
 }
 
@@ -763,36 +1770,57 @@ impl TryFrom<&str> for TargetFileHeader {
 }
 
 /// Splits the lines specifying the meta-information about the source and target files into file
-/// path and timestamp.
+/// path and timestamp. The path and the timestamp are usually separated by a tab. If there is no
+/// tab, the path ends at the first space outside of quotes if the rest of the line looks like a
+/// timestamp (i.e., contains a time of day such as `10:00`). Otherwise, the entire line after the
+/// '---' or '+++' marker is considered to be the path (or label) and there is no timestamp.
 ///
-/// Returns a tuple of path and timestamp.
-fn split_file_metainfo(input: String) -> Result<(PathBuf, String), Error> {
-    let parts: Vec<&str> = if input.contains("\"") {
-        input.split("\"").map(|s| s.trim()).collect()
-    } else {
-        input.split_whitespace().collect()
+/// Returns a tuple of path and optional timestamp.
+fn split_file_metainfo(input: String) -> Result<(PathBuf, Option<String>), Error> {
+    // Skip the marker (i.e., '--- ' or '+++ ')
+    let metainfo = input.get(4..).unwrap_or_default().trim();
+    let path_end = match metainfo.strip_prefix('"') {
+        Some(quoted) => quoted.find('"').map(|i| i + 2),
+        None => metainfo.find(' '),
     };
-
-    let path_id = 1;
-    let path = PathBuf::from(parts[path_id]);
-
-    let mut timestamp = String::new();
-    let timestamp_start = 2;
-    for (i, part) in parts.into_iter().skip(timestamp_start).enumerate() {
-        if i > 0 {
-            // Add whitespace before each added part after the first one
-            timestamp.push(' ');
-        }
-        timestamp.push_str(part);
+    let (path, timestamp) = match metainfo.split_once('\t') {
+        Some((path, timestamp)) => (path, Some(timestamp.trim().to_string())),
+        None => match path_end {
+            Some(end) if contains_time_of_day(&metainfo[end..]) => {
+                let (path, timestamp) = metainfo.split_at(end);
+                (path, Some(timestamp.trim().to_string()))
+            }
+            _ => (metainfo, None),
+        },
+    };
+    let path = path.trim();
+    // Paths with whitespace are quoted
+    let path = path
+        .strip_prefix('"')
+        .and_then(|p| p.strip_suffix('"'))
+        .unwrap_or(path);
+    if path.is_empty() {
+        return Err(Error::new(
+            &format!("invalid format: missing path in line '{input}'"),
+            ErrorKind::DiffParseError,
+        ));
     }
 
-    Ok((path, timestamp))
+    Ok((PathBuf::from(path), timestamp))
+}
+
+/// Returns true if the given text contains a time of day, i.e., a colon between two digits.
+fn contains_time_of_day(text: &str) -> bool {
+    text.as_bytes()
+        .windows(3)
+        .any(|w| w[0].is_ascii_digit() && w[1] == b':' && w[2].is_ascii_digit())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         diffs::{FileDiff, Hunk, LineType, TargetFileHeader, VersionDiff},
+        patch::LineChangeType,
         ErrorKind,
     };
 
@@ -802,6 +1830,90 @@ mod tests {
         LineLocation::{ChangeLocation, RealLocation},
     };
 
+    fn hunk_line(line: &str) -> HunkLine {
+        let line_type = LineType::determine_type(line).unwrap();
+        HunkLine::new(
+            RealLocation(1),
+            RealLocation(1),
+            line_type,
+            line.to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn classify_context_line() {
+        let line = hunk_line(" unchanged code");
+        assert!(!line.is_change());
+        assert!(line.is_context());
+        assert!(!line.is_eof());
+        assert_eq!(None, line.change_type());
+    }
+
+    #[test]
+    fn classify_add_line() {
+        let line = hunk_line("+added code");
+        assert!(line.is_change());
+        assert!(!line.is_context());
+        assert!(!line.is_eof());
+        assert_eq!(Some(LineChangeType::Add), line.change_type());
+    }
+
+    #[test]
+    fn classify_remove_line() {
+        let line = hunk_line("-removed code");
+        assert!(line.is_change());
+        assert!(!line.is_context());
+        assert!(!line.is_eof());
+        assert_eq!(Some(LineChangeType::Remove), line.change_type());
+    }
+
+    #[test]
+    fn classify_eof_line() {
+        let line = hunk_line("\\ No newline at end of file");
+        assert!(!line.is_change());
+        assert!(!line.is_context());
+        assert!(line.is_eof());
+        assert_eq!(None, line.change_type());
+    }
+
+    #[test]
+    fn text_of_context_line() {
+        let line = hunk_line(" unchanged code");
+        assert_eq!(" unchanged code", line.content());
+        assert_eq!("unchanged code", line.text());
+    }
+
+    #[test]
+    fn text_of_add_line() {
+        assert_eq!("added code", hunk_line("+added code").text());
+    }
+
+    #[test]
+    fn text_of_remove_line() {
+        assert_eq!("removed code", hunk_line("-removed code").text());
+    }
+
+    #[test]
+    fn text_of_eof_line() {
+        assert_eq!("", hunk_line("\\ No newline at end of file").text());
+    }
+
+    #[test]
+    fn text_of_bare_marker_lines() {
+        assert_eq!("", hunk_line("+").text());
+        assert_eq!("", hunk_line("-").text());
+        assert_eq!("", hunk_line(" ").text());
+        let empty_line = HunkLine::new(
+            RealLocation(1),
+            RealLocation(1),
+            LineType::Context,
+            String::new(),
+        )
+        .unwrap();
+        assert_eq!("", empty_line.text());
+    }
+
     fn check_line_parsing(line: &str, expected_type: LineType) {
         let line_type = LineType::determine_type(line).unwrap();
         assert_eq!(line_type, expected_type);
@@ -888,7 +2000,62 @@ mod tests {
         let line = "--- version-A/double_end.txt	2023-11-03 16:39:35.953263076 +0100";
         let source = SourceFileHeader::try_from(line).unwrap();
         assert_eq!("version-A/double_end.txt", source.path.to_str().unwrap());
-        assert_eq!("2023-11-03 16:39:35.953263076 +0100", source.timestamp);
+        assert_eq!(
+            "2023-11-03 16:39:35.953263076 +0100",
+            source.timestamp.unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_labeled_source_file() {
+        let line = "--- before";
+        let source = SourceFileHeader::try_from(line).unwrap();
+        assert_eq!("before", source.path.to_str().unwrap());
+        assert_eq!(None, source.timestamp());
+    }
+
+    #[test]
+    fn parse_source_file_with_space_separated_timestamp() {
+        let line = "--- a/file.c 2024-01-01 10:00:00";
+        let source = SourceFileHeader::try_from(line).unwrap();
+        assert_eq!("a/file.c", source.path.to_str().unwrap());
+        assert_eq!(Some("2024-01-01 10:00:00"), source.timestamp());
+
+        let line = "--- \"a/my file.c\" Mon Jan  1 10:00:00 2024";
+        let source = SourceFileHeader::try_from(line).unwrap();
+        assert_eq!("a/my file.c", source.path.to_str().unwrap());
+        assert_eq!(Some("Mon Jan  1 10:00:00 2024"), source.timestamp());
+    }
+
+    #[test]
+    fn parse_labeled_target_file() {
+        let line = "+++ after the change";
+        let target = TargetFileHeader::try_from(line).unwrap();
+        assert_eq!("after the change", target.path.to_str().unwrap());
+        assert_eq!(None, target.timestamp());
+    }
+
+    #[test]
+    fn parse_labeled_file_diff() {
+        let content = vec![
+            "diff -u --label before --label after a.txt b.txt".to_string(),
+            "--- before".to_string(),
+            "+++ after".to_string(),
+            "@@ -1 +1 @@".to_string(),
+            "-old".to_string(),
+            "+new".to_string(),
+        ];
+        let file_diff = FileDiff::try_from(content.clone()).unwrap();
+        assert_eq!(
+            "before",
+            file_diff.source_file_header().path().to_str().unwrap()
+        );
+        assert_eq!(
+            "after",
+            file_diff.target_file_header().path().to_str().unwrap()
+        );
+        // The labels are written back without timestamps
+        assert_eq!(content.join("\n"), file_diff.to_string());
     }
 
     #[test]
@@ -896,7 +2063,10 @@ mod tests {
         let line = "--- \"version-A/double end.txt\"	2023-11-03 16:39:35.953263076 +0100";
         let source = SourceFileHeader::try_from(line).unwrap();
         assert_eq!("version-A/double end.txt", source.path.to_str().unwrap());
-        assert_eq!("2023-11-03 16:39:35.953263076 +0100", source.timestamp);
+        assert_eq!(
+            "2023-11-03 16:39:35.953263076 +0100",
+            source.timestamp.unwrap()
+        );
     }
 
     #[test]
@@ -904,7 +2074,10 @@ mod tests {
         let line = "+++ \"version-B/double end.txt\"	2023-11-03 16:40:12.500153951 +0100";
         let source = TargetFileHeader::try_from(line).unwrap();
         assert_eq!("version-B/double end.txt", source.path.to_str().unwrap());
-        assert_eq!("2023-11-03 16:40:12.500153951 +0100", source.timestamp);
+        assert_eq!(
+            "2023-11-03 16:40:12.500153951 +0100",
+            source.timestamp.unwrap()
+        );
     }
 
     #[test]
@@ -912,7 +2085,10 @@ mod tests {
         let line = "+++ version-B/double_end.txt	2023-11-03 16:40:12.500153951 +0100";
         let source = TargetFileHeader::try_from(line).unwrap();
         assert_eq!("version-B/double_end.txt", source.path.to_str().unwrap());
-        assert_eq!("2023-11-03 16:40:12.500153951 +0100", source.timestamp);
+        assert_eq!(
+            "2023-11-03 16:40:12.500153951 +0100",
+            source.timestamp.unwrap()
+        );
     }
 
     #[test]
@@ -1081,11 +2257,11 @@ mod tests {
         );
         assert_eq!(
             file_diff.source_file_header.timestamp,
-            "2023-11-03 16:26:28.701847364 +0100".to_string()
+            Some("2023-11-03 16:26:28.701847364 +0100".to_string())
         );
         assert_eq!(
             file_diff.target_file_header.timestamp,
-            "2023-11-03 16:26:37.168563729 +0100".to_string()
+            Some("2023-11-03 16:26:37.168563729 +0100".to_string())
         );
         assert_eq!(file_diff.hunks.len(), 2);
     }
@@ -1219,6 +2395,28 @@ di -Naur version-A/B.txt version-B/B.txt
         assert!(result.message().starts_with("invalid file diff start"));
     }
 
+    #[test]
+    fn hunk_location_ranges() {
+        let location = HunkLocation::try_from("-23,7").unwrap();
+        assert_eq!(23..30, location.range());
+        assert!(!location.contains(22));
+        assert!(location.contains(23));
+        assert!(location.contains(29));
+        assert!(!location.contains(30));
+
+        // The location of the source of a created file has no lines
+        let location = HunkLocation::try_from("-0,0").unwrap();
+        assert!(location.range().is_empty());
+        assert!(!location.contains(0));
+        assert!(!location.contains(1));
+
+        // The abbreviated location of a single line
+        let location = HunkLocation::try_from("+1").unwrap();
+        assert_eq!(1..2, location.range());
+        assert!(location.contains(1));
+        assert!(!location.contains(2));
+    }
+
     #[test]
     fn invalid_empty_hunk_location() {
         let content = "";
@@ -1226,4 +2424,181 @@ di -Naur version-A/B.txt version-B/B.txt
         assert_eq!(ErrorKind::DiffParseError, *result.kind());
         assert!(result.message().starts_with("invalid hunk location: "));
     }
+
+    fn file_diff_with_hunks(first_hunk: &str, second_hunk: &str) -> Vec<String> {
+        let content = format!(
+            "diff -Naur version-A/A.txt version-B/A.txt
+--- version-A/A.txt	2023-11-03 16:26:28.701847364 +0100
++++ version-B/A.txt	2023-11-03 16:26:37.168563729 +0100
+{first_hunk}
+ context 1
+-REMOVED
++ADDED
+ context 2
+{second_hunk}
+ context 3
+-REMOVED
++ADDED
+ context 4"
+        );
+        content.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn reject_overlapping_hunks() {
+        let content = file_diff_with_hunks("@@ -1,3 +1,3 @@", "@@ -3,3 +3,3 @@");
+        let file_diff = FileDiff::try_from(content).unwrap();
+        let result = file_diff.validate().unwrap_err();
+        assert_eq!(ErrorKind::DiffParseError, *result.kind());
+        assert!(result
+            .message()
+            .starts_with("the source locations of two hunks overlap"));
+    }
+
+    #[test]
+    fn accept_adjacent_hunks() {
+        let content = file_diff_with_hunks("@@ -1,3 +1,3 @@", "@@ -4,3 +4,3 @@");
+        let file_diff = FileDiff::try_from(content).unwrap();
+        assert_eq!(2, file_diff.hunks().len());
+        assert!(file_diff.validate().is_ok());
+    }
+
+    #[test]
+    fn parse_binary_file_markers() {
+        let content = "diff -Naur version-A/A.txt version-B/A.txt
+--- version-A/A.txt	2023-11-03 16:26:28.701847364 +0100
++++ version-B/A.txt	2023-11-03 16:26:37.168563729 +0100
+@@ -1,2 +1,2 @@
+ context 1
+-REMOVED
++ADDED
+Binary files version-A/image.png and version-B/image.png differ
+diff -Naur version-A/data.bin version-B/data.bin
+Binary files version-A/data.bin and version-B/data.bin differ
+diff -Naur version-A/B.txt version-B/B.txt
+--- version-A/B.txt	2023-11-03 16:26:28.701847364 +0100
++++ version-B/B.txt	2023-11-03 16:26:37.168563729 +0100
+@@ -1,2 +1,2 @@
+ context 1
+-REMOVED
++ADDED";
+        let version_diff = VersionDiff::try_from(content.to_string()).unwrap();
+        assert_eq!(4, version_diff.len());
+
+        let file_diffs = version_diff.file_diffs();
+        assert!(!file_diffs[0].is_binary());
+        assert!(!file_diffs[3].is_binary());
+
+        let standalone = &file_diffs[1];
+        assert!(standalone.is_binary());
+        assert!(standalone.hunks().is_empty());
+        assert_eq!(
+            "version-A/image.png",
+            standalone.source_file_header().path().to_str().unwrap()
+        );
+        assert_eq!(
+            "version-B/image.png",
+            standalone.target_file_header().path().to_str().unwrap()
+        );
+
+        let with_command = &file_diffs[2];
+        assert!(with_command.is_binary());
+        assert_eq!(
+            "diff -Naur version-A/data.bin version-B/data.bin",
+            with_command.diff_command().0
+        );
+        assert_eq!(
+            "version-B/data.bin",
+            with_command.target_file_header().path().to_str().unwrap()
+        );
+
+        // Binary markers are preserved when printing the diff
+        assert_eq!(content, version_diff.to_string());
+    }
+
+    #[test]
+    fn invalid_binary_file_marker() {
+        let content = vec!["Binary files version-A/data.bin differ".to_string()];
+        let result = FileDiff::try_from(content).unwrap_err();
+        assert_eq!(ErrorKind::DiffParseError, *result.kind());
+        assert!(result.message().starts_with("invalid binary file marker"));
+    }
+
+    fn file_diff(hunks: &str) -> FileDiff {
+        let content = format!(
+            "diff -Naur version-A/A.txt version-B/A.txt
+--- version-A/A.txt	2023-11-03 16:26:28.701847364 +0100
++++ version-B/A.txt	2023-11-03 16:26:37.168563729 +0100
+{hunks}"
+        );
+        FileDiff::try_from(content.lines().map(|l| l.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn normalize_hunk_boundaries() {
+        // A single hunk with three lines of context
+        let single_hunk = file_diff(
+            "@@ -1,10 +1,10 @@
+ line 1
+ line 2
+ line 3
+-line 4
++LINE 4
+ line 5
+ line 6
+-line 7
++LINE 7
+ line 8
+ line 9
+ line 10",
+        );
+        // The same changes split into two hunks with one line of context, in which lines are
+        // added before they are removed
+        let two_hunks = file_diff(
+            "@@ -3,3 +3,3 @@
+ line 3
++LINE 4
+-line 4
+ line 5
+@@ -6,3 +6,3 @@
+ line 6
++LINE 7
+-line 7
+ line 8",
+        );
+        assert_ne!(single_hunk, two_hunks);
+
+        let normalized = single_hunk.normalize_with_context(1);
+        assert_eq!(normalized, two_hunks.normalize_with_context(1));
+        assert_eq!(
+            "@@ -3,6 +3,6 @@
+ line 3
++LINE 4
+-line 4
+ line 5
+ line 6
++LINE 7
+-line 7
+ line 8",
+            normalized.hunks()[0].to_string()
+        );
+
+        // Normalizing is idempotent
+        assert_eq!(normalized, normalized.normalize_with_context(1));
+        // The changes are not modified
+        assert_eq!(single_hunk.changes().count(), normalized.changes().count());
+    }
+
+    #[test]
+    fn normalize_added_end_of_file() {
+        let file_diff = file_diff(
+            r"@@ -1,2 +1,3 @@
+ line 1
+ line 2
++line 3
+\ No newline at end of file",
+        );
+        let normalized = file_diff.normalize();
+        assert_eq!(file_diff, normalized);
+    }
 }
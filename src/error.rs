@@ -1,11 +1,15 @@
 use std::fmt::Display;
 
+use crate::patch::Change;
+
 /// Error is the main error type of this crate and used in all high-level instances of Result<...>
-/// return values. Each error contains a message and an ErrorKind instance.
+/// return values. Each error contains a message and an ErrorKind instance. Errors that are caused
+/// by a specific change of a patch additionally carry that change.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error {
     message: String,
     kind: ErrorKind,
+    change: Option<Box<Change>>,
 }
 
 impl Error {
@@ -25,6 +29,17 @@ impl Error {
         Error {
             message: message.to_string(),
             kind,
+            change: None,
+        }
+    }
+
+    /// Creates a new Error instance with the given message and kind that has been caused by the
+    /// given change.
+    pub fn with_change(message: &str, kind: ErrorKind, change: Change) -> Error {
+        Error {
+            message: message.to_string(),
+            kind,
+            change: Some(Box::new(change)),
         }
     }
 
@@ -37,6 +52,11 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// Returns the change that caused this error, if any
+    pub fn change(&self) -> Option<&Change> {
+        self.change.as_deref()
+    }
 }
 
 impl std::error::Error for Error {}
@@ -52,6 +72,7 @@ impl From<std::io::Error> for Error {
         Error {
             message: value.to_string(),
             kind: ErrorKind::IOError,
+            change: None,
         }
     }
 }
@@ -65,6 +86,10 @@ pub enum ErrorKind {
     IOError,
     /// A PatchError may occur while applying a patch
     PatchError,
+    /// A FilterError may occur while filtering a patch, e.g., if the filter has invalid parameters
+    FilterError,
+    /// A Cancelled error occurs if a patch application is cancelled (see `CancelFlag`)
+    Cancelled,
 }
 
 impl Display for ErrorKind {
@@ -73,6 +98,8 @@ impl Display for ErrorKind {
             ErrorKind::DiffParseError => write!(f, "DiffParseError"),
             ErrorKind::IOError => write!(f, "IOError"),
             ErrorKind::PatchError => write!(f, "PatchError"),
+            ErrorKind::FilterError => write!(f, "FilterError"),
+            ErrorKind::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
@@ -99,5 +126,6 @@ mod tests {
         assert_eq!("DiffParseError", &ErrorKind::DiffParseError.to_string());
         assert_eq!("IOError", &ErrorKind::IOError.to_string());
         assert_eq!("PatchError", &ErrorKind::PatchError.to_string());
+        assert_eq!("FilterError", &ErrorKind::FilterError.to_string());
     }
 }
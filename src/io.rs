@@ -1,11 +1,14 @@
 use std::{fmt::Display, fs};
 use std::{
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 
-use crate::{patch::Change, Error};
+#[cfg(feature = "serde")]
+use crate::patch::{LineChangeType, RejectReason};
+use crate::{patch::Change, Error, ErrorKind};
 
 /// Prints the given rejects with print!
 pub fn print_rejects(diff_header: String, rejects: &[Change]) {
@@ -15,31 +18,290 @@ pub fn print_rejects(diff_header: String, rejects: &[Change]) {
     }
 }
 
-/// Writes the given diff header and the rejects of the diff to the specified file.
-pub fn write_rejects<P: AsRef<Path>>(
-    diff_header: String,
+/// Entries of a file that are recorded together with the position of the file diff to which they
+/// belong. Recording only requires a shared reference, so that the entries can be recorded by
+/// threads that patch files in parallel.
+#[derive(Debug, Default)]
+struct PositionedEntries(Mutex<Vec<(usize, String)>>);
+
+impl PositionedEntries {
+    fn push(&self, position: usize, entry: String) {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((position, entry));
+    }
+
+    /// Returns the entries ordered by their position. Entries at the same position retain the
+    /// order in which they were recorded.
+    fn into_sorted(self) -> Vec<String> {
+        let mut entries = self
+            .0
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.sort_by_key(|(position, _)| *position);
+        entries.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+/// Writes the given entries to the file under the given path, overwriting an existing file.
+fn write_entries(path: &Path, entries: Vec<String>) -> Result<(), Error> {
+    let mut file_writer = BufWriter::new(File::create(path)?);
+    for entry in entries {
+        file_writer.write_all(entry.as_bytes())?;
+    }
+    file_writer.flush()?;
+    Ok(())
+}
+
+/// Collects the rejects of patched files and writes them to a single rejects file. The rejects
+/// of each file are recorded together with the position of its file diff in the diff, and they
+/// are written in the order of these positions once all files have been patched. As recording
+/// only requires a shared reference, a RejectsFile can be shared between threads that patch files
+/// in parallel without garbling the written rejects.
+#[derive(Debug)]
+pub struct RejectsFile {
+    path: PathBuf,
+    entries: PositionedEntries,
+}
+
+impl RejectsFile {
+    /// Creates a new RejectsFile that writes the recorded rejects to the given path.
+    pub fn new(path: PathBuf) -> RejectsFile {
+        RejectsFile {
+            path,
+            entries: PositionedEntries::default(),
+        }
+    }
+
+    /// Records the given diff header and the rejects of the diff at the given position in the
+    /// diff. Rejects at the same position are written in the order in which they were recorded.
+    pub fn record(&self, position: usize, diff_header: &str, rejects: &[Change]) {
+        let mut entry = format!("{diff_header}\n");
+        for reject in rejects {
+            entry.push_str(&format!("{}: {}", reject.change_id(), reject));
+        }
+        self.entries.push(position, entry);
+    }
+
+    /// Writes all recorded rejects to the rejects file, ordered by their position in the diff.
+    /// The file is only created if rejects have been recorded; an existing file is overwritten.
+    pub fn write(self) -> Result<(), Error> {
+        let entries = self.entries.into_sorted();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        write_entries(&self.path, entries)
+    }
+}
+
+/// Collects the unified diffs of patched files (see `PatchOutcome::as_unified_diff`) and writes
+/// them to a single report file. Like in a RejectsFile, the diffs are written in the order of the
+/// positions of their file diffs in the diff.
+#[derive(Debug)]
+pub struct ReportFile {
+    path: PathBuf,
+    entries: PositionedEntries,
+}
+
+impl ReportFile {
+    /// Creates a new ReportFile that writes the recorded diffs to the given path.
+    pub fn new(path: PathBuf) -> ReportFile {
+        ReportFile {
+            path,
+            entries: PositionedEntries::default(),
+        }
+    }
+
+    /// Records the unified diff of the file that was patched according to the file diff at the
+    /// given position in the diff. Empty diffs of unchanged files are ignored.
+    pub fn record(&self, position: usize, unified_diff: String) {
+        if !unified_diff.is_empty() {
+            self.entries.push(position, unified_diff);
+        }
+    }
+
+    /// Writes all recorded diffs to the report file, ordered by their position in the diff. The
+    /// file is created even if no file has been changed; an existing file is overwritten.
+    pub fn write(self) -> Result<(), Error> {
+        write_entries(&self.path, self.entries.into_sorted())
+    }
+}
+
+/// A RejectSink determines where the rejected changes of a patch application are reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectSink {
+    /// Print the rejects to stdout.
+    Print,
+    /// Write the rejects of all patched files to a single rejects file at the given path. The
+    /// file is only created if there are rejects.
+    File(PathBuf),
+    /// Write the rejects of each patched file as JSON to a sidecar file next to it, which is
+    /// named after the patched file with the additional extension `.rej.json`.
+    #[cfg(feature = "serde")]
+    JsonSidecar,
+    /// Stream the rejects of all patched files to the file at the given path as newline-delimited
+    /// JSON, i.e., one JSON object per line and reject. The rejects of each file are appended as
+    /// soon as the file has been patched, so that tools like editor plugins can follow the file
+    /// while patching is in progress. The file is truncated before the first file is patched.
+    #[cfg(feature = "serde")]
+    Ndjson(PathBuf),
+}
+
+/// A single rejected change as it is serialized to JSON.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct RejectRecord<'a> {
+    change_id: usize,
+    change_type: LineChangeType,
+    line: usize,
+    content: &'a str,
+    reason: Option<RejectReason>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> From<&'a Change> for RejectRecord<'a> {
+    fn from(reject: &'a Change) -> Self {
+        RejectRecord {
+            change_id: reject.change_id(),
+            change_type: reject.change_type(),
+            line: reject.line_number(),
+            content: reject.line(),
+            reason: reject.reject_reason(),
+        }
+    }
+}
+
+/// A single rejected change together with the path of its patched file, as it is streamed to a
+/// newline-delimited JSON file.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct StreamedRejectRecord<'a> {
+    file: std::borrow::Cow<'a, str>,
+    #[serde(flatten)]
+    record: RejectRecord<'a>,
+}
+
+/// Writes the given rejects of the patched file as JSON to the sidecar file `<path>.rej.json`.
+#[cfg(feature = "serde")]
+pub fn write_json_rejects<P: AsRef<Path>>(
+    patched_file: P,
     rejects: &[Change],
-    rejects_file: &mut Option<BufWriter<File>>,
-    path: P,
 ) -> Result<(), Error> {
-    // Create the rejects file on demand
-    let file_writer = rejects_file.get_or_insert_with(|| {
-        BufWriter::new(File::create_new(&path).expect("was not able to create rejects file"))
-    });
-    file_writer.write_fmt(format_args!("{}\n", diff_header))?;
+    let mut path = patched_file.as_ref().as_os_str().to_owned();
+    path.push(".rej.json");
+
+    let records: Vec<RejectRecord> = rejects.iter().map(RejectRecord::from).collect();
+
+    let mut file_writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut file_writer, &records).map_err(std::io::Error::from)?;
+    file_writer.flush()?;
+    Ok(())
+}
+
+/// Appends the given rejects of the patched file to the newline-delimited JSON file under
+/// `ndjson_path`, writing one JSON object per line and reject.
+#[cfg(feature = "serde")]
+pub fn append_ndjson_rejects<P: AsRef<Path>>(
+    ndjson_path: &Path,
+    patched_file: P,
+    rejects: &[Change],
+) -> Result<(), Error> {
+    let file = patched_file.as_ref().to_string_lossy();
+    let mut lines = String::new();
     for reject in rejects {
-        file_writer.write_fmt(format_args!("{}: {}", reject.change_id(), reject))?
+        let record = StreamedRejectRecord {
+            file: file.clone(),
+            record: RejectRecord::from(reject),
+        };
+        lines.push_str(&serde_json::to_string(&record).map_err(std::io::Error::from)?);
+        lines.push('\n');
     }
-    file_writer.flush()?;
+
+    // The rejects of a file are appended at once, so that readers never see a partial file
+    let mut ndjson_file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(ndjson_path)?;
+    ndjson_file.write_all(lines.as_bytes())?;
+    ndjson_file.flush()?;
     Ok(())
 }
 
-/// Represents a file that can be patched. Each file artifact tracks the path to the file on disk
-/// and the content of the file in lines.
+/// A FileStore provides access to the files of the source and target variants during patching.
+pub(crate) trait FileStore {
+    /// Reads the file under the given path, or creates an empty FileArtifact if no such file
+    /// exists.
+    fn read_or_create_empty(&mut self, path: PathBuf) -> Result<FileArtifact, Error>;
+}
+
+/// A FileStore that reads the files from disk.
+#[derive(Debug)]
+pub(crate) struct DiskFileStore {
+    lossy: bool,
+}
+
+impl DiskFileStore {
+    /// Creates a new DiskFileStore. If lossy is set, invalid UTF-8 sequences in files are replaced
+    /// (see `FileArtifact::read_lossy`).
+    pub(crate) fn new(lossy: bool) -> DiskFileStore {
+        DiskFileStore { lossy }
+    }
+}
+
+impl FileStore for DiskFileStore {
+    fn read_or_create_empty(&mut self, path: PathBuf) -> Result<FileArtifact, Error> {
+        if !path.exists() {
+            Ok(FileArtifact::new(path))
+        } else if self.lossy {
+            FileArtifact::read_lossy(path)
+        } else {
+            FileArtifact::read(path)
+        }
+    }
+}
+
+/// The line ending with which the lines of a file are terminated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Lines end with a line feed (`\n`).
+    #[default]
+    Lf,
+    /// Lines end with a carriage return followed by a line feed (`\r\n`).
+    Crlf,
+}
+
+impl LineEnding {
+    /// Determines the dominant line ending of the given content, i.e., the line ending that
+    /// terminates most of its lines. Content without any line endings is considered to use LF.
+    pub fn detect(content: &str) -> LineEnding {
+        let line_feeds = content.matches('\n').count();
+        let crlfs = content.matches("\r\n").count();
+        if crlfs > line_feeds - crlfs {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// Returns the characters that represent this line ending.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// Represents a file that can be patched. Each file artifact tracks the path to the file on disk,
+/// the content of the file in lines, the line ending with which the lines are written, and whether
+/// the last line is followed by a line ending.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileArtifact {
     path: PathBuf,
     lines: Vec<String>,
+    line_ending: LineEnding,
+    final_newline: bool,
 }
 
 impl FileArtifact {
@@ -48,12 +310,33 @@ impl FileArtifact {
         FileArtifact {
             path,
             lines: vec![],
+            line_ending: LineEnding::Lf,
+            final_newline: false,
         }
     }
 
-    /// Creates a new file artifact with the given path and lines.
+    /// Creates a new file artifact with the given path and lines, which are written with LF line
+    /// endings and without a final newline.
     pub fn from_lines(path: PathBuf, lines: Vec<String>) -> FileArtifact {
-        FileArtifact { path, lines }
+        FileArtifact {
+            path,
+            lines,
+            line_ending: LineEnding::Lf,
+            final_newline: false,
+        }
+    }
+
+    /// Sets the line ending with which the lines of this file artifact are written.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> FileArtifact {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Sets whether the last line of this file artifact is followed by a line ending when it is
+    /// written.
+    pub fn with_final_newline(mut self, final_newline: bool) -> FileArtifact {
+        self.final_newline = final_newline;
+        self
     }
 
     /// Reads the content of the file under path and creates a new FileArtifact from it.
@@ -62,6 +345,27 @@ impl FileArtifact {
         Ok(FileArtifact::parse_content(path, content))
     }
 
+    /// Reads the content of the given reader, e.g., a network stream or an entry of an archive,
+    /// and creates a new FileArtifact with the given path from it. The lines and the line ending
+    /// are determined like in `read`.
+    ///
+    /// ## Error
+    /// Returns an Error if the content cannot be read or is not valid UTF-8.
+    pub fn from_reader<R: Read>(path: PathBuf, mut reader: R) -> Result<FileArtifact, Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        Ok(FileArtifact::parse_content(path, content))
+    }
+
+    /// Reads the content of the file under path and creates a new FileArtifact from it. In
+    /// contrast to `read`, invalid UTF-8 sequences do not cause an error but are replaced with
+    /// the Unicode replacement character (U+FFFD).
+    pub fn read_lossy<P: AsRef<Path>>(path: P) -> Result<FileArtifact, Error> {
+        let content = fs::read(&path)?;
+        let content = String::from_utf8_lossy(&content).into_owned();
+        Ok(FileArtifact::parse_content(path, content))
+    }
+
     /// Reads the contents of a file as file artifact or creates an empty FileArtifact instance
     /// if no corresponding file exists. This function does not create new files on disk, only
     /// representations in memory.
@@ -74,9 +378,20 @@ impl FileArtifact {
     }
 
     /// Writes the content of this FileArtifact back to the file from which it was loaded. This is meant
-    /// to be used in cases where the content has been modified.
+    /// to be used in cases where the content has been modified. The last line is followed by a line
+    /// ending if the file artifact has a final newline (see `has_final_newline`).
     pub fn write(&self) -> Result<(), std::io::Error> {
-        fs::write(&self.path, self.to_string())
+        fs::write(&self.path, self.content())
+    }
+
+    /// Returns the content of this FileArtifact as it is written to disk, i.e., the lines joined
+    /// by the line ending, followed by a final newline if the file artifact has one.
+    pub(crate) fn content(&self) -> String {
+        let mut content = self.to_string();
+        if self.final_newline && !self.lines.is_empty() {
+            content.push_str(self.line_ending.as_str());
+        }
+        content
     }
 
     /// Returns the number of lines in this file artifact.
@@ -90,7 +405,9 @@ impl FileArtifact {
         self.lines.is_empty()
     }
 
-    /// Creates a new file artifact from the given path and content.
+    /// Creates a new file artifact from the given path and content. The lines are stored without
+    /// their line endings; the dominant line ending of the content and whether the content ends
+    /// with a line ending are tracked instead.
     fn parse_content<P: AsRef<Path>>(path: P, file_content: String) -> Self {
         let mut lines = vec![];
         for line in file_content.lines().map(|l| l.to_string()) {
@@ -99,6 +416,8 @@ impl FileArtifact {
         FileArtifact {
             path: path.as_ref().to_path_buf(),
             lines,
+            line_ending: LineEnding::detect(&file_content),
+            final_newline: file_content.ends_with('\n'),
         }
     }
 
@@ -121,6 +440,125 @@ impl FileArtifact {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Returns the line ending with which the lines of this file artifact are written. For a
+    /// file artifact that was read from disk, this is the dominant line ending of the file.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Returns true if the last line of this file artifact is followed by a line ending. For a
+    /// file artifact that was read from disk, this is the case if the file ends with a newline.
+    /// The lines themselves do not reflect the final newline, and neither does the Display
+    /// implementation.
+    pub fn has_final_newline(&self) -> bool {
+        self.final_newline
+    }
+
+    /// Inserts the given content as new line at the given 1-based line number. The line that was
+    /// previously at this number and all following lines are shifted by one. A line number one
+    /// past the last line appends the content to the end of the file.
+    ///
+    /// Returns a PatchError if the line number is zero or more than one past the last line.
+    pub fn insert_line(&mut self, at: usize, content: String) -> Result<(), Error> {
+        if at == 0 || at > self.lines.len() + 1 {
+            return Err(Error::new(
+                &format!(
+                    "cannot insert line {at} into a file with {} lines",
+                    self.lines.len()
+                ),
+                ErrorKind::PatchError,
+            ));
+        }
+        self.lines.insert(at - 1, content);
+        Ok(())
+    }
+
+    /// Removes the line with the given 1-based line number and returns its content. All following
+    /// lines are shifted by one. Returns None if there is no line with this number.
+    pub fn remove_line(&mut self, at: usize) -> Option<String> {
+        if at == 0 || at > self.lines.len() {
+            return None;
+        }
+        Some(self.lines.remove(at - 1))
+    }
+
+    /// Returns a hash of the content of this file artifact as hexadecimal string. The hash is
+    /// computed from the lines of the file with the 64-bit FNV-1a algorithm, so that it does not
+    /// depend on the line endings of the file or the path of the file artifact. It is meant to
+    /// detect changes of a file, e.g., for `PatchOptions::expected_source_hash`, and is not
+    /// suitable for cryptographic purposes.
+    pub fn content_hash(&self) -> String {
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+        let hash = self
+            .lines
+            .iter()
+            .flat_map(|line| line.bytes().chain(std::iter::once(b'\n')))
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            });
+        format!("{hash:016x}")
+    }
+
+    /// Compares the lines of this file artifact with the lines of the other file artifact and
+    /// returns a mismatch for every line number at which the lines differ. If one file has more
+    /// lines than the other, each surplus line is reported as a mismatch with a missing
+    /// counterpart. The paths of the file artifacts are not compared.
+    ///
+    /// This can be used to validate the result of a patch application against an expected file.
+    pub fn compare(&self, other: &FileArtifact) -> Vec<LineMismatch> {
+        let line_count = self.len().max(other.len());
+        (0..line_count)
+            .filter_map(|index| {
+                let (line, other_line) = (self.lines.get(index), other.lines.get(index));
+                (line != other_line).then(|| LineMismatch {
+                    line_number: index + 1,
+                    line: line.cloned(),
+                    other_line: other_line.cloned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A difference between two file artifacts at a specific line (see `FileArtifact::compare`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMismatch {
+    line_number: usize,
+    line: Option<String>,
+    other_line: Option<String>,
+}
+
+impl LineMismatch {
+    /// Returns the number of the line at which the file artifacts differ.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Returns the line of the compared file artifact, or None if it has no such line.
+    pub fn line(&self) -> Option<&str> {
+        self.line.as_deref()
+    }
+
+    /// Returns the line of the other file artifact, or None if it has no such line.
+    pub fn other_line(&self) -> Option<&str> {
+        self.other_line.as_deref()
+    }
+}
+
+impl Display for LineMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_missing =
+            |line: Option<&str>| line.map_or("<missing>".to_string(), |l| format!("{l:?}"));
+        write!(
+            f,
+            "line {}: {} != {}",
+            self.line_number,
+            or_missing(self.line()),
+            or_missing(self.other_line())
+        )
+    }
 }
 
 impl Display for FileArtifact {
@@ -131,7 +569,7 @@ impl Display for FileArtifact {
             write!(f, "{line}")?;
         }
         for line in lines {
-            write!(f, "\n{line}")?;
+            write!(f, "{}{line}", self.line_ending.as_str())?;
         }
         Ok(())
     }
@@ -153,11 +591,66 @@ impl StrippedPath for PathBuf {
     }
 }
 
+/// Resolves the relative path below the root directory while ignoring the case of its components.
+/// Components that exist with the exact case are kept; otherwise, the directory is scanned for an
+/// entry whose name only differs in (ASCII) case. Once a component cannot be found, it and all
+/// following components are kept as they are, e.g., for files that are yet to be created.
+///
+/// ## Error
+/// Returns an Error of kind PatchError if a component is ambiguous because several entries only
+/// differ in case, or an IOError if a directory cannot be read.
+pub(crate) fn resolve_case_insensitively(root: &Path, relative: &Path) -> Result<PathBuf, Error> {
+    let mut resolved = root.to_path_buf();
+    let mut components = relative.iter();
+    for component in components.by_ref() {
+        let exact = resolved.join(component);
+        if exact.exists() || !resolved.is_dir() {
+            resolved = exact;
+            continue;
+        }
+        let mut candidates = vec![];
+        for entry in fs::read_dir(&resolved)? {
+            let name = entry?.file_name();
+            if name.eq_ignore_ascii_case(component) {
+                candidates.push(name);
+            }
+        }
+        match candidates.as_slice() {
+            [] => {
+                resolved = exact;
+                break;
+            }
+            [name] => resolved.push(name),
+            _ => {
+                return Err(Error::new(
+                    &format!(
+                        "the path {} is ambiguous, because several files in {} only differ in case",
+                        relative.display(),
+                        resolved.display()
+                    ),
+                    ErrorKind::PatchError,
+                ))
+            }
+        }
+    }
+    resolved.extend(components);
+    Ok(resolved)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{path::PathBuf, str::FromStr};
 
-    use super::{FileArtifact, StrippedPath};
+    use std::fs;
+
+    use crate::patch::Change;
+
+    use crate::ErrorKind;
+
+    use super::{
+        resolve_case_insensitively, FileArtifact, LineEnding, LineMismatch, RejectsFile,
+        StrippedPath,
+    };
 
     #[test]
     // Assure that the content of a file is not manipulated by pure read and write operations
@@ -176,6 +669,36 @@ mod tests {
         assert_eq!(5, artifact.len());
     }
 
+    #[test]
+    fn read_write_crlf_equality() {
+        let test_content = "hello\r\noh beautiful\r\nworld!".to_string();
+
+        let artifact = FileArtifact::parse_content("UNUSED PATH", test_content.clone());
+
+        assert_eq!(LineEnding::Crlf, artifact.line_ending());
+        assert_eq!(["hello", "oh beautiful", "world!"], artifact.lines());
+        assert_eq!(test_content, artifact.to_string());
+    }
+
+    #[test]
+    fn hash_content() {
+        let artifact = FileArtifact::parse_content("a.c", "first\nsecond\n".to_string());
+        assert_eq!("76a6ff4a080b3649", artifact.content_hash());
+        // The hash neither depends on the path nor on the line endings
+        let crlf_artifact = FileArtifact::parse_content("b.c", "first\r\nsecond\r\n".to_string());
+        assert_eq!(artifact.content_hash(), crlf_artifact.content_hash());
+        let changed = FileArtifact::parse_content("a.c", "first\nsecond line\n".to_string());
+        assert_ne!(artifact.content_hash(), changed.content_hash());
+    }
+
+    #[test]
+    fn detect_dominant_line_ending() {
+        assert_eq!(LineEnding::Lf, LineEnding::detect(""));
+        assert_eq!(LineEnding::Lf, LineEnding::detect("single line"));
+        assert_eq!(LineEnding::Lf, LineEnding::detect("a\r\nb\nc\n"));
+        assert_eq!(LineEnding::Crlf, LineEnding::detect("a\r\nb\r\nc\n"));
+    }
+
     #[test]
     fn path_strip_single() {
         let path = PathBuf::from_str("hello/world").unwrap();
@@ -198,4 +721,174 @@ mod tests {
         let stripped = PathBuf::strip_cloned(&path, 2);
         assert_eq!(stripped.to_str().unwrap(), "");
     }
+
+    fn artifact(lines: &[&str]) -> FileArtifact {
+        FileArtifact::from_lines(
+            PathBuf::from("file"),
+            lines.iter().map(|l| l.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn insert_lines() {
+        let mut file = artifact(&["int b;", "int d;"]);
+        file.insert_line(1, "int a;".to_string()).unwrap();
+        file.insert_line(3, "int c;".to_string()).unwrap();
+        file.insert_line(5, "int e;".to_string()).unwrap();
+        assert_eq!(
+            artifact(&["int a;", "int b;", "int c;", "int d;", "int e;"]),
+            file
+        );
+
+        let mut empty = artifact(&[]);
+        empty.insert_line(1, "int a;".to_string()).unwrap();
+        assert_eq!(artifact(&["int a;"]), empty);
+    }
+
+    #[test]
+    fn insert_lines_out_of_bounds() {
+        let mut file = artifact(&["int a;", "int b;"]);
+        assert!(file.insert_line(0, "int x;".to_string()).is_err());
+        let error = file.insert_line(4, "int x;".to_string()).unwrap_err();
+        assert_eq!(&ErrorKind::PatchError, error.kind());
+        assert_eq!(artifact(&["int a;", "int b;"]), file);
+    }
+
+    #[test]
+    fn remove_lines() {
+        let mut file = artifact(&["int a;", "int b;", "int c;", "int d;", "int e;"]);
+        assert_eq!(Some("int a;".to_string()), file.remove_line(1));
+        assert_eq!(Some("int c;".to_string()), file.remove_line(2));
+        assert_eq!(Some("int e;".to_string()), file.remove_line(3));
+        assert_eq!(artifact(&["int b;", "int d;"]), file);
+    }
+
+    #[test]
+    fn remove_lines_out_of_bounds() {
+        let mut file = artifact(&["int a;", "int b;"]);
+        assert_eq!(None, file.remove_line(0));
+        assert_eq!(None, file.remove_line(3));
+        assert_eq!(None, artifact(&[]).remove_line(1));
+        assert_eq!(artifact(&["int a;", "int b;"]), file);
+    }
+
+    #[test]
+    fn compare_equal_files() {
+        let file = artifact(&["int a;", "int b;"]);
+        assert!(file.compare(&file.clone()).is_empty());
+        assert!(artifact(&[]).compare(&artifact(&[])).is_empty());
+    }
+
+    #[test]
+    fn compare_differing_files() {
+        let file = artifact(&["int a;", "int b;", "int c;"]);
+        let other = artifact(&["int a;", "int B;", "int c;", "int d;"]);
+
+        let mismatches = file.compare(&other);
+        assert_eq!(2, mismatches.len());
+        assert_eq!(2, mismatches[0].line_number());
+        assert_eq!(Some("int b;"), mismatches[0].line());
+        assert_eq!(Some("int B;"), mismatches[0].other_line());
+        // The surplus line of the other file has no counterpart
+        assert_eq!(
+            LineMismatch {
+                line_number: 4,
+                line: None,
+                other_line: Some("int d;".to_string()),
+            },
+            mismatches[1]
+        );
+        assert_eq!("line 4: <missing> != \"int d;\"", mismatches[1].to_string());
+    }
+
+    fn rejects(position: usize) -> Vec<Change> {
+        let file_diff = crate::VersionDiff::try_from(format!(
+            "diff -Naur a/file b/file\n--- a/file\n+++ b/file\n@@ -1 +1,3 @@\n line\n+first of {position}\n+second of {position}"
+        ))
+        .unwrap()
+        .file_diffs()[0]
+            .clone();
+        crate::FilePatch::from(file_diff).changes().to_vec()
+    }
+
+    #[test]
+    fn write_rejects_from_concurrent_tasks() {
+        let path = std::env::temp_dir().join("mpatch_write_rejects_from_concurrent_tasks.rej");
+        // Rerunning must not fail because of an existing rejects file
+        fs::write(&path, "outdated rejects").unwrap();
+
+        let rejects_file = RejectsFile::new(path.clone());
+        std::thread::scope(|scope| {
+            for position in (0..8).rev() {
+                let rejects_file = &rejects_file;
+                scope.spawn(move || {
+                    let rejects = rejects(position);
+                    rejects_file.record(position, &format!("header {position}"), &rejects);
+                });
+            }
+        });
+        rejects_file.write().unwrap();
+
+        let expected: String = (0..8)
+            .map(|p| format!("header {p}\n0: +first of {p}\n1: +second of {p}\n"))
+            .collect();
+        assert_eq!(expected, fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skip_writing_without_rejects() {
+        let path = std::env::temp_dir().join("mpatch_skip_writing_without_rejects.rej");
+        let _ = fs::remove_file(&path);
+        RejectsFile::new(path.clone()).write().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn resolve_paths_case_insensitively() {
+        let root = std::env::temp_dir().join("mpatch_resolve_paths_case_insensitively");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Src")).unwrap();
+        fs::write(root.join("Src/main.c"), "").unwrap();
+
+        let resolve = |relative: &str| resolve_case_insensitively(&root, relative.as_ref());
+        assert_eq!(root.join("Src/main.c"), resolve("src/MAIN.c").unwrap());
+        assert_eq!(root.join("Src/main.c"), resolve("Src/main.c").unwrap());
+        // Missing files keep their case
+        assert_eq!(root.join("Src/New.c"), resolve("src/New.c").unwrap());
+        assert_eq!(root.join("lib/Util.c"), resolve("lib/Util.c").unwrap());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reject_ambiguous_case_insensitive_paths() {
+        let root = std::env::temp_dir().join("mpatch_reject_ambiguous_case_insensitive_paths");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("main.c"), "").unwrap();
+        fs::write(root.join("MAIN.c"), "").unwrap();
+
+        let error = resolve_case_insensitively(&root, "Main.c".as_ref()).unwrap_err();
+        assert_eq!(ErrorKind::PatchError, *error.kind());
+        // An exact match is not ambiguous
+        assert_eq!(
+            root.join("main.c"),
+            resolve_case_insensitively(&root, "main.c".as_ref()).unwrap()
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_artifact_from_reader() {
+        let reader = std::io::Cursor::new("int a;\r\nint b;\r\n".as_bytes());
+        let artifact = FileArtifact::from_reader(PathBuf::from("main.c"), reader).unwrap();
+        assert_eq!(PathBuf::from("main.c"), artifact.path());
+        assert_eq!(["int a;", "int b;"], artifact.lines());
+        assert_eq!(LineEnding::Crlf, artifact.line_ending());
+
+        let reader = std::io::Cursor::new([0x66, 0xff, 0x6f].as_slice());
+        let error = FileArtifact::from_reader(PathBuf::from("main.c"), reader).unwrap_err();
+        assert_eq!(&ErrorKind::IOError, error.kind());
+    }
 }
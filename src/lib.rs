@@ -37,9 +37,43 @@
 //!     eprintln!("{}", error);
 //! }
 //! ```
+//!
+//! ## Checking the outcome for each file
+//! If you need the outcome of each file patch, you can use `apply_all_collect` instead. It returns
+//! a result for each file in the diff, which can be checked for rejected changes.
+//! ```
+//! use std::path::PathBuf;
+//! use mpatch::KeepAllFilter;
+//! use mpatch::PatchOptions;
+//! use mpatch::PatchPaths;
+//!
+//! let patch_paths = PatchPaths::new(
+//!     PathBuf::from("tests/collect/source_variant/version-0"),
+//!     PathBuf::from("tests/collect/target_variant/version-0"),
+//!     PathBuf::from("tests/collect/diffs/collect.diff"),
+//!     None,
+//! );
+//!
+//! let options = PatchOptions::builder().dryrun(true).build();
+//! let results =
+//!     mpatch::apply_all_collect(patch_paths, 1, mpatch::LCSMatcher, KeepAllFilter, &options)
+//!         .unwrap();
+//! for (path, result) in results {
+//!     match result {
+//!         Ok(outcome) if outcome.is_clean() => println!("patched {}", path.display()),
+//!         Ok(outcome) => println!(
+//!             "{} rejects for {}",
+//!             outcome.rejected_changes().len(),
+//!             path.display()
+//!         ),
+//!         Err(error) => eprintln!("{}: {}", path.display(), error),
+//!     }
+//! }
+//! ```
 
-// TODO: Feature traces and target configuration are part of the input!
-// TODO: Handle git diffs as well; they have differences e.g., /dev/null, permission change
+// TODO: Feature traces are part of the input! Currently, they can only be provided as feature
+// tags in the changed lines (see FilePatch::from_file_diff and filtering::ConfigurationFilter).
+// TODO: Handle the remaining differences of git diffs, e.g., renames
 // TODO: Handle certain edge cases in which code is added at then end of the file (the existing
 // last line should not be pushed down)
 
@@ -62,6 +96,8 @@ pub use patch::filtering;
 pub use patch::matching;
 /// Module for types and functions that represent patches and patch application.
 pub mod patch;
+/// Module for running a patch application like the mpatch binary.
+pub mod run;
 
 #[doc(inline)]
 pub use diffs::FileDiff;
@@ -74,24 +110,97 @@ pub use error::ErrorKind;
 #[doc(inline)]
 pub use io::FileArtifact;
 #[doc(inline)]
+pub use io::LineEnding;
+#[doc(inline)]
+pub use io::LineMismatch;
+#[doc(inline)]
+pub use io::RejectSink;
+#[doc(inline)]
+pub use io::RejectsFile;
+#[doc(inline)]
+pub use io::ReportFile;
+#[doc(inline)]
+pub use matching::BlankLineCollapsingMatcher;
+#[doc(inline)]
+pub use matching::ContextHashMatcher;
+#[doc(inline)]
+pub use matching::ContextMatcher;
+#[cfg(feature = "regex")]
+#[doc(inline)]
+pub use matching::IgnorePatternMatcher;
+#[doc(inline)]
 pub use matching::LCSMatcher;
 #[doc(inline)]
+pub use matching::MatchAlgorithm;
+#[doc(inline)]
 pub use matching::Matcher;
 #[doc(inline)]
 pub use matching::Matching;
 #[doc(inline)]
+pub use matching::NumericWildcardMatcher;
+#[doc(inline)]
+pub use matching::UniqueExactMatcher;
+#[doc(inline)]
 pub use patch::apply_all;
 #[doc(inline)]
+pub use patch::apply_all_collect;
+#[doc(inline)]
+pub use patch::apply_all_with_options;
+#[doc(inline)]
+pub use patch::filtering::ConfigurationFilter;
+#[doc(inline)]
 pub use patch::filtering::DistanceFilter;
 #[doc(inline)]
+pub use patch::filtering::FeatureTagFilter;
+#[doc(inline)]
 pub use patch::filtering::Filter;
 #[doc(inline)]
 pub use patch::filtering::KeepAllFilter;
 #[doc(inline)]
+pub use patch::filtering::TargetConfiguration;
+#[doc(inline)]
+pub use patch::group_rejects;
+#[doc(inline)]
+pub use patch::patch_str;
+#[doc(inline)]
+pub use patch::preview_rejects;
+#[doc(inline)]
 pub use patch::AlignedPatch;
 #[doc(inline)]
+pub use patch::ApplyStats;
+#[doc(inline)]
+pub use patch::CancelFlag;
+#[doc(inline)]
+pub use patch::Conflict;
+#[doc(inline)]
+pub use patch::ConflictSet;
+#[doc(inline)]
+pub use patch::DuplicateTargetPolicy;
+#[doc(inline)]
 pub use patch::FilePatch;
 #[doc(inline)]
+pub use patch::FinalNewline;
+#[doc(inline)]
+pub use patch::MergeConflict;
+#[doc(inline)]
+pub use patch::PatchOptions;
+#[doc(inline)]
+pub use patch::PatchOptionsBuilder;
+#[doc(inline)]
 pub use patch::PatchOutcome;
 #[doc(inline)]
 pub use patch::PatchPaths;
+#[doc(inline)]
+pub use patch::PlacementStrategy;
+#[doc(inline)]
+pub use patch::RejectGroup;
+#[doc(inline)]
+pub use patch::SymlinkPolicy;
+#[doc(inline)]
+pub use patch::WhitespaceMode;
+#[doc(inline)]
+pub use run::run;
+#[doc(inline)]
+pub use run::FilterChoice;
+#[doc(inline)]
+pub use run::RunConfig;
@@ -3,16 +3,40 @@ pub mod application;
 pub mod filtering;
 pub mod matching;
 
-use std::{fmt::Display, fs::File, io::BufWriter, path::PathBuf, vec};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+    vec,
+};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+use similar::TextDiff;
 
 use crate::{
     alignment::align_filtered_patch_to_target,
-    diffs::{FileDiff, VersionDiff},
-    io::{print_rejects, write_rejects, FileArtifact, StrippedPath},
-    patch::application::apply_patch,
-    Error, Matcher,
+    diffs::{FileDiff, Hunk, LineType, VersionDiff},
+    io::{
+        print_rejects, resolve_case_insensitively, DiskFileStore, FileArtifact, FileStore,
+        RejectSink, RejectsFile, ReportFile, StrippedPath,
+    },
+    matching::MatchId,
+    patch::application::apply_patch_with_options,
+    Error, ErrorKind, KeepAllFilter, LCSMatcher, Matcher, Matching,
 };
 
+#[cfg(feature = "serde")]
+use crate::io::{append_ndjson_rejects, write_json_rejects};
+
 use self::filtering::Filter;
 
 /// Applies all file patches that are found in the diff file. This function also requires a path to
@@ -24,6 +48,7 @@ use self::filtering::Filter;
 /// This function creates new files for files that are created by a patch, and it deletes files for
 /// file deletions in a patch, regardless of whether the lines in the patch match the lines in the
 /// file completelty.
+/// Diffs of binary files are skipped.
 ///
 /// ## Parameters
 ///
@@ -65,71 +90,1103 @@ pub fn apply_all(
     patch_paths: PatchPaths,
     strip: usize,
     dryrun: bool,
+    matcher: impl Matcher,
+    filter: impl Filter,
+) -> Result<ApplyStats, Error> {
+    let options = PatchOptions {
+        dryrun,
+        ..Default::default()
+    };
+    apply_all_with_options(patch_paths, strip, matcher, filter, &options)
+}
+
+/// Statistics about the changes that have been applied across all files by
+/// `apply_all_with_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ApplyStats {
+    patched_files: usize,
+    added_lines: usize,
+    removed_lines: usize,
+    rejected_changes: usize,
+    elapsed: Duration,
+}
+
+impl ApplyStats {
+    /// Returns the number of files that have been patched, including files for which all changes
+    /// have been rejected. Files that have been skipped because the patch has already been
+    /// applied to them are not counted.
+    pub fn patched_files(&self) -> usize {
+        self.patched_files
+    }
+
+    /// Returns the number of changes that have been applied across all files.
+    pub fn applied_changes(&self) -> usize {
+        self.added_lines + self.removed_lines
+    }
+
+    /// Returns the number of lines that have been added across all files.
+    pub fn added_lines(&self) -> usize {
+        self.added_lines
+    }
+
+    /// Returns the number of lines that have been removed across all files.
+    pub fn removed_lines(&self) -> usize {
+        self.removed_lines
+    }
+
+    /// Returns the number of changes that have been rejected across all files.
+    pub fn rejected_changes(&self) -> usize {
+        self.rejected_changes
+    }
+
+    /// Returns the wall-clock time that the patch application took.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Applies all file patches that are found in the diff file using the given options. See
+/// `apply_all` for more information on the parameters; whether the patch application is only a
+/// dryrun is determined by `PatchOptions::dryrun`. The options also determine how the source and
+/// target files are read (see `PatchOptions::lossy_read`).
+///
+/// ## Returns
+/// Returns statistics about the changes that have been applied across all files.
+///
+/// ## Error
+/// Returns an Error if the target directory does not exist and cannot be created according to
+/// `PatchOptions::create_target_root`, or if the hunks of a file diff overlap (see
+/// `FileDiff::validate`).
+pub fn apply_all_with_options(
+    patch_paths: PatchPaths,
+    strip: usize,
     mut matcher: impl Matcher,
     mut filter: impl Filter,
-) -> Result<(), Error> {
-    let diff = VersionDiff::read(patch_paths.patch_file_path)?;
+    options: &PatchOptions,
+) -> Result<ApplyStats, Error> {
+    let start = Instant::now();
+    let file_diffs = read_file_diffs(&patch_paths, options)?;
 
-    // We only create a rejects file if there are rejects
-    let mut rejects_file: Option<BufWriter<File>> = None;
-
-    for file_diff in diff {
-        // Required for reject printing/writing
-        let diff_header = file_diff.header();
+    // By default, rejects are written to the rejects file or printed if there is no such file
+    let reject_sink =
+        options
+            .reject_sink
+            .clone()
+            .unwrap_or_else(|| match &patch_paths.rejects_file_path {
+                Some(path) => RejectSink::File(path.clone()),
+                None => RejectSink::Print,
+            });
+    let reporter = OutcomeReporter::new(Some(reject_sink), true, options)?;
+    let mut file_store = DiskFileStore::new(options.lossy_read);
+    let edit_counter = EditCounter::default();
 
-        let mut source_file_path = patch_paths.source_dir_path.clone();
-        source_file_path.push(PathBuf::strip_cloned(
-            &file_diff.source_file_header().path_cloned(),
+    // Aligns the patch of a single file diff, merged with its duplicates, to its target file.
+    // Returns None if the target file is already patched (see PatchOptions::skip_already_applied).
+    let mut align = |file_diffs: (FileDiff, Vec<FileDiff>)| {
+        check_cancelled(options)?;
+        let file_paths =
+            patch_paths.resolve_file_paths_with_options(&file_diffs.0, strip, options)?;
+        let target_file_path = file_paths.target.clone();
+        let alignment = align_file_diff(
+            file_diffs,
+            file_paths,
             strip,
-        ));
+            &mut matcher,
+            &mut filter,
+            &mut file_store,
+            options,
+        )?;
+        if alignment.is_none() {
+            println!("--------------------------------------------------------");
+            println!("Unchanged {}", target_file_path.to_string_lossy());
+        }
+        Ok(alignment)
+    };
+    // Applies an aligned patch and reports its outcome
+    let apply = |position: usize, diff_header: String, alignment: FileAlignment| {
+        check_cancelled(options)?;
+        let patch_outcome = apply_alignment(alignment, &edit_counter, options)??;
+        edit_counter.record(&patch_outcome);
+        reporter.report(position, diff_header, &patch_outcome)
+    };
 
-        let mut target_file_path = patch_paths.target_dir_path.clone();
-        target_file_path.push(PathBuf::strip_cloned(
-            &file_diff.target_file_header().path_cloned(),
-            strip,
+    let mut file_diffs = file_diffs
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (file_diff, _))| !skip_binary(file_diff));
+    let result = match options.max_in_flight {
+        None => file_diffs.try_for_each(|(position, file_diffs)| {
+            // Required for reject printing/writing
+            let diff_header = file_diffs.0.header();
+            match align(file_diffs)? {
+                Some(alignment) => apply(position, diff_header, alignment),
+                // Files that are already patched are skipped
+                None => Ok(()),
+            }
+        }),
+        Some(max_in_flight) => thread::scope(|scope| {
+            // The aligned patches are applied by a worker while the next files are aligned. The
+            // bounded queue blocks the alignment if the worker falls behind, so that the number
+            // of files held in memory is capped.
+            let (sender, receiver) = mpsc::sync_channel(max_in_flight);
+            let apply = &apply;
+            let worker = scope.spawn(move || {
+                receiver
+                    .into_iter()
+                    .try_for_each(|(position, diff_header, alignment)| {
+                        apply(position, diff_header, alignment)
+                    })
+            });
+
+            let mut result = Ok(());
+            for (position, file_diffs) in file_diffs {
+                let diff_header = file_diffs.0.header();
+                match align(file_diffs) {
+                    Ok(None) => (),
+                    Ok(Some(alignment)) => {
+                        // The worker only stops receiving if it failed; its error is returned below
+                        if sender.send((position, diff_header, alignment)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        result = Err(error);
+                        break;
+                    }
+                }
+            }
+            drop(sender);
+            let worker_result = worker.join().expect("the patch worker should not panic");
+            // The worker's error concerns an earlier file than an error of the alignment
+            worker_result.and(result)
+        }),
+    };
+
+    // Keep the rejects of the files that have been patched, even if the patching failed
+    reporter.finish()?;
+    result.map(|()| edit_counter.into_stats(start.elapsed()))
+}
+
+/// Reads the file diffs of the diff file and prepares them for patching according to the options
+/// (see `PatchOptions::skip_whitespace_hunks`, `PatchOptions::sort_files`, and
+/// `PatchOptions::duplicate_targets`). Each file diff is paired with the later file diffs for the
+/// same file that are merged into it. A missing target directory is created if the options allow
+/// it (see `PatchOptions::create_target_root`).
+fn read_file_diffs(
+    patch_paths: &PatchPaths,
+    options: &PatchOptions,
+) -> Result<Vec<(FileDiff, Vec<FileDiff>)>, Error> {
+    let mut diff = VersionDiff::read(&patch_paths.patch_file_path)?;
+    if options.skip_whitespace_hunks {
+        diff.retain_hunks(|hunk| !hunk.is_whitespace_only());
+    }
+
+    // The target directory may only be created for diffs that create all of their files
+    if !patch_paths.target_dir_path.exists() {
+        let creates_only = diff
+            .file_diffs()
+            .iter()
+            .filter(|d| !d.is_binary())
+            .all(FileDiff::is_creation);
+        if !options.create_target_root || !creates_only {
+            return Err(Error::new(
+                &format!(
+                    "the target directory {} does not exist",
+                    patch_paths.target_dir_path.display()
+                ),
+                ErrorKind::IOError,
+            ));
+        }
+        if !options.dryrun {
+            fs::create_dir_all(&patch_paths.target_dir_path)?;
+        }
+    }
+
+    if options.duplicate_targets == DuplicateTargetPolicy::Error && diff.has_duplicate_targets() {
+        return Err(Error::new(
+            "the diff contains several file diffs for the same file",
+            ErrorKind::PatchError,
         ));
+    }
 
-        let source = FileArtifact::read_or_create_empty(source_file_path)?;
-        let target = FileArtifact::read_or_create_empty(target_file_path)?;
+    let mut file_diffs: Vec<FileDiff> = diff.into_iter().collect();
+    if options.sort_files {
+        file_diffs.sort_by_cached_key(|file_diff| file_diff.changed_file_path().to_path_buf());
+    }
 
-        let matching = matcher.match_files(source, target);
-        let patch = FilePatch::from(file_diff);
-        let filtered_patch = filter.apply_filter(patch, &matching);
-        let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+    if options.duplicate_targets != DuplicateTargetPolicy::Merge {
+        return Ok(file_diffs.into_iter().map(|d| (d, vec![])).collect());
+    }
+    let mut groups: Vec<(FileDiff, Vec<FileDiff>)> = vec![];
+    let mut group_indices: HashMap<PathBuf, usize> = HashMap::new();
+    for file_diff in file_diffs {
+        if file_diff.is_binary() {
+            groups.push((file_diff, vec![]));
+            continue;
+        }
+        match group_indices.get(file_diff.changed_file_path()) {
+            Some(&index) => groups[index].1.push(file_diff),
+            None => {
+                group_indices.insert(file_diff.changed_file_path().to_path_buf(), groups.len());
+                groups.push((file_diff, vec![]));
+            }
+        }
+    }
+    Ok(groups)
+}
+
+/// Fails with an error of kind `ErrorKind::Cancelled` if the patch application has been cancelled
+/// (see `PatchOptions::cancel_flag`).
+fn check_cancelled(options: &PatchOptions) -> Result<(), Error> {
+    match &options.cancel_flag {
+        Some(cancel_flag) if cancel_flag.is_cancelled() => Err(Error::new(
+            "the patch application has been cancelled",
+            ErrorKind::Cancelled,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Prints a message for skipping the given file diff if it is a diff of a binary file, which
+/// cannot be patched line by line. Returns true if the file diff is skipped.
+fn skip_binary(file_diff: &FileDiff) -> bool {
+    if file_diff.is_binary() {
+        println!("--------------------------------------------------------");
+        println!("Skipping binary file diff: {file_diff}");
+    }
+    file_diff.is_binary()
+}
+
+/// Creates the patch of the given file diff. The file diff is validated first, because the
+/// alignment of changes from overlapping hunks is ambiguous (see `FileDiff::validate`).
+fn validated_patch(file_diff: FileDiff) -> Result<FilePatch, Error> {
+    file_diff.validate()?;
+    Ok(FilePatch::from(file_diff))
+}
+
+/// Returns the path in the source file header of the given file diff. The source header of a
+/// created file may be `/dev/null` (e.g., in git diffs), in which case the path in the target file
+/// header is returned instead.
+fn source_header_path(file_diff: &FileDiff) -> PathBuf {
+    if file_diff.source_file_header().is_dev_null() {
+        file_diff.target_file_header().path_cloned()
+    } else {
+        file_diff.source_file_header().path_cloned()
+    }
+}
 
-        let patch_outcome = apply_patch(aligned_patch, dryrun)?;
+/// Returns the path in the target file header of the given file diff. The target header of a
+/// removed file may be `/dev/null` (e.g., in git diffs), in which case the path in the source file
+/// header is returned instead.
+fn target_header_path(file_diff: &FileDiff) -> PathBuf {
+    if file_diff.target_file_header().is_dev_null() {
+        file_diff.source_file_header().path_cloned()
+    } else {
+        file_diff.target_file_header().path_cloned()
+    }
+}
+
+/// Reports the outcomes of the patched files: the outcome of each file is printed, its rejects are
+/// reported to the reject sink, and its unified diff is recorded for the report file (see
+/// `PatchOptions::report_file`). The rejects file and the report file are written once all files
+/// have been patched (see `OutcomeReporter::finish`).
+struct OutcomeReporter {
+    /// Whether the change type and path of each patched file are printed.
+    print_outcomes: bool,
+    /// The sink to which rejects are reported. If there is none, rejects are not reported.
+    reject_sink: Option<RejectSink>,
+    /// The rejects are collected and written to the rejects file once all files have been patched
+    rejects_file: Option<RejectsFile>,
+    report_file: Option<ReportFile>,
+    /// Printed rejects are collected and grouped by content once all files have been patched
+    grouped_rejects: Option<Mutex<Vec<FileRejects>>>,
+}
+
+impl OutcomeReporter {
+    /// Creates a reporter that reports rejects to the given sink, if any. The rejects that have
+    /// been streamed to the sink by earlier patch applications are discarded.
+    fn new(
+        reject_sink: Option<RejectSink>,
+        print_outcomes: bool,
+        options: &PatchOptions,
+    ) -> Result<OutcomeReporter, Error> {
+        let rejects_file = match &reject_sink {
+            Some(RejectSink::File(path)) => Some(RejectsFile::new(path.clone())),
+            _ => None,
+        };
+        let grouped_rejects = (options.group_rejects && reject_sink == Some(RejectSink::Print))
+            .then(Mutex::<Vec<FileRejects>>::default);
+        #[cfg(feature = "serde")]
+        if let Some(RejectSink::Ndjson(path)) = &reject_sink {
+            fs::File::create(path)?;
+        }
+        Ok(OutcomeReporter {
+            print_outcomes,
+            reject_sink,
+            rejects_file,
+            report_file: options.report_file.clone().map(ReportFile::new),
+            grouped_rejects,
+        })
+    }
 
+    /// Reports the outcome of patching a single file. The position of the file diff in the diff
+    /// determines the order of the rejects in the rejects file and of the diffs in the report
+    /// file. If the rejects are grouped, printed rejects are collected instead (see
+    /// `PatchOptions::group_rejects`).
+    fn report(
+        &self,
+        position: usize,
+        diff_header: String,
+        patch_outcome: &PatchOutcome,
+    ) -> Result<(), Error> {
         let (actual_result, rejects, change_type) = (
             patch_outcome.patched_file(),
             patch_outcome.rejected_changes(),
             patch_outcome.change_type(),
         );
+        if let Some(report_file) = &self.report_file {
+            report_file.record(position, patch_outcome.as_unified_diff());
+        }
 
         // print the result
-        println!("--------------------------------------------------------");
-        println!("{change_type} {}", actual_result.path().to_string_lossy());
+        if self.print_outcomes {
+            println!("--------------------------------------------------------");
+            println!("{change_type} {}", actual_result.path().to_string_lossy());
+        }
+
+        if rejects.is_empty() {
+            return Ok(());
+        }
+        match &self.reject_sink {
+            None => (),
+            Some(RejectSink::Print) => match &self.grouped_rejects {
+                Some(grouped_rejects) => grouped_rejects
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push((actual_result.path().to_path_buf(), rejects.to_vec())),
+                None => print_rejects(diff_header, rejects),
+            },
+            Some(RejectSink::File(_)) => self
+                .rejects_file
+                .as_ref()
+                .expect("there should be a rejects file for the file sink")
+                .record(position, &diff_header, rejects),
+            #[cfg(feature = "serde")]
+            Some(RejectSink::JsonSidecar) => write_json_rejects(actual_result.path(), rejects)?,
+            #[cfg(feature = "serde")]
+            Some(RejectSink::Ndjson(path)) => {
+                append_ndjson_rejects(path, actual_result.path(), rejects)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the rejects file and the report file, and prints the grouped rejects.
+    fn finish(self) -> Result<(), Error> {
+        if let Some(rejects_file) = self.rejects_file {
+            rejects_file.write()?;
+        }
+        if let Some(report_file) = self.report_file {
+            report_file.write()?;
+        }
+        if let Some(grouped_rejects) = self.grouped_rejects {
+            let file_rejects = grouped_rejects
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let groups = group_file_rejects(
+                file_rejects
+                    .iter()
+                    .map(|(path, rejects)| (path.as_path(), rejects.as_slice())),
+            );
+            if !groups.is_empty() {
+                println!("--------------------------------------------------------");
+                println!("Rejects grouped by content:");
+                for group in groups {
+                    println!("{group}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies all file patches that are found in the diff file and collects the outcome of each
+/// patch application. In contrast to `apply_all`, an error that occurs while patching a single
+/// file (e.g., because its source cannot be read or its hunks overlap) does not abort the patch
+/// application. Instead, the error is recorded for that file and the remaining files are patched
+/// nonetheless.
+///
+/// The results are returned in the order in which the file diffs are patched (see
+/// `PatchOptions::sort_files`). Each result is paired with the path to the target file of the file
+/// diff. Diffs of binary files and files that are skipped because the patch has already been
+/// applied to them (see `PatchOptions::skip_already_applied`) have no result.
+///
+/// The files are patched like in `apply_all_with_options` and with the same options, except that
+/// they are patched one after another (see `PatchOptions::max_in_flight`) and that nothing is
+/// printed. Rejects are only reported if a sink has been set explicitly (see
+/// `PatchOptions::reject_sink`); otherwise, it is up to the caller to process the collected
+/// outcomes. See `apply_all_with_options` for more information on the parameters.
+///
+/// ## Error
+/// Returns an Error if the diff file cannot be read or parsed, or if the target directory does not
+/// exist and cannot be created according to `PatchOptions::create_target_root`. Returns an Error
+/// of kind `ErrorKind::Cancelled` if the patch application has been cancelled, and an Error of
+/// kind PatchError if it has been aborted because of `PatchOptions::duplicate_targets` or
+/// `PatchOptions::max_total_edits`.
+pub fn apply_all_collect(
+    patch_paths: PatchPaths,
+    strip: usize,
+    mut matcher: impl Matcher,
+    mut filter: impl Filter,
+    options: &PatchOptions,
+) -> Result<Vec<FileResult>, Error> {
+    let file_diffs = read_file_diffs(&patch_paths, options)?;
+    let reporter = OutcomeReporter::new(options.reject_sink.clone(), false, options)?;
+    let mut file_store = DiskFileStore::new(options.lossy_read);
+    let edit_counter = EditCounter::default();
+
+    let mut results = Vec::with_capacity(file_diffs.len());
+    // Binary files cannot be patched line by line and are skipped
+    let file_diffs = file_diffs
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (file_diff, _))| !file_diff.is_binary());
+    for (position, file_diffs) in file_diffs {
+        check_cancelled(options)?;
+        let diff_header = file_diffs.0.header();
+        // The path of the target file is only resolved according to the options if possible
+        let mut target_file_path = patch_paths.resolve_file_paths(&file_diffs.0, strip).target;
+        let alignment = patch_paths
+            .resolve_file_paths_with_options(&file_diffs.0, strip, options)
+            .and_then(|file_paths| {
+                target_file_path = file_paths.target.clone();
+                align_file_diff(
+                    file_diffs,
+                    file_paths,
+                    strip,
+                    &mut matcher,
+                    &mut filter,
+                    &mut file_store,
+                    options,
+                )
+            });
+
+        let result = match alignment {
+            Ok(Some(alignment)) => apply_alignment(alignment, &edit_counter, options)?,
+            // Files that are already patched are skipped
+            Ok(None) => continue,
+            Err(error) => Err(error),
+        };
+        let result = result.and_then(|patch_outcome| {
+            edit_counter.record(&patch_outcome);
+            reporter.report(position, diff_header, &patch_outcome)?;
+            Ok(PatchOutcome {
+                matcher_description: Some(matcher.describe()),
+                filter_description: Some(filter.describe()),
+                ..patch_outcome
+            })
+        });
+        results.push((target_file_path, result));
+    }
+
+    reporter.finish()?;
+    Ok(results)
+}
+
+/// Applies the diff of a single file to the given target text and returns the patched text. The
+/// diff is expected to have been created for the given source text. The source and target are
+/// matched with an LCSMatcher and all changes are kept (see `KeepAllFilter`). Nothing is read
+/// from or written to disk. The patched text has the line endings of the target text and ends
+/// with a newline if the target text does.
+///
+/// ```
+/// let diff = "diff -Naur version-0/main.c version-1/main.c
+/// --- version-0/main.c
+/// +++ version-1/main.c
+/// @@ -1,2 +1,3 @@
+///  int a;
+/// +int b;
+///  int c;
+/// ";
+/// let source = "int a;\nint c;\n";
+/// let target = "#include <stdio.h>\nint a;\nint c;\n";
+///
+/// let patched = mpatch::patch_str(diff, source, target).unwrap();
+/// assert_eq!("#include <stdio.h>\nint a;\nint b;\nint c;\n", patched);
+/// ```
+///
+/// ## Error
+/// Returns an Error of kind DiffParseError if the diff cannot be parsed or does not consist of
+/// exactly one FileDiff with hunks that do not overlap, and an Error of kind PatchError if a change cannot be applied
+/// to the target text.
+pub fn patch_str(diff: &str, source: &str, target: &str) -> Result<String, Error> {
+    let diff = VersionDiff::try_from(diff.to_string())?;
+    if diff.len() != 1 {
+        return Err(Error::new(
+            &format!(
+                "expected a diff of a single file, found {} file diffs",
+                diff.len()
+            ),
+            ErrorKind::DiffParseError,
+        ));
+    }
+    let file_diff = diff
+        .into_iter()
+        .next()
+        .expect("there should be one file diff");
+    if file_diff.hunks().is_empty() {
+        return Err(Error::new(
+            &format!(
+                "the diff of {} contains no hunks",
+                file_diff.changed_file_path().display()
+            ),
+            ErrorKind::DiffParseError,
+        ));
+    }
+
+    let path = file_diff.changed_file_path().to_path_buf();
+    let source = FileArtifact::from_reader(path.clone(), source.as_bytes())?;
+    let target = FileArtifact::from_reader(path, target.as_bytes())?;
+    let matching = LCSMatcher.match_files(source, target);
+    let filtered_patch = KeepAllFilter.apply_filter(validated_patch(file_diff)?, &matching)?;
+    let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+
+    let options = PatchOptions {
+        normalize_inserted_endings: true,
+        final_newline: FinalNewline::Preserve,
+        ..Default::default()
+    };
+    let patch_outcome = application::apply_in_memory(aligned_patch, &options)?;
+    if !patch_outcome.is_clean() {
+        return Err(Error::new(
+            &format!(
+                "{} of the changes cannot be applied to the target",
+                patch_outcome.rejected_changes.len()
+            ),
+            ErrorKind::PatchError,
+        ));
+    }
+    Ok(patch_outcome.patched_file.content())
+}
+
+/// A group of identical rejects in several files, i.e., rejects with the same change type and
+/// line (see `group_rejects`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectGroup {
+    reject: Change,
+    files: Vec<PathBuf>,
+}
+
+impl RejectGroup {
+    /// Returns the first reject of the group. All rejects in the group have its change type and
+    /// line, but may differ in their line numbers and ids.
+    pub fn reject(&self) -> &Change {
+        &self.reject
+    }
+
+    /// Returns the paths to the files in which the rejects occurred, in the order in which the
+    /// files were reported. Each file is contained once.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+impl Display for RejectGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let files: Vec<_> = self.files.iter().map(|f| f.to_string_lossy()).collect();
+        write!(
+            f,
+            "{} (rejected in {} {}: {})",
+            self.reject.to_marked_line(),
+            self.files.len(),
+            if self.files.len() == 1 {
+                "file"
+            } else {
+                "files"
+            },
+            files.join(", ")
+        )
+    }
+}
+
+/// Groups the rejects of the collected outcomes (see `apply_all_collect`) by their content, so
+/// that a change that is rejected in many files is reported once. Rejects have the same content
+/// if they have the same change type and line. The groups are ordered by their first reject.
+/// Results with an error are skipped.
+pub fn group_rejects(results: &[FileResult]) -> Vec<RejectGroup> {
+    group_file_rejects(results.iter().filter_map(|(path, result)| {
+        result
+            .as_ref()
+            .ok()
+            .map(|outcome| (path.as_path(), outcome.rejected_changes()))
+    }))
+}
 
-        if !rejects.is_empty() {
-            match &patch_paths.rejects_file_path {
-                Some(path) => write_rejects(diff_header, rejects, &mut rejects_file, path)?,
+/// Groups the rejects of the given files by their content (see `group_rejects`).
+fn group_file_rejects<'a>(
+    file_rejects: impl IntoIterator<Item = (&'a Path, &'a [Change])>,
+) -> Vec<RejectGroup> {
+    let mut groups: Vec<RejectGroup> = vec![];
+    let mut group_indices: HashMap<String, usize> = HashMap::new();
+    for (path, rejects) in file_rejects {
+        for reject in rejects {
+            match group_indices.get(&reject.to_marked_line()) {
+                Some(&index) => {
+                    let files = &mut groups[index].files;
+                    // The rejects of a file are consecutive, so it suffices to check the last file
+                    if files.last().map(PathBuf::as_path) != Some(path) {
+                        files.push(path.to_path_buf());
+                    }
+                }
                 None => {
-                    print_rejects(diff_header, rejects);
+                    group_indices.insert(reject.to_marked_line(), groups.len());
+                    groups.push(RejectGroup {
+                        reject: reject.clone(),
+                        files: vec![path.to_path_buf()],
+                    });
+                }
+            }
+        }
+    }
+    groups
+}
+
+/// Determines the rejects that would occur when applying all file patches that are found in the
+/// diff file, without applying them. In contrast to a dryrun, the patched content of the target
+/// files is not computed; the source and target files are only matched and the patches aligned.
+/// Patches for target files that are missing (or that already exist, if the patch creates the
+/// file) are rejected entirely, as they would be during the application.
+///
+/// The rejects are returned file by file in the order of the file diffs in the diff file, paired
+/// with the path to the target file. Files without rejects are included with an empty vector of
+/// rejects. Diffs of binary files are skipped. See `apply_all` for more information on the
+/// parameters.
+///
+/// ## Error
+/// Returns an Error if the diff file cannot be read or parsed, if the source or target file of a
+/// file diff cannot be read, or if the hunks of a file diff overlap (see `FileDiff::validate`).
+pub fn preview_rejects(
+    patch_paths: PatchPaths,
+    strip: usize,
+    mut matcher: impl Matcher,
+    mut filter: impl Filter,
+) -> Result<Vec<(PathBuf, Vec<Change>)>, Error> {
+    let diff = VersionDiff::read(&patch_paths.patch_file_path)?;
+
+    let mut previews = Vec::with_capacity(diff.len());
+    let mut file_store = DiskFileStore::new(false);
+    for file_diff in diff.into_iter().filter(|d| !d.is_binary()) {
+        let file_paths = patch_paths.resolve_file_paths(&file_diff, strip);
+        let target_file_path = file_paths.target.clone();
+
+        let rejects = match align_file_patch(
+            validated_patch(file_diff)?,
+            file_paths,
+            &mut matcher,
+            &mut filter,
+            &mut file_store,
+        )? {
+            FileAlignment::Aligned(aligned_patch) => application::preview_rejects(aligned_patch),
+            FileAlignment::Rejected(patch_outcome) => patch_outcome.rejected_changes,
+        };
+        previews.push((target_file_path, rejects));
+    }
+
+    Ok(previews)
+}
+
+/// The result of patching a single file, paired with the path to the patched target file.
+pub type FileResult = (PathBuf, Result<PatchOutcome, Error>);
+
+/// The rejects of a single file, paired with the path to the patched target file.
+type FileRejects = (PathBuf, Vec<Change>);
+
+/// Creates the patch of a single file diff, merged with the later file diffs for the same file,
+/// and aligns it to the target file (see `align_file_patch`). Before the alignment, the source
+/// file is checked against its expected hash (see `PatchOptions::expected_source_hashes`).
+/// Returns None if the target file is already patched (see
+/// `PatchOptions::skip_already_applied`).
+fn align_file_diff(
+    (file_diff, duplicates): (FileDiff, Vec<FileDiff>),
+    file_paths: FilePaths,
+    strip: usize,
+    matcher: &mut impl Matcher,
+    filter: &mut impl Filter,
+    file_store: &mut impl FileStore,
+    options: &PatchOptions,
+) -> Result<Option<FileAlignment>, Error> {
+    let source_path = PathBuf::strip_cloned(&source_header_path(&file_diff), strip);
+    let mut patch = validated_patch(file_diff)?;
+    for duplicate in duplicates {
+        patch = patch
+            .chain(validated_patch(duplicate)?)
+            .map_err(|conflict| {
+                Error::new(
+                    &format!("cannot merge the file diffs for the same file: {conflict}"),
+                    ErrorKind::PatchError,
+                )
+            })?;
+    }
+    if let Some(expected_hash) = options.expected_source_hashes.get(&source_path) {
+        let source = file_store.read_or_create_empty(file_paths.source.clone())?;
+        if source.content_hash() != *expected_hash {
+            return Err(Error::new(
+                &format!(
+                    "the source file {} has changed since the diff was created: expected hash \
+                     {expected_hash}, found {}",
+                    file_paths.source.display(),
+                    source.content_hash()
+                ),
+                ErrorKind::PatchError,
+            ));
+        }
+    }
+    if options.skip_already_applied && is_already_applied(&patch, &file_paths, file_store)? {
+        return Ok(None);
+    }
+    align_file_patch(patch, file_paths, matcher, filter, file_store).map(Some)
+}
+
+/// Applies an aligned patch and writes its outcome, unless this is a dryrun. The outcome is only
+/// written once it is known that it does not exceed the maximum number of edits (see
+/// `PatchOptions::max_total_edits`). The outer Error aborts the patch application of all files
+/// because the maximum would be exceeded; the inner Error concerns only the patched file.
+fn apply_alignment(
+    alignment: FileAlignment,
+    edit_counter: &EditCounter,
+    options: &PatchOptions,
+) -> Result<Result<PatchOutcome, Error>, Error> {
+    let aligned_patch = match alignment {
+        FileAlignment::Aligned(aligned_patch) => aligned_patch,
+        // Rejected patches have no applied changes that count towards the maximum
+        FileAlignment::Rejected(patch_outcome) => return Ok(Ok(patch_outcome)),
+    };
+    let patch_outcome = match application::apply_without_writing(aligned_patch, options) {
+        Ok(patch_outcome) => patch_outcome,
+        Err(error) => return Ok(Err(error)),
+    };
+    edit_counter.check_budget(&patch_outcome, options)?;
+    Ok(match options.dryrun {
+        true => Ok(patch_outcome),
+        false => application::write_outcome(&patch_outcome, options).map(|()| patch_outcome),
+    })
+}
+
+/// Counts the changes that have been applied across all files and the files to which they have
+/// been applied (see `ApplyStats`).
+#[derive(Default)]
+struct EditCounter {
+    applied_edits: AtomicUsize,
+    patched_files: AtomicUsize,
+    added_lines: AtomicUsize,
+    rejected_changes: AtomicUsize,
+}
+
+impl EditCounter {
+    /// Fails if applying the outcome's changes would exceed the maximum number of edits (see
+    /// `PatchOptions::max_total_edits`).
+    fn check_budget(
+        &self,
+        patch_outcome: &PatchOutcome,
+        options: &PatchOptions,
+    ) -> Result<(), Error> {
+        let applied_edits = self.applied_edits.load(Ordering::SeqCst);
+        match options.max_total_edits {
+            Some(max_total_edits)
+                if applied_edits + patch_outcome.applied_changes().len() > max_total_edits =>
+            {
+                Err(Error::new(
+                    &format!(
+                        "the patch exceeds the maximum of {max_total_edits} edits: {applied_edits} \
+                         edits have been applied to {} file(s) before patching {}",
+                        self.patched_files.load(Ordering::SeqCst),
+                        patch_outcome.patched_file().path().display()
+                    ),
+                    ErrorKind::PatchError,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts the applied and rejected changes of the given outcome.
+    fn record(&self, patch_outcome: &PatchOutcome) {
+        let applied_changes = patch_outcome.applied_changes();
+        self.applied_edits
+            .fetch_add(applied_changes.len(), Ordering::SeqCst);
+        self.patched_files.fetch_add(1, Ordering::SeqCst);
+        self.added_lines.fetch_add(
+            applied_changes
+                .iter()
+                .filter(|change| change.change_type() == LineChangeType::Add)
+                .count(),
+            Ordering::SeqCst,
+        );
+        self.rejected_changes
+            .fetch_add(patch_outcome.rejected_changes().len(), Ordering::SeqCst);
+    }
+
+    /// Returns the statistics of the counted changes.
+    fn into_stats(self, elapsed: Duration) -> ApplyStats {
+        let added_lines = self.added_lines.into_inner();
+        ApplyStats {
+            patched_files: self.patched_files.into_inner(),
+            added_lines,
+            removed_lines: self.applied_edits.into_inner() - added_lines,
+            rejected_changes: self.rejected_changes.into_inner(),
+            elapsed,
+        }
+    }
+}
+
+/// The result of aligning the patch of a single file diff to its target file.
+enum FileAlignment {
+    /// The patch has been aligned to the target file.
+    Aligned(AlignedPatch),
+    /// All changes of the patch have been rejected before the alignment, so that applying the
+    /// patch has no effect.
+    Rejected(PatchOutcome),
+}
+
+/// Returns true if the target file is already in the state that applying the patch would produce
+/// for the source file, i.e., if the target file is identical to the source file after the
+/// patch. A removed file is in this state if it does not exist.
+fn is_already_applied(
+    patch: &FilePatch,
+    file_paths: &FilePaths,
+    file_store: &mut impl FileStore,
+) -> Result<bool, Error> {
+    let target_exists = file_paths.target.exists();
+    let lines_after_patch = match patch.change_type {
+        FileChangeType::Remove => return Ok(!target_exists),
+        _ if !target_exists => return Ok(false),
+        FileChangeType::Create => patch.changes.iter().map(|c| c.line.clone()).collect(),
+        FileChangeType::Modify => {
+            // The changes refer to the lines of the source file, so that the patch is aligned to
+            // the source file by matching each line with itself
+            let source = file_store.read_or_create_empty(file_paths.source.clone())?;
+            let matching = identity_matching(source.clone(), source);
+            let filtered_patch = KeepAllFilter.apply_filter(patch.clone(), &matching)?;
+            let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+            match apply_patch_with_options(
+                aligned_patch,
+                &PatchOptions::builder().dryrun(true).build(),
+            ) {
+                Ok(patch_outcome) if patch_outcome.is_clean() => {
+                    patch_outcome.patched_file.into_lines()
                 }
+                // The patch does not apply to the source file, so its result is unknown
+                _ => return Ok(false),
+            }
+        }
+    };
+    let target = file_store.read_or_create_empty(file_paths.target.clone())?;
+    Ok(target.lines() == lines_after_patch)
+}
+
+/// Reads the source and target file of a single file patch, matches them, and aligns the patch
+/// to the target file. If there are alternative source files, each of
+/// them is matched with the target as well, and the matching with the best match ratio is used.
+/// If a matching dump path is given, the chosen matching is written to it as CSV.
+///
+/// The files are only read if they are required. If the filter does not require a matching and
+/// it rejects all changes, the patch has no effect and neither the source nor the target file is
+/// read. In this case, the patched file of the outcome is empty and no matching is dumped.
+fn align_file_patch(
+    patch: FilePatch,
+    file_paths: FilePaths,
+    matcher: &mut impl Matcher,
+    filter: &mut impl Filter,
+    file_store: &mut impl FileStore,
+) -> Result<FileAlignment, Error> {
+    let FilePaths {
+        source: source_file_path,
+        alternative_sources,
+        target: target_file_path,
+        matching_dump,
+    } = file_paths;
+    let mut match_files = |file_store: &mut dyn FileStore| -> Result<Matching, Error> {
+        let source = file_store.read_or_create_empty(source_file_path.clone())?;
+        let target = file_store.read_or_create_empty(target_file_path.clone())?;
+        // If the target is identical to the source, the changes apply at their original line
+        // numbers, so that the matcher is not needed
+        let identical =
+            source.lines() == target.lines() && source.line_ending() == target.line_ending();
+        let mut matching = match identical {
+            true => identity_matching(source, target),
+            false => matcher.match_files(source, target),
+        };
+        // An alternative source is only chosen if it matches the target strictly better
+        for source_path in alternative_sources.iter().filter(|_| !identical) {
+            let source = file_store.read_or_create_empty(source_path.clone())?;
+            let candidate = matcher.match_files(source, matching.target().clone());
+            if candidate.match_ratio() > matching.match_ratio() {
+                matching = candidate;
+            }
+        }
+        if let Some(path) = &matching_dump {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, matching.to_csv())?;
+        }
+        Ok(matching)
+    };
+
+    let (filtered_patch, matching) = if filter.requires_matching() {
+        let matching = match_files(file_store)?;
+        (filter.apply_filter(patch, &matching)?, Some(matching))
+    } else {
+        // The filter does not consider the matching, so an empty one suffices
+        let empty_matching = Matching::new(
+            FileArtifact::new(source_file_path.clone()),
+            FileArtifact::new(target_file_path.clone()),
+            vec![],
+            vec![],
+        );
+        (filter.apply_filter(patch, &empty_matching)?, None)
+    };
+
+    // A patch whose changes have all been rejected has no effect, unless it removes the file
+    if filtered_patch.changes.is_empty()
+        && !filtered_patch.rejected_changes.is_empty()
+        && filtered_patch.change_type != FileChangeType::Remove
+    {
+        return Ok(FileAlignment::Rejected(PatchOutcome {
+            patched_file: FileArtifact::new(target_file_path),
+            applied_changes: vec![],
+            rejected_changes: filtered_patch.rejected_changes,
+            change_type: filtered_patch.change_type,
+            mode_change: None,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        }));
+    }
+
+    let matching = match matching {
+        Some(matching) => matching,
+        None => match_files(file_store)?,
+    };
+    Ok(FileAlignment::Aligned(align_filtered_patch_to_target(
+        filtered_patch,
+        matching,
+    )))
+}
+
+/// Returns a matching of two files with identical lines in which each line is matched with the
+/// line at the same position.
+fn identity_matching(source: FileArtifact, target: FileArtifact) -> Matching {
+    let identity: Vec<MatchId> = (0..source.len()).map(Some).collect();
+    Matching::new(source, target, identity.clone(), identity)
+}
+
+/// Returns the name of the given type without the paths of its modules, e.g., `Option<String>`
+/// instead of `core::option::Option<alloc::string::String>`.
+pub(crate) fn describe_type<T: ?Sized>() -> String {
+    let type_name = std::any::type_name::<T>();
+    let mut description = String::with_capacity(type_name.len());
+    // The start of the path that is currently described
+    let mut path_start = 0;
+    let mut chars = type_name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            // Drop the module path that precedes the separator
+            chars.next();
+            description.truncate(path_start);
+        } else {
+            description.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                path_start = description.len();
             }
         }
     }
+    description
+}
 
-    Ok(())
+/// The paths of the files that are involved in patching a single file.
+struct FilePaths {
+    source: PathBuf,
+    /// Source files of other variants that might match the target better than the source.
+    alternative_sources: Vec<PathBuf>,
+    target: PathBuf,
+    /// The path to which the matching of source and target is dumped, if any.
+    matching_dump: Option<PathBuf>,
 }
 
+/// A function that rewrites the resolved path of a target file (see
+/// `PatchPaths::with_target_path_rewrite`).
+type TargetPathRewrite = Box<dyn Fn(&Path) -> PathBuf + Send + Sync>;
+
 pub struct PatchPaths {
     source_dir_path: PathBuf,
+    alternative_source_dir_paths: Vec<PathBuf>,
     target_dir_path: PathBuf,
     patch_file_path: PathBuf,
     rejects_file_path: Option<PathBuf>,
+    target_path_rewrite: Option<TargetPathRewrite>,
 }
 
 impl PatchPaths {
+    /// Determines the paths to the source and target file of the given file diff by stripping the
+    /// paths in the diff's headers and appending them to the source and target directory.
+    fn resolve_file_paths(&self, file_diff: &FileDiff, strip: usize) -> FilePaths {
+        let source_path = PathBuf::strip_cloned(&source_header_path(file_diff), strip);
+        let source_file_path = self.source_dir_path.join(&source_path);
+        let alternative_sources = self
+            .alternative_source_dir_paths
+            .iter()
+            .map(|dir| dir.join(&source_path))
+            .collect();
+
+        let mut target_file_path = self.target_dir_path.clone();
+        target_file_path.push(PathBuf::strip_cloned(&target_header_path(file_diff), strip));
+
+        FilePaths {
+            source: source_file_path,
+            alternative_sources,
+            target: self.rewrite_target_path(target_file_path),
+            matching_dump: None,
+        }
+    }
+
+    /// Determines the paths to the files of the given file diff like `resolve_file_paths`, but
+    /// resolves the path to the target file case-insensitively (see
+    /// `PatchOptions::case_insensitive_paths`) and determines the path to which the matching is
+    /// dumped (see `PatchOptions::dump_matching_dir`).
+    fn resolve_file_paths_with_options(
+        &self,
+        file_diff: &FileDiff,
+        strip: usize,
+        options: &PatchOptions,
+    ) -> Result<FilePaths, Error> {
+        let mut file_paths = self.resolve_file_paths(file_diff, strip);
+        let relative_target_path = PathBuf::strip_cloned(&target_header_path(file_diff), strip);
+
+        // The matching of each file is dumped to a file that is named after the target file
+        file_paths.matching_dump = options.dump_matching_dir.as_ref().map(|dir| {
+            let mut file_name = relative_target_path.clone().into_os_string();
+            file_name.push(".csv");
+            dir.join(file_name)
+        });
+
+        if options.case_insensitive_paths {
+            file_paths.target = self.rewrite_target_path(resolve_case_insensitively(
+                &self.target_dir_path,
+                &relative_target_path,
+            )?);
+        }
+        Ok(file_paths)
+    }
+
+    /// Rewrites the resolved path of a target file with the rewrite function, if there is one.
+    fn rewrite_target_path(&self, target_file_path: PathBuf) -> PathBuf {
+        match &self.target_path_rewrite {
+            Some(rewrite) => rewrite(&target_file_path),
+            None => target_file_path,
+        }
+    }
+
     pub fn new(
         source_dir_path: PathBuf,
         target_dir_path: PathBuf,
@@ -138,20 +1195,114 @@ impl PatchPaths {
     ) -> PatchPaths {
         PatchPaths {
             source_dir_path,
+            alternative_source_dir_paths: vec![],
             target_dir_path,
             patch_file_path,
             rejects_file_path,
+            target_path_rewrite: None,
         }
     }
+
+    /// Adds the directories of further source variants that are related to the source variant
+    /// (e.g., other variants of the same family). For each patched file, the corresponding file
+    /// of every source variant is matched with the target file, and the source whose matching
+    /// has the best match ratio (see `Matching::match_ratio`) is used to align the patch. If
+    /// several sources match equally well, the first of them is chosen, starting with the source
+    /// directory given to `PatchPaths::new`.
+    pub fn with_alternative_source_dirs(mut self, source_dir_paths: Vec<PathBuf>) -> PatchPaths {
+        self.alternative_source_dir_paths = source_dir_paths;
+        self
+    }
+
+    /// Sets a function that rewrites the path of each target file after it has been resolved
+    /// from the diff header, the strip, and the target directory. The function receives the
+    /// resolved path and returns the path of the file that is actually patched, e.g., to apply a
+    /// library's patch to a vendored copy of the library at a different location. The paths of
+    /// the source files are not affected.
+    pub fn with_target_path_rewrite(
+        mut self,
+        rewrite: impl Fn(&Path) -> PathBuf + Send + Sync + 'static,
+    ) -> PatchPaths {
+        self.target_path_rewrite = Some(Box::new(rewrite));
+        self
+    }
 }
 
 /// A file patch contains a vector of changes for a specific file from a FileDiff.
 /// A file patch also has a change type that describes whether the file is created, removed, or
-/// modified.
+/// modified. Furthermore, it retains the text of the hunks from which its changes originate and
+/// the mode that a git diff sets for the file (see `FileDiff::mode_change`).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FilePatch {
     changes: Vec<Change>,
     change_type: FileChangeType,
+    mode_change: Option<u32>,
+    hunk_texts: Vec<HunkText>,
+}
+
+/// The text of a hunk together with the origin ids of the changes in the hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HunkText {
+    origin_ids: Range<usize>,
+    text: String,
+}
+
+impl HunkText {
+    /// Reproduces the hunk for the patched file, in which only the changes with the given origin
+    /// ids have been rejected (see `FilePatch::reject_diff`).
+    fn reject_hunk(&self, rejected: &HashSet<usize>) -> Hunk {
+        let hunk = Hunk::try_from(self.text.lines().map(str::to_string).collect::<Vec<_>>())
+            .expect("the text of a parsed hunk should be valid");
+        let mut lines = vec![];
+        let (mut source_length, mut target_length) = (0, 0);
+        let mut origin_ids = self.origin_ids.clone();
+        // EOF metalines are only kept if the line that they refer to is kept
+        let mut previous_kept = false;
+        for line in hunk.lines() {
+            let line_type = match line.line_type() {
+                LineType::EOF if previous_kept => LineType::EOF,
+                LineType::EOF => continue,
+                LineType::Context => LineType::Context,
+                change_type => {
+                    let origin_id = origin_ids.next().expect("each change should have an id");
+                    if rejected.contains(&origin_id) {
+                        change_type
+                    } else if change_type == LineType::Add {
+                        // An applied add is part of the patched file
+                        LineType::Context
+                    } else {
+                        // An applied remove is no longer part of the patched file
+                        previous_kept = false;
+                        continue;
+                    }
+                }
+            };
+            match line_type {
+                LineType::Context => {
+                    source_length += 1;
+                    target_length += 1;
+                    lines.push(format!(" {}", line.text()));
+                }
+                LineType::Remove => {
+                    source_length += 1;
+                    lines.push(line.content().to_string());
+                }
+                LineType::Add => {
+                    target_length += 1;
+                    lines.push(line.content().to_string());
+                }
+                LineType::EOF => lines.push(line.content().to_string()),
+            }
+            previous_kept = true;
+        }
+        let header = format!(
+            "@@ -{},{source_length} +{},{target_length} @@",
+            hunk.source_location().hunk_start(),
+            hunk.target_location().hunk_start()
+        );
+        lines.insert(0, header);
+        Hunk::try_from(lines).expect("the reproduced hunk should be valid")
+    }
 }
 
 impl FilePatch {
@@ -159,166 +1310,1401 @@ impl FilePatch {
     pub fn changes(&self) -> &[Change] {
         &self.changes
     }
-}
 
-impl From<FileDiff> for FilePatch {
-    fn from(file_diff: FileDiff) -> Self {
-        let mut changes = vec![];
+    /// Returns the permission bits that are set for the patched file, if the patch changes the
+    /// mode of the file.
+    pub fn mode_change(&self) -> Option<u32> {
+        self.mode_change
+    }
 
-        // Determine the change type of this patch by looking at the first hunk
-        let first_hunk = file_diff.hunks().first().expect("no hunk in diff");
-        // A hunk start of '0' indicates that the file does not exist for source or target
-        let file_change_type = if first_hunk.source_location().hunk_start() == 0 {
-            FileChangeType::Create
-        } else if first_hunk.target_location().hunk_start() == 0 {
-            FileChangeType::Remove
-        } else {
-            FileChangeType::Modify
-        };
+    /// Returns the number of changes in this patch.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
 
-        // Extract all changes from the file diff
-        for (change_id, line) in file_diff.into_changes().enumerate() {
-            let line_number;
-            let change_type;
-            match line.line_type() {
-                crate::diffs::LineType::Add => {
-                    change_type = LineChangeType::Add;
-                    // Lines that are added do not exist in the source file yet; therefore, they only
-                    // have a change location, but no real location
-                    line_number = line.source_line().change_location();
-                }
-                crate::diffs::LineType::Remove => {
-                    change_type = LineChangeType::Remove;
-                    // Lines that are removed must exist in the source file and must thus have a
+    /// Returns true if this patch has no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the original text of the hunk that contains the change with the given change id
+    /// (see `Hunk`'s `Display` implementation). Returns None if the patch has no such change or
+    /// if the patch does not retain the hunk texts, which is the case for merged patches.
+    pub fn hunk_text_for(&self, change_id: usize) -> Option<&str> {
+        let change = self.changes.iter().find(|c| c.change_id == change_id)?;
+        self.hunk_texts
+            .iter()
+            .find(|h| h.origin_ids.contains(&change.origin_id))
+            .map(|h| h.text.as_str())
+    }
+
+    /// Creates a FileDiff with the hunks of the given rejects of this patch, which can be written
+    /// to a `.rej` file and applied to the patched file later on, e.g., with `patch`. The rejects
+    /// are identified by their origin ids.
+    ///
+    /// Each hunk of the original diff that contains a reject is reproduced with its context
+    /// lines, so that the rejected changes can be located in the patched file. Changes of the hunk
+    /// that have been applied are adapted to the patched file: applied adds become context lines
+    /// and applied removes are left out. The locations of the hunks are those of the original
+    /// hunks in the source file.
+    ///
+    /// ## Returns
+    /// Returns None if there are no rejects or if this patch does not retain the hunk texts,
+    /// which is the case for merged patches.
+    pub fn reject_diff(&self, path: &Path, rejects: &[Change]) -> Option<FileDiff> {
+        let rejected: HashSet<usize> = rejects.iter().map(|r| r.origin_id).collect();
+        let hunks: Vec<Hunk> = self
+            .hunk_texts
+            .iter()
+            .filter(|h| h.origin_ids.clone().any(|id| rejected.contains(&id)))
+            .map(|h| h.reject_hunk(&rejected))
+            .collect();
+        if hunks.is_empty() {
+            return None;
+        }
+        Some(FileDiff::from_hunks(path, hunks))
+    }
+
+    /// Merges this patch with another patch for the same source file into a single patch.
+    ///
+    /// The changes of both patches are combined by their line numbers in the source file.
+    /// Identical changes that are contained in both patches are only kept once. The patches
+    /// conflict if they remove the same source line with different content, or if they add
+    /// different lines at the same location. The change ids of the merged patch are reassigned
+    /// in the order of the merged changes, while their origin ids are retained. The merged patch
+    /// does not retain the hunk texts of the patches.
+    ///
+    /// ## Error
+    /// Returns a MergeConflict describing the first conflict that was found, if the patches
+    /// cannot be merged.
+    pub fn merge(self, other: FilePatch) -> Result<FilePatch, MergeConflict> {
+        if self.change_type != other.change_type {
+            return Err(MergeConflict::ChangeType(
+                self.change_type,
+                other.change_type,
+            ));
+        }
+
+        let mut changes: Vec<Change> = vec![];
+        let mut left = group_by_location(self.changes).into_iter().peekable();
+        let mut right = group_by_location(other.changes).into_iter().peekable();
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (None, None) => break,
+                (Some(_), None) => left.next(),
+                (None, Some(_)) => right.next(),
+                (Some(l), Some(r)) => match l.0.cmp(&r.0) {
+                    std::cmp::Ordering::Less => left.next(),
+                    std::cmp::Ordering::Greater => right.next(),
+                    std::cmp::Ordering::Equal => {
+                        let (location, left_changes) = left.next().expect("peeked before");
+                        let (_, right_changes) = right.next().expect("peeked before");
+                        let compatible = left_changes.len() == right_changes.len()
+                            && left_changes
+                                .iter()
+                                .zip(right_changes.iter())
+                                .all(|(l, r)| l.line == r.line);
+                        if !compatible {
+                            return Err(MergeConflict::Lines {
+                                line_number: location.0,
+                                changes: left_changes,
+                                other_changes: right_changes,
+                            });
+                        }
+                        Some((location, left_changes))
+                    }
+                },
+            };
+            if let Some((_, group)) = next {
+                changes.extend(group);
+            }
+        }
+
+        for (change_id, change) in changes.iter_mut().enumerate() {
+            change.change_id = change_id;
+        }
+
+        // The origin ids of both patches overlap; thus, the hunk texts cannot be retained
+        Ok(FilePatch {
+            changes,
+            change_type: self.change_type,
+            mode_change: other.mode_change.or(self.mode_change),
+            hunk_texts: vec![],
+        })
+    }
+
+    /// Chains this patch with a later patch for the same file into a single patch. In contrast to
+    /// `merge`, the line numbers of the later patch refer to the file as it is after applying this
+    /// patch (e.g., if several diffs for the same file have been concatenated); they are rebased
+    /// onto the source file of this patch. A line that this patch adds and the later patch removes
+    /// is neither added nor removed by the chained patch. The change ids of the chained patch are
+    /// reassigned in the order of its changes, while their origin ids are retained. The chained
+    /// patch does not retain the hunk texts of the patches.
+    ///
+    /// ## Error
+    /// Returns a MergeConflict if the patches have different change types.
+    pub fn chain(self, later: FilePatch) -> Result<FilePatch, MergeConflict> {
+        if self.change_type != later.change_type {
+            return Err(MergeConflict::ChangeType(
+                self.change_type,
+                later.change_type,
+            ));
+        }
+
+        /// A line of the source file with the change that removes it, if any, or a line that is
+        /// added by this patch.
+        enum Entry {
+            Source(usize, Option<Change>),
+            Added(Change),
+        }
+
+        let last_line_number =
+            |changes: &[Change]| changes.iter().map(|c| c.line_number).max().unwrap_or(0);
+        let (last_line, later_last_line) = (
+            last_line_number(&self.changes),
+            last_line_number(&later.changes),
+        );
+        let mut adds: BTreeMap<usize, Vec<Change>> = BTreeMap::new();
+        let mut removes: HashMap<usize, Change> = HashMap::new();
+        for change in self.changes {
+            match change.change_type {
+                // Lines that are added at location 0 are added before the first line as well
+                LineChangeType::Add => adds
+                    .entry(change.line_number.max(1))
+                    .or_default()
+                    .push(change),
+                LineChangeType::Remove => {
+                    removes.insert(change.line_number, change);
+                }
+            }
+        }
+
+        // The entries are in the order of the patched file; source lines that are removed are
+        // retained to determine the location of the adds. Each line of the patched file to which
+        // the later patch refers is covered.
+        let mut entries: Vec<Option<Entry>> = vec![];
+        let mut patched_lines = vec![];
+        let mut line_number = 1;
+        while line_number <= last_line || patched_lines.len() < later_last_line {
+            for add in adds.remove(&line_number).unwrap_or_default() {
+                patched_lines.push(entries.len());
+                entries.push(Some(Entry::Added(add)));
+            }
+            let remove = removes.remove(&line_number);
+            if remove.is_none() {
+                patched_lines.push(entries.len());
+            }
+            entries.push(Some(Entry::Source(line_number, remove)));
+            line_number += 1;
+        }
+
+        // Rebase the later changes onto the entries; adds are inserted before the entry of the
+        // patched line at their location
+        let mut inserts: BTreeMap<usize, Vec<Change>> = BTreeMap::new();
+        for change in later.changes {
+            let index = patched_lines
+                .get(change.line_number.max(1) - 1)
+                .copied()
+                .unwrap_or(entries.len());
+            match change.change_type {
+                LineChangeType::Add => inserts.entry(index).or_default().push(change),
+                LineChangeType::Remove => match &mut entries[index] {
+                    Some(Entry::Source(_, remove)) => *remove = Some(change),
+                    // The line is added by this patch; it is not added at all
+                    entry => *entry = None,
+                },
+            }
+        }
+
+        let mut changes = vec![];
+        let mut pending_adds = vec![];
+        for (index, entry) in entries.into_iter().enumerate() {
+            pending_adds.extend(inserts.remove(&index).unwrap_or_default());
+            match entry {
+                Some(Entry::Added(add)) => pending_adds.push(add),
+                Some(Entry::Source(line_number, remove)) => {
+                    changes.extend(remove.map(|remove| Change {
+                        line_number,
+                        ..remove
+                    }));
+                    changes.extend(
+                        pending_adds
+                            .drain(..)
+                            .map(|add| Change { line_number, ..add }),
+                    );
+                }
+                None => (),
+            }
+        }
+        pending_adds.extend(inserts.into_values().flatten());
+        changes.extend(
+            pending_adds
+                .into_iter()
+                .map(|add| Change { line_number, ..add }),
+        );
+        for (change_id, change) in changes.iter_mut().enumerate() {
+            change.change_id = change_id;
+        }
+
+        Ok(FilePatch {
+            changes,
+            change_type: self.change_type,
+            mode_change: later.mode_change.or(self.mode_change),
+            hunk_texts: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+impl FilePatch {
+    /// Creates a patch for tests that modifies a file with the given changes, without a mode change
+    /// or hunk texts.
+    pub(crate) fn for_test(changes: Vec<Change>) -> FilePatch {
+        FilePatch {
+            changes,
+            change_type: FileChangeType::Modify,
+            mode_change: None,
+            hunk_texts: vec![],
+        }
+    }
+}
+
+/// Groups the given changes by their line number and change type while retaining their order.
+fn group_by_location(changes: Vec<Change>) -> Vec<((usize, LineChangeType), Vec<Change>)> {
+    let mut groups: Vec<((usize, LineChangeType), Vec<Change>)> = vec![];
+    for change in changes {
+        let location = (change.line_number, change.change_type);
+        match groups.iter_mut().find(|(l, _)| *l == location) {
+            Some((_, group)) => group.push(change),
+            None => groups.push((location, vec![change])),
+        }
+    }
+    // The changes of different groups might be unordered (e.g., if a diff lists an add before a
+    // remove at the same line)
+    groups.sort_by_key(|(location, _)| *location);
+    groups
+}
+
+/// A MergeConflict describes why two FilePatches cannot be merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// The patches have different change types (e.g., one creates the file and the other
+    /// modifies it).
+    ChangeType(FileChangeType, FileChangeType),
+    /// The patches change the same source line incompatibly.
+    Lines {
+        /// The line number in the source file at which the patches conflict.
+        line_number: usize,
+        /// The changes of the patch on which merge was called.
+        changes: Vec<Change>,
+        /// The changes of the patch that was to be merged.
+        other_changes: Vec<Change>,
+    },
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeConflict::ChangeType(change_type, other_change_type) => write!(
+                f,
+                "conflicting file change types: {change_type} and {other_change_type}"
+            ),
+            MergeConflict::Lines { line_number, .. } => {
+                write!(f, "conflicting changes to line {line_number}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeConflict {}
+
+/// A Conflict describes changes of several patches that collide on the same target line when
+/// the patches are applied together (see `application::apply_many`). None of the colliding changes
+/// is applied; they are rejected instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    line_number: usize,
+    change_type: LineChangeType,
+    changes: Vec<(usize, Vec<Change>)>,
+}
+
+impl Conflict {
+    /// Returns the target line number at which the changes collide.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Returns the type of the colliding changes.
+    pub fn change_type(&self) -> LineChangeType {
+        self.change_type
+    }
+
+    /// Returns the colliding changes grouped by patch. Each group is paired with the index of its
+    /// patch in the applied patches.
+    pub fn changes(&self) -> &[(usize, Vec<Change>)] {
+        &self.changes
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "conflicting changes of {} patches to line {}",
+            self.changes.len(),
+            self.line_number
+        )
+    }
+}
+
+impl From<FileDiff> for FilePatch {
+    fn from(file_diff: FileDiff) -> Self {
+        FilePatch::from_file_diff_with_tags(file_diff, |_| Vec::new())
+    }
+}
+
+impl FilePatch {
+    /// Creates a FilePatch from the given FileDiff. If a tag marker is given, the feature tags of
+    /// each change are extracted from the changed line: each match of the marker in the line
+    /// yields one tag. If the marker has a capture group, the tag is the text captured by the
+    /// first group; otherwise, the tag is the entire match.
+    ///
+    /// For example, the marker `#feature:(\w+)` extracts the tag `X` from the line
+    /// `foo(); // #feature:X`.
+    #[cfg(feature = "regex")]
+    pub fn from_file_diff(file_diff: FileDiff, tag_marker: Option<&Regex>) -> FilePatch {
+        FilePatch::from_file_diff_with_tags(file_diff, |line| {
+            tag_marker.map_or_else(Vec::new, |m| extract_tags(line, m))
+        })
+    }
+
+    /// Creates a FilePatch from the given FileDiff and determines the feature tags of each
+    /// change with the given function.
+    fn from_file_diff_with_tags(
+        file_diff: FileDiff,
+        feature_tags: impl Fn(&str) -> Vec<String>,
+    ) -> FilePatch {
+        // A diff without hunks (e.g., a git diff that only renames a file) yields a patch without
+        // changes
+        let mut changes = vec![];
+        let file_change_type = file_diff.change_type();
+        let mode_change = file_diff.mode_change();
+
+        // The changes are numbered consecutively across the hunks
+        let mut hunk_texts = Vec::with_capacity(file_diff.hunks().len());
+        let mut first_id = 0;
+        for hunk in file_diff.hunks() {
+            let change_count = hunk.lines().iter().filter(|l| l.is_change()).count();
+            hunk_texts.push(HunkText {
+                origin_ids: first_id..first_id + change_count,
+                text: hunk.to_string(),
+            });
+            first_id += change_count;
+        }
+
+        // Extract all changes from the file diff
+        for (change_id, line) in file_diff.into_changes().enumerate() {
+            let line_number;
+            let change_type;
+            match line.line_type() {
+                crate::diffs::LineType::Add => {
+                    change_type = LineChangeType::Add;
+                    // Lines that are added do not exist in the source file yet; therefore, they only
+                    // have a change location, but no real location
+                    line_number = line.source_line().change_location();
+                }
+                crate::diffs::LineType::Remove => {
+                    change_type = LineChangeType::Remove;
+                    // Lines that are removed must exist in the source file and must thus have a
                     // real location.
                     line_number = line.source_line().real_location();
                 }
                 _ => panic!("a change must always be an Add or Remove"),
             }
 
+            let line = line.into_original_text();
+            let feature_tags = feature_tags(&line);
             changes.push(Change {
-                line: line.into_original_text(),
+                line,
                 change_type,
                 line_number,
                 change_id,
+                origin_id: change_id,
+                feature_tags,
+                reject_reason: None,
+                occurrence: None,
             });
         }
 
-        FilePatch {
-            changes,
-            change_type: file_change_type,
-        }
+        FilePatch {
+            changes,
+            change_type: file_change_type,
+            mode_change,
+            hunk_texts,
+        }
+    }
+
+    /// Converts this patch back into a FileDiff for the given source file. As a FilePatch does
+    /// not retain the context of its changes, the hunks of the FileDiff are reconstructed from
+    /// the lines of the source file with the given number of context lines. The changes of the
+    /// patch must refer to the lines of the source file.
+    ///
+    /// The reconstructed FileDiff is equivalent to the FileDiff from which the patch was created
+    /// (i.e., both have the same normalized form, see `FileDiff::normalize_with_context`), but it
+    /// only contains the changes that are still part of the patch. Both file headers refer to the
+    /// path of the source file.
+    pub fn to_file_diff(&self, source: &FileArtifact, context: usize) -> FileDiff {
+        FileDiff::from_changes(source.path(), source.lines(), &self.changes, context)
+    }
+}
+
+/// Extracts all feature tags from the given line using the tag marker.
+#[cfg(feature = "regex")]
+fn extract_tags(line: &str, tag_marker: &Regex) -> Vec<String> {
+    tag_marker
+        .captures_iter(line)
+        .filter_map(|c| c.get(1).or_else(|| c.get(0)))
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+/// An aligned patch contains a vector of changes that were aligned for a specific target file.
+/// The patch holds ownership of the target FileArtifact and changes it during patch application.
+/// Applying the patch consumes it to prohibit mutliple applications of the same patch to the same
+/// file. An aligned patch also has a change type that describes whether the file is created,
+/// removed, or modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilteredPatch {
+    changes: Vec<Change>,
+    rejected_changes: Vec<Change>,
+    change_type: FileChangeType,
+    mode_change: Option<u32>,
+}
+
+impl FilteredPatch {
+    /// Returns a reference to the aligned changes of this patch.
+    pub fn changes(&self) -> &[Change] {
+        self.changes.as_ref()
+    }
+
+    /// Returns a reference to the rejects of the filtering process
+    pub fn rejected_changes(&self) -> &[Change] {
+        &self.rejected_changes
+    }
+}
+
+#[cfg(test)]
+impl FilteredPatch {
+    /// Creates a filtered patch for tests that modifies a file with the given changes, without
+    /// rejects or a mode change.
+    pub(crate) fn for_test(changes: Vec<Change>) -> FilteredPatch {
+        FilteredPatch {
+            changes,
+            rejected_changes: vec![],
+            change_type: FileChangeType::Modify,
+            mode_change: None,
+        }
+    }
+}
+
+impl Display for FilteredPatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.change_type,)
+    }
+}
+
+/// An aligned patch contains a vector of changes that were aligned for a specific target file.
+/// The patch holds ownership of the target FileArtifact and changes it during patch application.
+/// Applying the patch consumes it to prohibit mutliple applications of the same patch to the same
+/// file. An aligned patch also has a change type that describes whether the file is created,
+/// removed, or modified.
+///
+/// Adds that were aligned to the same target line but originate from different locations in the
+/// source file and differ in their content are reported as conflict sets. They are applied
+/// nonetheless; it is up to the caller to resolve the conflicts before the patch is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignedPatch {
+    changes: Vec<Change>,
+    rejected_changes: Vec<Change>,
+    target: FileArtifact,
+    change_type: FileChangeType,
+    mode_change: Option<u32>,
+    conflicts: Vec<ConflictSet>,
+    authored_order: Vec<Change>,
+}
+
+impl AlignedPatch {
+    /// Consumes and applies this patch to its target file using the given options. See
+    /// `apply_patch` for more information.
+    ///
+    /// ## Error
+    /// Returns an Error if the necessary file operations cannot be performed.
+    pub fn apply(self, options: &PatchOptions) -> Result<PatchOutcome, Error> {
+        apply_patch_with_options(self, options)
+    }
+
+    /// Consumes this patch and simulates its application with the default options, without
+    /// modifying any files. This is a shortcut for applying the patch with
+    /// `PatchOptions::dryrun` set.
+    ///
+    /// ## Error
+    /// Returns an Error if the necessary file operations cannot be performed.
+    pub fn apply_dryrun(self) -> Result<PatchOutcome, Error> {
+        self.apply(&PatchOptions::builder().dryrun(true).build())
+    }
+
+    /// Returns a reference to the aligned changes of this patch.
+    pub fn changes(&self) -> &[Change] {
+        self.changes.as_ref()
+    }
+
+    /// Returns the number of aligned changes in this patch. Rejected changes are not counted.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns true if this patch has no aligned changes, i.e., if applying it would not change
+    /// the target file. Rejected changes are not considered.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns a reference to the aligned changes in the order in which they appear in the diff.
+    /// In contrast to `changes`, which are ordered for their application, this order retains
+    /// the authored interleaving of adds and removes and is meant for displaying the patch. The
+    /// authored order is only retained if `AlignmentOptions::retain_authored_order` was set;
+    /// otherwise, it is empty.
+    pub fn authored_order(&self) -> &[Change] {
+        &self.authored_order
+    }
+
+    /// Returns a reference to the conflict sets that were detected during the alignment.
+    pub fn conflicts(&self) -> &[ConflictSet] {
+        &self.conflicts
+    }
+
+    /// Returns true if conflicting adds were detected during the alignment.
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+
+    /// Returns a reference to the target file artifact of this patch.
+    pub fn target(&self) -> &FileArtifact {
+        &self.target
+    }
+
+    /// Consumes this patch and returns a minimal patch that achieves the same result. To this
+    /// end, each remove that is immediately followed by an add of the identical line at the same
+    /// target line is cancelled out, as the add would restore the removed line. The cancelled
+    /// changes are neither applied nor rejected, and they are dropped from the authored order
+    /// and the conflict sets. Only modification patches are minimized.
+    ///
+    /// Note that the line in the target file is not compared with the cancelled remove, so that a
+    /// target line with unexpected content is kept instead of causing an error.
+    pub fn minimize(mut self) -> AlignedPatch {
+        if self.change_type != FileChangeType::Modify {
+            return self;
+        }
+        let mut cancelled_ids = vec![];
+        let mut changes = Vec::with_capacity(self.changes.len());
+        let mut remaining = self.changes.into_iter().peekable();
+        while let Some(change) = remaining.next() {
+            let restores_removed_line = |next: &Change| {
+                next.change_type == LineChangeType::Add
+                    && next.line_number == change.line_number
+                    && next.line == change.line
+            };
+            if change.change_type == LineChangeType::Remove
+                && remaining.peek().is_some_and(restores_removed_line)
+            {
+                let add = remaining.next().expect("there should be an add to cancel");
+                cancelled_ids.extend([change.change_id, add.change_id]);
+            } else {
+                changes.push(change);
+            }
+        }
+        self.changes = changes;
+
+        self.authored_order
+            .retain(|c| !cancelled_ids.contains(&c.change_id));
+        for conflict in &mut self.conflicts {
+            conflict.change_ids.retain(|id| !cancelled_ids.contains(id));
+        }
+        // A single remaining add is no longer in conflict
+        self.conflicts.retain(|c| c.change_ids.len() > 1);
+        self
+    }
+
+    /// Returns a human-readable dump of this patch for troubleshooting. The dump lists the change
+    /// type and target path, followed by all aligned changes with their target line numbers and
+    /// all rejected changes with the line numbers they had when they were rejected.
+    pub fn debug_dump(&self) -> String {
+        let mut dump = format!(
+            "{} {}\n",
+            self.change_type,
+            self.target.path().to_string_lossy()
+        );
+        dump.push_str(&format!("aligned changes ({}):\n", self.changes.len()));
+        for change in &self.changes {
+            dump.push_str(&format!(
+                "  [{}] line {}: {}",
+                change.change_id, change.line_number, change
+            ));
+        }
+        dump.push_str(&format!(
+            "rejected changes ({}):\n",
+            self.rejected_changes.len()
+        ));
+        for reject in &self.rejected_changes {
+            dump.push_str(&format!(
+                "  [{}] line {}: {}",
+                reject.change_id, reject.line_number, reject
+            ));
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+impl AlignedPatch {
+    /// Creates a patch for tests that modifies the given target with the given changes, without
+    /// rejects, conflicts, a mode change, or an authored order.
+    pub(crate) fn for_test(changes: Vec<Change>, target: FileArtifact) -> AlignedPatch {
+        AlignedPatch {
+            changes,
+            rejected_changes: vec![],
+            target,
+            change_type: FileChangeType::Modify,
+            mode_change: None,
+            conflicts: vec![],
+            authored_order: vec![],
+        }
+    }
+}
+
+/// A ConflictSet contains the ids of adds that were aligned to the same target line, but which
+/// originate from different locations in the source file and add different content. The order
+/// in which these adds should be applied, or whether all of them should be applied at all, is
+/// ambiguous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictSet {
+    target_line: usize,
+    change_ids: Vec<usize>,
+}
+
+impl ConflictSet {
+    /// Returns the target line number to which all adds in this set were aligned.
+    pub fn target_line(&self) -> usize {
+        self.target_line
+    }
+
+    /// Returns the ids of all conflicting adds in the order in which they are applied.
+    pub fn change_ids(&self) -> &[usize] {
+        &self.change_ids
+    }
+}
+
+impl Display for AlignedPatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.change_type,
+            self.target.path().to_string_lossy()
+        )
+    }
+}
+
+/// A patch outcome contains all information about a performed patch application. It contains the
+/// patched file artifact, which has already been written to disk if dryrun is disabled.
+/// Furthermore, it contains a vector of all rejected changes and the change type of the applied
+/// patch.
+///
+/// The outcomes for a dryrun of a patch and its real application are the same.  
+/// TODO: Should the outcome really still contain the FileArtifact? This might suggest that it
+/// should still be saved or edited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchOutcome {
+    patched_file: FileArtifact,
+    applied_changes: Vec<Change>,
+    rejected_changes: Vec<Change>,
+    change_type: FileChangeType,
+    mode_change: Option<u32>,
+    applied_change_offsets: Vec<(usize, usize)>,
+    matcher_description: Option<String>,
+    filter_description: Option<String>,
+}
+
+impl PatchOutcome {
+    /// Returns a reference to the patched file artifact.
+    pub fn patched_file(&self) -> &FileArtifact {
+        &self.patched_file
+    }
+
+    /// Returns a reference to the changes that have been applied to the patched file. The line
+    /// numbers of the applied changes refer to the lines of the target file before it was
+    /// patched.
+    pub fn applied_changes(&self) -> &[Change] {
+        &self.applied_changes
+    }
+
+    /// Returns a reference to the rejected changes.
+    pub fn rejected_changes(&self) -> &[Change] {
+        &self.rejected_changes
+    }
+
+    /// Returns the change type of the applied patch.
+    pub fn change_type(&self) -> FileChangeType {
+        self.change_type
+    }
+
+    /// Returns the permission bits that have been set for the patched file, if the patch changed
+    /// the mode of the file. In a dryrun, these are the permission bits that would have been set.
+    pub fn mode_change(&self) -> Option<u32> {
+        self.mode_change
+    }
+
+    /// Returns the byte offset range of each applied change in the content of the patched file
+    /// (see `FileArtifact`'s `Display` implementation); the ranges have the same order as the
+    /// applied changes. The range of an added line covers the line without its line ending. A
+    /// removed line is represented by an empty range at the position where it was removed.
+    pub fn applied_change_offsets(&self) -> &[(usize, usize)] {
+        &self.applied_change_offsets
+    }
+
+    /// Returns the description of the matcher that was used to align the patch (see
+    /// `Matcher::describe`). The description is only recorded by `apply_all_collect`; it is None
+    /// for patches that were applied individually.
+    pub fn matcher_description(&self) -> Option<&str> {
+        self.matcher_description.as_deref()
+    }
+
+    /// Returns the description of the filter that was applied to the patch (see
+    /// `Filter::describe`). Like the matcher description, it is only recorded by
+    /// `apply_all_collect`.
+    pub fn filter_description(&self) -> Option<&str> {
+        self.filter_description.as_deref()
+    }
+
+    /// Returns true if all changes of the patch have been applied (i.e., there are no rejects).
+    pub fn is_clean(&self) -> bool {
+        self.rejected_changes.is_empty()
+    }
+
+    /// Returns true if at least one change of the patch has been rejected.
+    pub fn had_rejects(&self) -> bool {
+        !self.is_clean()
+    }
+
+    /// Returns a unified diff of the target file before and after the patch application with
+    /// three lines of context. Created files are diffed against `/dev/null` as source; removed
+    /// files are diffed against `/dev/null` as target, and only the lines that were removed by
+    /// the patch are part of the diff. The diff is empty if the patch did not change the file.
+    pub fn as_unified_diff(&self) -> String {
+        let path = self.patched_file.path().to_string_lossy();
+        let (source_header, target_header) = match self.change_type {
+            FileChangeType::Create => ("/dev/null", path.as_ref()),
+            FileChangeType::Remove => (path.as_ref(), "/dev/null"),
+            FileChangeType::Modify => (path.as_ref(), path.as_ref()),
+        };
+        let to_text =
+            |lines: &[String]| -> String { lines.iter().map(|l| format!("{l}\n")).collect() };
+        let (before, after) = (
+            to_text(&self.lines_before_patch()),
+            to_text(self.patched_file.lines()),
+        );
+        TextDiff::from_lines(&before, &after)
+            .unified_diff()
+            .context_radius(3)
+            .header(source_header, target_header)
+            .to_string()
+    }
+
+    /// Returns a diff from the patched file to the file that would have resulted if the rejected
+    /// changes had been applied as well, so that the gap between both can be reviewed. Each
+    /// reject is placed at a best-guess location: rejects that were aligned to the target file
+    /// (e.g., removes whose target line differs) keep their location, while rejects without a
+    /// location in the target file are placed next to the preceding change of the patch. A
+    /// rejected remove whose line does not exist at its location in the patched file is shown as
+    /// a removal of a line that is inserted there; thus, such a diff does not apply to the
+    /// patched file.
+    ///
+    /// ## Returns
+    /// Returns None if there are no rejects or if the patch did not modify the file.
+    pub fn rejects_as_diff(&self) -> Option<FileDiff> {
+        if self.rejected_changes.is_empty() || self.change_type != FileChangeType::Modify {
+            return None;
+        }
+
+        // Determine the best-guess location of each reject in the target file before the patch
+        // application by walking all changes in the order of the patch
+        let mut all_changes: Vec<(&Change, bool)> = self
+            .applied_changes
+            .iter()
+            .map(|c| (c, false))
+            .chain(self.rejected_changes.iter().map(|c| (c, true)))
+            .collect();
+        all_changes.sort_by_key(|(c, _)| c.origin_id);
+        let line_count = self.lines_before_patch().len();
+        // The target line before which the next change is located, if known
+        let mut cursor: Option<usize> = None;
+        let mut guesses = vec![];
+        for (change, rejected) in all_changes {
+            let line_number = match change.reject_reason {
+                Some(RejectReason::NoMatch | RejectReason::Filtered) if rejected => {
+                    cursor.unwrap_or(change.line_number.clamp(1, line_count + 1))
+                }
+                _ => change.line_number,
+            };
+            cursor = Some(match change.change_type {
+                LineChangeType::Add => line_number,
+                LineChangeType::Remove => line_number + 1,
+            });
+            if rejected {
+                guesses.push((change, line_number));
+            }
+        }
+
+        // Convert the locations to the patched file, in which the applied changes are located
+        let mut guesses: Vec<(&Change, usize)> = guesses
+            .into_iter()
+            .map(|(change, line_number)| {
+                let shift = |change_type, shifts: fn(usize, usize) -> bool| {
+                    self.applied_changes
+                        .iter()
+                        .filter(|c| {
+                            c.change_type == change_type && shifts(c.line_number, line_number)
+                        })
+                        .count()
+                };
+                let adds = shift(LineChangeType::Add, |l, line_number| l <= line_number);
+                let removes = shift(LineChangeType::Remove, |l, line_number| l < line_number);
+                (change, line_number + adds - removes)
+            })
+            .collect();
+        guesses.sort_by_key(|(_, line_number)| *line_number);
+
+        // Rejected removes of lines that are not at their location are inserted into the lines
+        let mut lines = self.patched_file.lines().to_vec();
+        let mut inserted = 0;
+        let mut changes = Vec::with_capacity(guesses.len());
+        for (change, line_number) in guesses {
+            let line_number = (line_number + inserted).min(lines.len() + 1);
+            if change.change_type == LineChangeType::Remove
+                && lines.get(line_number - 1) != Some(&change.line)
+            {
+                lines.insert(line_number - 1, change.line.clone());
+                inserted += 1;
+            }
+            let mut change = change.clone();
+            change.line_number = line_number;
+            change.reject_reason = None;
+            changes.push(change);
+        }
+        Some(FileDiff::from_changes(
+            self.patched_file.path(),
+            &lines,
+            &changes,
+            3,
+        ))
+    }
+
+    /// Reconstructs the lines of the target file before the patch application from the patched
+    /// file and the applied changes.
+    fn lines_before_patch(&self) -> Vec<String> {
+        let changes_of_type = |change_type| {
+            self.applied_changes
+                .iter()
+                .filter(move |c| c.change_type == change_type)
+        };
+        match self.change_type {
+            FileChangeType::Create => return vec![],
+            FileChangeType::Remove => {
+                return changes_of_type(LineChangeType::Remove)
+                    .map(|c| c.line.clone())
+                    .collect()
+            }
+            FileChangeType::Modify => (),
+        }
+
+        // Revert the changes in the same order in which they have been applied
+        let line_count = self.patched_file.len() + changes_of_type(LineChangeType::Remove).count()
+            - changes_of_type(LineChangeType::Add).count();
+        let mut lines = Vec::with_capacity(line_count);
+        let mut patched_lines = self.patched_file.lines().iter();
+        let mut changes = self.applied_changes.iter().peekable();
+        loop {
+            let line_number = lines.len() + 1;
+            match changes.peek() {
+                // Adds after the last line are applied at the end, regardless of their number
+                Some(c)
+                    if c.change_type == LineChangeType::Add
+                        && (c.line_number <= line_number || line_number > line_count) =>
+                {
+                    patched_lines.next();
+                    changes.next();
+                }
+                Some(c)
+                    if c.change_type == LineChangeType::Remove && c.line_number == line_number =>
+                {
+                    lines.push(c.line.clone());
+                    changes.next();
+                }
+                _ => match patched_lines.next() {
+                    Some(line) => lines.push(line.clone()),
+                    None => break,
+                },
+            }
+        }
+        lines
+    }
+}
+
+/// Options that configure how a patch is applied to its target file. The options can be created
+/// with struct update syntax based on their `Default`, or with the builder returned by
+/// `PatchOptions::builder`.
+///
+/// # Examples
+/// ```
+/// use mpatch::{PatchOptions, PlacementStrategy};
+///
+/// let options = PatchOptions::builder()
+///     .dryrun(true)
+///     .placement(PlacementStrategy::Nearby(2))
+///     .build();
+/// assert!(options.dryrun);
+/// assert_eq!(None, options.add_line_indent);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatchOptions {
+    /// Whether the patch application is only simulated. If set, no files are created, modified,
+    /// or removed.
+    pub dryrun: bool,
+    /// An optional prefix that is prepended to the content of every added line. This can be used
+    /// to adjust the indentation of changes that are ported to a more deeply nested context.
+    pub add_line_indent: Option<String>,
+    /// The strategy that determines where a change is applied if the content of its anchored
+    /// target line does not match.
+    pub placement: PlacementStrategy,
+    /// Whether the source and target files are read lossily when patching a variant with
+    /// `apply_all_with_options` or `apply_all_collect`. If set, invalid UTF-8 sequences are replaced with the Unicode
+    /// replacement character (U+FFFD) instead of aborting the patch application with an error.
+    pub lossy_read: bool,
+    /// An optional directory to which the matching of each patched file is written when patching
+    /// a variant with `apply_all_with_options`. The matching is written as CSV (see
+    /// `Matching::to_csv`) to a file that is named after the path of the target file in the diff
+    /// with the additional extension `.csv`. This is meant for debugging the alignment.
+    pub dump_matching_dir: Option<PathBuf>,
+    /// The sink to which `apply_all_with_options` reports rejected changes. If no sink is given,
+    /// the rejects are written to the rejects file of the PatchPaths, or printed to stdout if no
+    /// rejects file has been specified.
+    pub reject_sink: Option<RejectSink>,
+    /// Whether `apply_all_with_options` creates the target directory if it does not exist. The
+    /// directory is only created if all file diffs in the diff create new files; otherwise, or if
+    /// this option is not set, patching a missing target directory fails with an error.
+    pub create_target_root: bool,
+    /// How a target file that is a symbolic link is handled when the patched file is written.
+    pub symlink_policy: SymlinkPolicy,
+    /// Whether removed lines that cannot be found in the target file are rejected instead of
+    /// aborting the application of a modification patch with an error. If set, a removed line
+    /// whose target line has different content or that lies past the end of the target file is
+    /// rejected with `RejectReason::LineMismatch` and the target line is kept; all other changes
+    /// are applied.
+    pub lenient_removes: bool,
+    /// Whether `apply_all_with_options` processes the file diffs sorted by the paths of their
+    /// target files instead of in the order of the diff. Removed files are sorted by the paths of
+    /// their source files. This makes printed reports and rejects files independent of the order
+    /// of the file diffs in the diff.
+    pub sort_files: bool,
+    /// How trailing whitespace in added lines of a modified file is handled.
+    pub whitespace: WhitespaceMode,
+    /// The maximum number of aligned patches that `apply_all_with_options` holds in memory while
+    /// they wait to be applied. If set, the patches are applied by a worker thread while the next
+    /// files are read and aligned; the alignment pauses whenever this many patches are queued.
+    /// This caps the memory used for diffs that touch a large number of files. If not set, each
+    /// file is aligned and applied before the next file is processed.
+    pub max_in_flight: Option<usize>,
+    /// An optional path to which `apply_all_with_options` writes a report with the unified diff
+    /// of every file that was changed by the patch (see `PatchOutcome::as_unified_diff`). The
+    /// report is also written in a dryrun.
+    pub report_file: Option<PathBuf>,
+    /// How `apply_all_with_options` handles several file diffs in the diff that change the same
+    /// file (see `VersionDiff::has_duplicate_targets`).
+    pub duplicate_targets: DuplicateTargetPolicy,
+    /// Whether `apply_all_with_options` resolves the paths of target files that do not exist
+    /// with the exact case in the diff by ignoring the case of the path, e.g., to find `foo.c`
+    /// for the path `Foo.c`. Paths that are ambiguous, because several files only differ in
+    /// case, cause an error.
+    pub case_insensitive_paths: bool,
+    /// Whether the lines added to a modified file are translated to the dominant line ending of
+    /// the target file (see `FileArtifact::line_ending`), e.g., to insert lines of a patch that
+    /// was authored with LF into a file with CRLF line endings. As a file artifact tracks a
+    /// single line ending, the patched file is then written with the line ending of the target
+    /// file. If not set, the patched file is written with LF line endings.
+    pub normalize_inserted_endings: bool,
+    /// Whether `apply_all_with_options` skips target files that are already in the state that
+    /// the patch would produce, i.e., that are identical to the source file after the patch. A
+    /// file that is to be removed is in this state if it does not exist. Skipped files are
+    /// reported as unchanged and have no rejects.
+    pub skip_already_applied: bool,
+    /// The expected content hashes of source files (see `FileArtifact::content_hash`), keyed by
+    /// the path of the source file relative to the source directory (i.e., the path in the diff
+    /// after stripping). Before aligning a patch, `apply_all_with_options` compares the hash of
+    /// each source file that has an expected hash with it and fails if they differ, because a
+    /// source file that has been modified since the diff was created makes the alignment
+    /// unreliable. Source files without an expected hash are not checked.
+    pub expected_source_hashes: HashMap<PathBuf, String>,
+    /// The maximum number of line edits that `apply_all_with_options` applies across all files.
+    /// Before a file is patched, its edits are counted by simulating the patch application; if
+    /// they would exceed the maximum, the patch application is aborted with an error that states
+    /// how many edits have been applied to how many files. The files that have been patched up
+    /// to this point remain patched.
+    pub max_total_edits: Option<usize>,
+    /// Whether printed rejects are grouped by their content across files (see `group_rejects`).
+    /// If set, the rejects are not printed file by file, but once all files have been patched,
+    /// with one line per group that lists the files in which the change was rejected. Only
+    /// affects rejects that are printed (see `RejectSink::Print`).
+    pub group_rejects: bool,
+    /// The prefixes that start a trailing comment, e.g., `//` or `#`. When a line is removed, a
+    /// trailing comment that starts with one of the prefixes is ignored when the target line is
+    /// compared to the removed line, so that a comment that only exists in the target does not
+    /// block the removal. The comment must follow code on the same line; lines that only consist
+    /// of a comment are compared as usual. The removed line is removed together with its comment.
+    pub comment_prefixes: Vec<String>,
+    /// Whether `apply_all_with_options` skips the hunks of the diff that only change whitespace
+    /// (see `Hunk::is_whitespace_only`), e.g., to port a patch to a variant that has been
+    /// reformatted. File diffs whose hunks are all skipped are not applied at all.
+    pub skip_whitespace_hunks: bool,
+    /// A flag with which a running `apply_all_with_options` can be cancelled, e.g., from another
+    /// thread. The flag is checked before each file is aligned and before it is patched; once it
+    /// is set, the patch application stops with an error of kind `ErrorKind::Cancelled`. The
+    /// files that have been patched up to this point remain patched.
+    pub cancel_flag: Option<CancelFlag>,
+    /// Whether a patched file ends with a newline. By default, patched and created files are
+    /// written without a final newline.
+    pub final_newline: FinalNewline,
+}
+
+/// A flag that cancels a patch application once it is set (see `PatchOptions::cancel_flag`).
+/// Clones of a CancelFlag share the same flag; two flags are equal if they share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    /// Creates a new CancelFlag that is not set.
+    pub fn new() -> CancelFlag {
+        CancelFlag::default()
+    }
+
+    /// Sets the flag, so that the patch applications that check it are cancelled.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if the flag has been set.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelFlag {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancelFlag(flag)
+    }
+}
+
+impl PartialEq for CancelFlag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelFlag {}
+
+/// A DuplicateTargetPolicy determines how several file diffs in a diff that change the same file
+/// are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicateTargetPolicy {
+    /// The file diffs are applied one after another, each to the target file as it is on disk.
+    #[default]
+    Allow,
+    /// The patch application fails with an error before any file is patched.
+    Error,
+    /// The patches of the file diffs are chained into a single patch (see `FilePatch::chain`),
+    /// which is applied in place of the first file diff. Each later file diff refers to the file
+    /// as it is after the earlier file diffs, as in a concatenation of diffs. Chaining patches
+    /// with different change types fails with an error.
+    Merge,
+}
+
+/// A WhitespaceMode determines how added lines with trailing whitespace are handled, similar to
+/// the `--whitespace` option of `git apply`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WhitespaceMode {
+    /// Trailing whitespace is ignored.
+    #[default]
+    #[value(name = "nowarn")]
+    NoWarn,
+    /// A warning is printed to stderr for each added line with trailing whitespace; the line is
+    /// added unchanged.
+    Warn,
+    /// Trailing whitespace is removed from added lines.
+    Fix,
+    /// Added lines with trailing whitespace are rejected.
+    Error,
+}
+
+/// A FinalNewline determines whether the last line of a patched file is followed by a line ending,
+/// independently of whether content has been appended to the end of the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FinalNewline {
+    /// A modified file ends with a newline if the target file did before the patch application.
+    /// Created files are written without a final newline.
+    Preserve,
+    /// The patched file always ends with a newline.
+    Always,
+    /// The patched file never ends with a newline.
+    #[default]
+    Never,
+}
+
+impl FinalNewline {
+    /// Returns true if a patched file ends with a newline under this setting, given whether the
+    /// original target file ended with a newline.
+    pub fn ends_with_newline(&self, target_has_final_newline: bool) -> bool {
+        match self {
+            FinalNewline::Preserve => target_has_final_newline,
+            FinalNewline::Always => true,
+            FinalNewline::Never => false,
+        }
+    }
+}
+
+/// A SymlinkPolicy determines how a patch is applied to a target file that is a symbolic link.
+/// The policy only affects modified files; removing a symbolic link always removes the link itself
+/// and not its destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// The link is followed and the patched content is written to the link's destination.
+    #[default]
+    Follow,
+    /// Patching a symbolic link fails with an error, even in a dryrun.
+    Refuse,
+    /// The link is replaced with a regular file with the patched content. The link's destination
+    /// is not modified.
+    Replace,
+}
+
+impl PatchOptions {
+    /// Returns a builder for PatchOptions that starts with the default options.
+    pub fn builder() -> PatchOptionsBuilder {
+        PatchOptionsBuilder::default()
+    }
+}
+
+/// A builder for PatchOptions. See the fields of PatchOptions for a description of each option.
+#[derive(Debug, Clone, Default)]
+pub struct PatchOptionsBuilder {
+    options: PatchOptions,
+}
+
+impl PatchOptionsBuilder {
+    /// Sets whether the patch application is only simulated.
+    pub fn dryrun(mut self, dryrun: bool) -> Self {
+        self.options.dryrun = dryrun;
+        self
+    }
+
+    /// Sets the prefix that is prepended to the content of every added line.
+    pub fn add_line_indent(mut self, indent: impl Into<String>) -> Self {
+        self.options.add_line_indent = Some(indent.into());
+        self
+    }
+
+    /// Sets the strategy that determines where a change is applied.
+    pub fn placement(mut self, placement: PlacementStrategy) -> Self {
+        self.options.placement = placement;
+        self
+    }
+
+    /// Sets whether the source and target files are read lossily.
+    pub fn lossy_read(mut self, lossy_read: bool) -> Self {
+        self.options.lossy_read = lossy_read;
+        self
+    }
+
+    /// Sets the directory to which the matching of each patched file is written.
+    pub fn dump_matching_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.dump_matching_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the sink to which rejected changes are reported.
+    pub fn reject_sink(mut self, reject_sink: RejectSink) -> Self {
+        self.options.reject_sink = Some(reject_sink);
+        self
+    }
+
+    /// Sets whether a missing target directory is created for diffs that only create files.
+    pub fn create_target_root(mut self, create_target_root: bool) -> Self {
+        self.options.create_target_root = create_target_root;
+        self
+    }
+
+    /// Sets how a target file that is a symbolic link is handled.
+    pub fn symlink_policy(mut self, symlink_policy: SymlinkPolicy) -> Self {
+        self.options.symlink_policy = symlink_policy;
+        self
+    }
+
+    /// Sets whether removed lines that cannot be found in the target file are rejected.
+    pub fn lenient_removes(mut self, lenient_removes: bool) -> Self {
+        self.options.lenient_removes = lenient_removes;
+        self
+    }
+
+    /// Sets whether the file diffs are processed sorted by the paths of their target files.
+    pub fn sort_files(mut self, sort_files: bool) -> Self {
+        self.options.sort_files = sort_files;
+        self
+    }
+
+    /// Sets how trailing whitespace in added lines is handled.
+    pub fn whitespace(mut self, whitespace: WhitespaceMode) -> Self {
+        self.options.whitespace = whitespace;
+        self
+    }
+
+    /// Sets the maximum number of aligned patches that wait to be applied.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.options.max_in_flight = Some(max_in_flight);
+        self
     }
-}
 
-/// An aligned patch contains a vector of changes that were aligned for a specific target file.
-/// The patch holds ownership of the target FileArtifact and changes it during patch application.
-/// Applying the patch consumes it to prohibit mutliple applications of the same patch to the same
-/// file. An aligned patch also has a change type that describes whether the file is created,
-/// removed, or modified.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FilteredPatch {
-    changes: Vec<Change>,
-    rejected_changes: Vec<Change>,
-    change_type: FileChangeType,
-}
+    /// Sets the path to which the report of all changed files is written.
+    pub fn report_file(mut self, report_file: impl Into<PathBuf>) -> Self {
+        self.options.report_file = Some(report_file.into());
+        self
+    }
 
-impl FilteredPatch {
-    /// Returns a reference to the aligned changes of this patch.
-    pub fn changes(&self) -> &[Change] {
-        self.changes.as_ref()
+    /// Sets how several file diffs that change the same file are handled.
+    pub fn duplicate_targets(mut self, duplicate_targets: DuplicateTargetPolicy) -> Self {
+        self.options.duplicate_targets = duplicate_targets;
+        self
     }
 
-    /// Returns a reference to the rejects of the filtering process
-    pub fn rejected_changes(&self) -> &[Change] {
-        &self.rejected_changes
+    /// Sets whether the paths of target files are resolved case-insensitively.
+    pub fn case_insensitive_paths(mut self, case_insensitive_paths: bool) -> Self {
+        self.options.case_insensitive_paths = case_insensitive_paths;
+        self
     }
-}
 
-impl Display for FilteredPatch {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.change_type,)
+    /// Sets whether added lines are translated to the line ending of the target file.
+    pub fn normalize_inserted_endings(mut self, normalize_inserted_endings: bool) -> Self {
+        self.options.normalize_inserted_endings = normalize_inserted_endings;
+        self
     }
-}
 
-/// An aligned patch contains a vector of changes that were aligned for a specific target file.
-/// The patch holds ownership of the target FileArtifact and changes it during patch application.
-/// Applying the patch consumes it to prohibit mutliple applications of the same patch to the same
-/// file. An aligned patch also has a change type that describes whether the file is created,
-/// removed, or modified.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct AlignedPatch {
-    changes: Vec<Change>,
-    rejected_changes: Vec<Change>,
-    target: FileArtifact,
-    change_type: FileChangeType,
-}
+    /// Sets whether target files that are already in the patched state are skipped.
+    pub fn skip_already_applied(mut self, skip_already_applied: bool) -> Self {
+        self.options.skip_already_applied = skip_already_applied;
+        self
+    }
 
-impl AlignedPatch {
-    /// Returns a reference to the aligned changes of this patch.
-    pub fn changes(&self) -> &[Change] {
-        self.changes.as_ref()
+    /// Adds the expected content hash of the source file under the given path, relative to the
+    /// source directory.
+    pub fn expected_source_hash(
+        mut self,
+        source_path: impl Into<PathBuf>,
+        expected_source_hash: impl Into<String>,
+    ) -> Self {
+        self.options
+            .expected_source_hashes
+            .insert(source_path.into(), expected_source_hash.into());
+        self
     }
 
-    /// Returns a reference to the target file artifact of this patch.
-    pub fn target(&self) -> &FileArtifact {
-        &self.target
+    /// Sets the maximum number of line edits that are applied across all files.
+    pub fn max_total_edits(mut self, max_total_edits: usize) -> Self {
+        self.options.max_total_edits = Some(max_total_edits);
+        self
     }
-}
 
-impl Display for AlignedPatch {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} {}",
-            self.change_type,
-            self.target.path().to_string_lossy()
-        )
+    /// Sets whether printed rejects are grouped by their content across files.
+    pub fn group_rejects(mut self, group_rejects: bool) -> Self {
+        self.options.group_rejects = group_rejects;
+        self
     }
-}
 
-/// A patch outcome contains all information about a performed patch application. It contains the
-/// patched file artifact, which has already been written to disk if dryrun is disabled.
-/// Furthermore, it contains a vector of all rejected changes and the change type of the applied
-/// patch.
-///
-/// The outcomes for a dryrun of a patch and its real application are the same.  
-/// TODO: Should the outcome really still contain the FileArtifact? This might suggest that it
-/// should still be saved or edited.
-pub struct PatchOutcome {
-    patched_file: FileArtifact,
-    rejected_changes: Vec<Change>,
-    change_type: FileChangeType,
-}
+    /// Sets the prefixes that start a trailing comment that is ignored when removing lines.
+    pub fn comment_prefixes<S: Into<String>>(
+        mut self,
+        comment_prefixes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options.comment_prefixes = comment_prefixes.into_iter().map(Into::into).collect();
+        self
+    }
 
-impl PatchOutcome {
-    /// Returns a reference to the patched file artifact.
-    pub fn patched_file(&self) -> &FileArtifact {
-        &self.patched_file
+    /// Sets whether hunks that only change whitespace are skipped.
+    pub fn skip_whitespace_hunks(mut self, skip_whitespace_hunks: bool) -> Self {
+        self.options.skip_whitespace_hunks = skip_whitespace_hunks;
+        self
     }
 
-    /// Returns a reference to the rejected changes.
-    pub fn rejected_changes(&self) -> &[Change] {
-        &self.rejected_changes
+    /// Sets the flag with which the patch application can be cancelled.
+    pub fn cancel_flag(mut self, cancel_flag: impl Into<CancelFlag>) -> Self {
+        self.options.cancel_flag = Some(cancel_flag.into());
+        self
     }
 
-    /// Returns the change type of the applied patch.
-    pub fn change_type(&self) -> FileChangeType {
-        self.change_type
+    /// Sets whether a patched file ends with a newline.
+    pub fn final_newline(mut self, final_newline: FinalNewline) -> Self {
+        self.options.final_newline = final_newline;
+        self
+    }
+
+    /// Returns the built PatchOptions.
+    pub fn build(self) -> PatchOptions {
+        self.options
     }
 }
 
+/// A PlacementStrategy determines how changes are placed in the target file if the line at the
+/// location determined during alignment does not have the expected content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlacementStrategy {
+    /// Changes are applied exactly at the location determined during alignment.
+    #[default]
+    Exact,
+    /// A removed line whose anchored target line has different content is relocated to the
+    /// closest line with the exact same content, if that line lies within the given number of
+    /// lines. This handles lines that appear multiple times in the target file and for which the
+    /// alignment chose the wrong instance.
+    Nearby(usize),
+}
+
 /// A change represent a single line change (i.e., adding or removing a line of text).
 /// Each change has a content, a change type, a line number, and a change id.
 ///
 /// The change id is used to identify a change among all changes of a patch which was originally
-/// created from a diff. Here, the changes in a diff are given ids from 0 to n-1.
+/// created from a diff. Here, the changes in a diff are given ids from 0 to n-1. The change id
+/// may be reassigned when patches are combined (see `FilePatch::merge`). In contrast, the origin
+/// id is set once when the change is created from a diff and is preserved by all subsequent
+/// stages (i.e., filtering, alignment, and application), so that a change can be correlated across
+/// them.
+///
+/// A change can additionally carry feature tags that were extracted from its content (see
+/// `FilePatch::from_file_diff`). Rejected changes carry the reason for their rejection.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Change {
     line: String,
     change_type: LineChangeType,
     line_number: usize,
     change_id: usize,
+    origin_id: usize,
+    feature_tags: Vec<String>,
+    reject_reason: Option<RejectReason>,
+    occurrence: Option<usize>,
 }
 
 impl Change {
@@ -341,6 +2727,124 @@ impl Change {
     pub fn change_id(&self) -> usize {
         self.change_id
     }
+
+    /// Returns the id that this change was given when it was created from a diff. In contrast to
+    /// the change id, the origin id is never reassigned.
+    pub fn origin_id(&self) -> usize {
+        self.origin_id
+    }
+
+    /// Returns the feature tags of this change. The tags are empty if no tags were extracted
+    /// when the patch was created.
+    pub fn feature_tags(&self) -> &[String] {
+        &self.feature_tags
+    }
+
+    /// Returns the reason why this change has been rejected, or None if it has not been rejected.
+    pub fn reject_reason(&self) -> Option<RejectReason> {
+        self.reject_reason
+    }
+
+    /// Returns the occurrence of the removed line in the target file that this change removes, if
+    /// it has been annotated with one (see `with_occurrence`).
+    pub fn occurrence(&self) -> Option<usize> {
+        self.occurrence
+    }
+
+    /// Annotates this change with the occurrence of its line that is to be removed, if the line
+    /// occurs several times in the target file. The occurrence is counted from 1, from top to
+    /// bottom, among the lines that are at most the distance of `PlacementStrategy::Nearby` away
+    /// from the line determined during alignment, or in the entire target file for
+    /// `PlacementStrategy::Exact`. Adds ignore the occurrence.
+    pub fn with_occurrence(mut self, occurrence: usize) -> Change {
+        self.occurrence = Some(occurrence);
+        self
+    }
+
+    /// Returns the content of this change prefixed with the symbol of its change type (i.e., `+`
+    /// or `-`) as in a diff. In contrast to the `Display` implementation, the returned line has no
+    /// trailing newline, so that it can be embedded in other text.
+    pub fn to_marked_line(&self) -> String {
+        match self.change_type {
+            LineChangeType::Add => format!("+{}", self.line),
+            LineChangeType::Remove => format!("-{}", self.line),
+        }
+    }
+
+    /// Consumes this change and returns it marked as rejected for the given reason.
+    pub(crate) fn into_reject(mut self, reason: RejectReason) -> Change {
+        self.reject_reason = Some(reason);
+        self
+    }
+}
+
+#[cfg(test)]
+impl Change {
+    /// Creates an add of the given line before the given line number for tests. The change id is
+    /// also used as origin id.
+    pub(crate) fn test_add(line: &str, line_number: usize, change_id: usize) -> Change {
+        Change::for_test(line, LineChangeType::Add, line_number, change_id)
+    }
+
+    /// Creates a remove of the given line at the given line number for tests. The change id is also
+    /// used as origin id.
+    pub(crate) fn test_remove(line: &str, line_number: usize, change_id: usize) -> Change {
+        Change::for_test(line, LineChangeType::Remove, line_number, change_id)
+    }
+
+    /// Creates a change of the given type for tests. The change id is also used as origin id.
+    pub(crate) fn for_test(
+        line: &str,
+        change_type: LineChangeType,
+        line_number: usize,
+        change_id: usize,
+    ) -> Change {
+        Change {
+            line: line.to_string(),
+            change_type,
+            line_number,
+            change_id,
+            origin_id: change_id,
+            feature_tags: vec![],
+            reject_reason: None,
+            occurrence: None,
+        }
+    }
+}
+
+/// The reason why a change has been rejected.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RejectReason {
+    /// The change has been removed from the patch by a filter.
+    Filtered,
+    /// No suitable location for the change has been found in the target file.
+    NoMatch,
+    /// The file that is to be created already exists.
+    TargetExists,
+    /// The file that is to be modified or removed does not exist.
+    TargetMissing,
+    /// The line that is to be removed does not exist in the target file or has different content.
+    LineMismatch,
+    /// The line that is to be added has trailing whitespace (see `WhitespaceMode::Error`).
+    Whitespace,
+    /// The change collides with a different change of another patch that is applied together
+    /// with it (see `application::apply_many`).
+    Conflict,
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::Filtered => write!(f, "filtered"),
+            RejectReason::NoMatch => write!(f, "no matching location in target file"),
+            RejectReason::TargetExists => write!(f, "target file already exists"),
+            RejectReason::TargetMissing => write!(f, "target file does not exist"),
+            RejectReason::LineMismatch => write!(f, "removed line not found in target file"),
+            RejectReason::Whitespace => write!(f, "trailing whitespace in added line"),
+            RejectReason::Conflict => write!(f, "conflicting change of another patch"),
+        }
+    }
 }
 
 impl PartialOrd for Change {
@@ -368,20 +2872,28 @@ impl Ord for Change {
 
 impl Display for Change {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.change_type {
-            LineChangeType::Add => writeln!(f, "+{}", self.line),
-            LineChangeType::Remove => writeln!(f, "-{}", self.line),
-        }
+        writeln!(f, "{}", self.to_marked_line())
     }
 }
 
 /// Enum representing the two possible change types for a line: Add and Remove.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum LineChangeType {
     Add,
     Remove,
 }
 
+impl LineChangeType {
+    /// Returns the opposite change type (i.e., Remove for Add and Add for Remove).
+    pub fn opposite(self) -> LineChangeType {
+        match self {
+            LineChangeType::Add => LineChangeType::Remove,
+            LineChangeType::Remove => LineChangeType::Add,
+        }
+    }
+}
+
 impl PartialOrd for LineChangeType {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -425,10 +2937,24 @@ impl Display for FileChangeType {
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
 
-    use crate::diffs::VersionDiff;
+    #[cfg(feature = "regex")]
+    use regex::Regex;
 
-    use super::{Change, FilePatch, LineChangeType};
+    use crate::{
+        diffs::VersionDiff,
+        io::{DiskFileStore, FileStore},
+        ConfigurationFilter, Error, ErrorKind, FileArtifact, KeepAllFilter, LCSMatcher,
+        TargetConfiguration,
+    };
+
+    use super::{
+        AlignedPatch, Change, ConflictSet, DuplicateTargetPolicy, FileChangeType, FilePatch,
+        FilePaths, FinalNewline, LineChangeType, MergeConflict, PatchOptions, PatchOutcome,
+        PlacementStrategy, SymlinkPolicy, WhitespaceMode,
+    };
 
     #[test]
     fn patch_from_diff() {
@@ -436,36 +2962,15 @@ mod tests {
         let file_diff = file_diff.file_diffs().first().unwrap().clone();
 
         let expected_changes = [
-            Change {
-                line: "REMOVED".to_string(),
-                change_type: LineChangeType::Remove,
-                line_number: 4,
-                change_id: 0,
-            },
-            Change {
-                line: "ADDED".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 5,
-                change_id: 1,
-            },
-            Change {
-                line: "REMOVED".to_string(),
-                change_type: LineChangeType::Remove,
-                line_number: 26,
-                change_id: 2,
-            },
-            Change {
-                line: "ADDED".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 27,
-                change_id: 3,
-            },
+            Change::test_remove("REMOVED", 4, 0),
+            Change::test_add("ADDED", 5, 1),
+            Change::test_remove("REMOVED", 26, 2),
+            Change::test_add("ADDED", 27, 3),
         ];
 
         let patch = FilePatch::from(file_diff);
 
-        for (change, expected_change) in patch.changes.into_iter().zip(expected_changes.into_iter())
-        {
+        for (change, expected_change) in patch.changes.into_iter().zip(expected_changes) {
             assert_eq!(change, expected_change);
         }
     }
@@ -473,18 +2978,8 @@ mod tests {
     #[test]
     fn order_changes_by_id_as_last_resort() {
         let mut changes = [
-            Change {
-                line: "second line".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 1,
-                change_id: 1,
-            },
-            Change {
-                line: "first line".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 1,
-                change_id: 0,
-            },
+            Change::test_add("second line", 1, 1),
+            Change::test_add("first line", 1, 0),
         ];
 
         changes.sort();
@@ -520,4 +3015,497 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn aligned_patch_debug_dump() {
+        let patch = AlignedPatch {
+            rejected_changes: vec![Change::test_remove("rejected line", 3, 2)],
+            ..AlignedPatch::for_test(
+                vec![
+                    Change::test_remove("removed line", 7, 0),
+                    Change::test_add("added line", 12, 1),
+                ],
+                FileArtifact::new(PathBuf::from("target.c")),
+            )
+        };
+
+        let dump = patch.debug_dump();
+        assert!(dump.starts_with("Modify target.c\n"));
+        assert!(dump.contains("[0] line 7: -removed line\n"));
+        assert!(dump.contains("[1] line 12: +added line\n"));
+        assert!(dump.contains("rejected changes (1):\n  [2] line 3: -rejected line\n"));
+    }
+
+    fn change(line: &str, change_type: LineChangeType, line_number: usize) -> Change {
+        Change::for_test(line, change_type, line_number, 0)
+    }
+
+    #[test]
+    fn count_changes_of_patches() {
+        let patch = FilePatch::for_test(vec![]);
+        assert_eq!(0, patch.len());
+        assert!(patch.is_empty());
+
+        let patch = FilePatch::for_test(vec![
+            change("removed", LineChangeType::Remove, 3),
+            change("added", LineChangeType::Add, 4),
+        ]);
+        assert_eq!(2, patch.len());
+        assert!(!patch.is_empty());
+
+        let mut aligned = AlignedPatch {
+            rejected_changes: vec![change("rejected", LineChangeType::Remove, 3)],
+            ..AlignedPatch::for_test(vec![], FileArtifact::new(PathBuf::from("target.c")))
+        };
+        // Rejected changes are not counted
+        assert_eq!(0, aligned.len());
+        assert!(aligned.is_empty());
+
+        aligned.changes = vec![change("added", LineChangeType::Add, 4)];
+        assert_eq!(1, aligned.len());
+        assert!(!aligned.is_empty());
+    }
+
+    #[test]
+    fn minimize_aligned_patch() {
+        let mut remove = change("b", LineChangeType::Remove, 2);
+        let mut restore = change("b", LineChangeType::Add, 2);
+        let mut add = change("x", LineChangeType::Add, 3);
+        (remove.change_id, restore.change_id, add.change_id) = (0, 1, 2);
+        let patch = AlignedPatch {
+            conflicts: vec![ConflictSet {
+                target_line: 2,
+                change_ids: vec![1, 2],
+            }],
+            ..AlignedPatch::for_test(
+                vec![remove, restore, add.clone()],
+                FileArtifact::from_lines(
+                    PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+                    vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                ),
+            )
+        };
+
+        let minimized = patch.clone().minimize();
+        assert_eq!(&[add], minimized.changes());
+        assert!(minimized.conflicts().is_empty());
+        assert_eq!(
+            patch.apply_dryrun().unwrap().patched_file(),
+            minimized.apply_dryrun().unwrap().patched_file()
+        );
+    }
+
+    #[test]
+    fn merge_patches() {
+        let patch = FilePatch::for_test(vec![
+            change("removed", LineChangeType::Remove, 3),
+            change("added", LineChangeType::Add, 4),
+            change("shared", LineChangeType::Add, 10),
+        ]);
+        let other = FilePatch::for_test(vec![
+            change("first", LineChangeType::Add, 1),
+            change("removed", LineChangeType::Remove, 3),
+            change("shared", LineChangeType::Add, 10),
+        ]);
+
+        let merged = patch.merge(other).unwrap();
+        let lines: Vec<(&str, usize, usize)> = merged
+            .changes()
+            .iter()
+            .map(|c| (c.line(), c.line_number(), c.change_id()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("first", 1, 0),
+                ("removed", 3, 1),
+                ("added", 4, 2),
+                ("shared", 10, 3)
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn merge_conflicting_patches() {
+        let patch = FilePatch::for_test(vec![
+            change("removed", LineChangeType::Remove, 3),
+            change("ours", LineChangeType::Add, 4),
+        ]);
+        let other = FilePatch::for_test(vec![
+            change("removed", LineChangeType::Remove, 3),
+            change("theirs", LineChangeType::Add, 4),
+        ]);
+
+        match patch.merge(other).unwrap_err() {
+            MergeConflict::Lines {
+                line_number,
+                changes,
+                other_changes,
+            } => {
+                assert_eq!(4, line_number);
+                assert_eq!("ours", changes[0].line());
+                assert_eq!("theirs", other_changes[0].line());
+            }
+            conflict => panic!("unexpected conflict: {conflict}"),
+        }
+    }
+
+    #[test]
+    fn chain_patches() {
+        // Adds a line before line 2 of "a b c d"
+        let patch = FilePatch::for_test(vec![change("x", LineChangeType::Add, 2)]);
+        // Replaces "c" in "a x b c d" and removes the added "x"
+        let later = FilePatch::for_test(vec![
+            change("x", LineChangeType::Remove, 2),
+            change("c", LineChangeType::Remove, 4),
+            change("C", LineChangeType::Add, 5),
+        ]);
+
+        let chained = patch.chain(later).unwrap();
+        let lines: Vec<(&str, LineChangeType, usize, usize)> = chained
+            .changes()
+            .iter()
+            .map(|c| (c.line(), c.change_type(), c.line_number(), c.change_id()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("c", LineChangeType::Remove, 3, 0),
+                ("C", LineChangeType::Add, 4, 1)
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn merge_patches_with_different_change_types() {
+        let patch = FilePatch::for_test(vec![]);
+        let other = FilePatch {
+            change_type: FileChangeType::Remove,
+            ..FilePatch::for_test(vec![])
+        };
+        assert_eq!(
+            MergeConflict::ChangeType(FileChangeType::Modify, FileChangeType::Remove),
+            patch.merge(other).unwrap_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn extract_feature_tags() {
+        let diff = VersionDiff::try_from(
+            "diff -Naur version-0/main.c version-1/main.c
+--- version-0/main.c\t2024-05-17 15:52:33.083273519 +0200
++++ version-1/main.c\t2024-05-17 15:53:35.893272901 +0200
+@@ -1,2 +1,3 @@
+ int main() {
++  log(); // #feature:LOGGING #feature:DEBUG
+ }
+"
+            .to_string(),
+        )
+        .unwrap();
+        let file_diff = diff.file_diffs().first().unwrap().clone();
+        let tag_marker = Regex::new(r"#feature:(\w+)").unwrap();
+
+        let patch = FilePatch::from_file_diff(file_diff.clone(), Some(&tag_marker));
+        assert_eq!(&["LOGGING", "DEBUG"], patch.changes()[0].feature_tags());
+
+        // Without a marker, no tags are extracted
+        let patch = FilePatch::from(file_diff);
+        assert!(patch.changes()[0].feature_tags().is_empty());
+    }
+
+    #[test]
+    fn mark_lines_without_newline() {
+        let add = change("added();", LineChangeType::Add, 1);
+        let remove = change("removed();", LineChangeType::Remove, 1);
+        assert_eq!("+added();", add.to_marked_line());
+        assert_eq!("-removed();", remove.to_marked_line());
+        assert!(!add.to_marked_line().ends_with('\n'));
+        // The Display implementation still ends with a newline
+        assert_eq!("+added();\n", add.to_string());
+    }
+
+    #[test]
+    fn map_changes_to_hunk_texts() {
+        let diff = VersionDiff::read("tests/diffs/base_patch.diff").unwrap();
+        // The diff of long.txt has two hunks with two changes each
+        let file_diff = diff.file_diffs()[2].clone();
+        let hunks = file_diff.hunks().to_vec();
+        let patch = FilePatch::from(file_diff);
+
+        let first_hunk = hunks[0].to_string();
+        let second_hunk = hunks[1].to_string();
+        assert_eq!(Some(first_hunk.as_str()), patch.hunk_text_for(0));
+        assert_eq!(Some(first_hunk.as_str()), patch.hunk_text_for(1));
+        assert_eq!(Some(second_hunk.as_str()), patch.hunk_text_for(2));
+        assert_eq!(Some(second_hunk.as_str()), patch.hunk_text_for(3));
+        assert_eq!(None, patch.hunk_text_for(4));
+
+        let merged = patch.clone().merge(patch).unwrap();
+        assert_eq!(None, merged.hunk_text_for(0));
+    }
+
+    #[test]
+    fn clean_and_rejecting_outcomes() {
+        let clean = PatchOutcome {
+            patched_file: FileArtifact::new(PathBuf::from("clean")),
+            applied_changes: vec![],
+            rejected_changes: vec![],
+            change_type: FileChangeType::Modify,
+            mode_change: None,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        };
+        assert!(clean.is_clean());
+        assert!(!clean.had_rejects());
+
+        let rejecting = PatchOutcome {
+            patched_file: FileArtifact::new(PathBuf::from("rejecting")),
+            applied_changes: vec![],
+            rejected_changes: vec![Change::test_add("rejected", 1, 0)],
+            change_type: FileChangeType::Modify,
+            mode_change: None,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        };
+        assert!(!rejecting.is_clean());
+        assert!(rejecting.had_rejects());
+    }
+
+    /// A FileStore that counts how often each file has been read.
+    #[derive(Default)]
+    struct CountingFileStore {
+        reads: Vec<PathBuf>,
+    }
+
+    impl FileStore for CountingFileStore {
+        fn read_or_create_empty(&mut self, path: PathBuf) -> Result<FileArtifact, Error> {
+            self.reads.push(path.clone());
+            DiskFileStore::new(false).read_or_create_empty(path)
+        }
+    }
+
+    #[test]
+    fn skip_reading_files_of_filtered_patch() {
+        let diff = VersionDiff::read("tests/collect/diffs/collect.diff").unwrap();
+        let file_diff = diff.file_diffs()[0].clone();
+        let file_paths = || FilePaths {
+            source: PathBuf::from("tests/collect/source_variant/version-0/first.c"),
+            alternative_sources: vec![],
+            target: PathBuf::from("tests/collect/target_variant/version-0/first.c"),
+            matching_dump: None,
+        };
+
+        // A filter that rejects all changes
+        let mut filter = ConfigurationFilter::new(
+            |_: &Change, _: &TargetConfiguration| false,
+            TargetConfiguration::default(),
+        );
+        let options = PatchOptions::default();
+        let mut file_store = CountingFileStore::default();
+        let alignment = super::align_file_diff(
+            (file_diff.clone(), vec![]),
+            file_paths(),
+            1,
+            &mut LCSMatcher,
+            &mut filter,
+            &mut file_store,
+            &options,
+        )
+        .unwrap();
+        assert!(matches!(
+            alignment,
+            Some(super::FileAlignment::Rejected(outcome)) if outcome.rejected_changes().len() == 1
+        ));
+        assert!(file_store.reads.is_empty());
+
+        // Otherwise, source and target are read once
+        let mut file_store = CountingFileStore::default();
+        super::align_file_diff(
+            (file_diff, vec![]),
+            file_paths(),
+            1,
+            &mut LCSMatcher,
+            &mut KeepAllFilter,
+            &mut file_store,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            file_store.reads,
+            vec![file_paths().source, file_paths().target]
+        );
+    }
+
+    #[test]
+    fn opposite_line_change_type() {
+        assert_eq!(LineChangeType::Remove, LineChangeType::Add.opposite());
+        assert_eq!(LineChangeType::Add, LineChangeType::Remove.opposite());
+    }
+
+    #[test]
+    fn patch_strings_in_memory() {
+        let diff = "diff -Naur version-0/f.txt version-1/f.txt
+--- version-0/f.txt
++++ version-1/f.txt
+@@ -1,4 +1,4 @@
+ a
+-b
+ c
++C
+ d
+";
+        let source = "a\nb\nc\nd\n";
+        let patched = super::patch_str(diff, source, "header\na\nb\nc\nd\n").unwrap();
+        assert_eq!("header\na\nc\nC\nd\n", patched);
+
+        // The line endings of the target are retained
+        let source = source.replace('\n', "\r\n");
+        let patched = super::patch_str(diff, &source, "header\r\na\r\nb\r\nc\r\nd").unwrap();
+        assert_eq!("header\r\na\r\nc\r\nC\r\nd", patched);
+
+        // A change that cannot be applied is an error instead of a silent reject
+        let error = super::patch_str(diff, &source, "").unwrap_err();
+        assert_eq!(&ErrorKind::PatchError, error.kind());
+        let error = super::patch_str("", &source, "a").unwrap_err();
+        assert_eq!(&ErrorKind::DiffParseError, error.kind());
+    }
+
+    #[test]
+    fn reject_diff_of_several_files_in_memory() {
+        let file_diff = "diff -Naur version-0/f.txt version-1/f.txt
+--- version-0/f.txt
++++ version-1/f.txt
+@@ -1,1 +1,2 @@
+ a
++b
+";
+        let error = super::patch_str(&file_diff.repeat(2), "a\n", "a\n").unwrap_err();
+        assert_eq!(&ErrorKind::DiffParseError, error.kind());
+        assert_eq!(
+            "expected a diff of a single file, found 2 file diffs",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn reject_diff_without_hunks_in_memory() {
+        let diff = "diff -Naur version-0/f.txt version-1/f.txt
+--- version-0/f.txt
++++ version-1/f.txt
+";
+        let error = super::patch_str(diff, "a\n", "a\n").unwrap_err();
+        assert_eq!(&ErrorKind::DiffParseError, error.kind());
+        assert_eq!(
+            "the diff of version-1/f.txt contains no hunks",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn reject_overlapping_hunks_in_memory() {
+        let diff = "diff -Naur version-0/f.txt version-1/f.txt
+--- version-0/f.txt
++++ version-1/f.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+@@ -2,2 +2,2 @@
+-b
++B
+ c
+";
+        let error = super::patch_str(diff, "a\nb\nc\n", "a\nb\nc\n").unwrap_err();
+        assert_eq!(&ErrorKind::DiffParseError, error.kind());
+        assert!(error
+            .message()
+            .starts_with("the source locations of two hunks overlap"));
+    }
+
+    #[test]
+    fn build_patch_options() {
+        assert_eq!(PatchOptions::default(), PatchOptions::builder().build());
+
+        let cancel_flag = super::CancelFlag::new();
+        let options = PatchOptions::builder()
+            .dryrun(true)
+            .add_line_indent("  ")
+            .placement(PlacementStrategy::Nearby(2))
+            .lossy_read(true)
+            .dump_matching_dir("matchings")
+            .create_target_root(true)
+            .lenient_removes(true)
+            .sort_files(true)
+            .whitespace(WhitespaceMode::Fix)
+            .max_in_flight(8)
+            .report_file("report.diff")
+            .duplicate_targets(DuplicateTargetPolicy::Merge)
+            .case_insensitive_paths(true)
+            .normalize_inserted_endings(true)
+            .skip_already_applied(true)
+            .expected_source_hash("main.c", "76a6ff4a080b3649")
+            .max_total_edits(100)
+            .group_rejects(true)
+            .comment_prefixes(["//", "#"])
+            .skip_whitespace_hunks(true)
+            .cancel_flag(cancel_flag.clone())
+            .final_newline(FinalNewline::Always)
+            .build();
+        assert_eq!(
+            PatchOptions {
+                dryrun: true,
+                add_line_indent: Some("  ".to_string()),
+                placement: PlacementStrategy::Nearby(2),
+                lossy_read: true,
+                dump_matching_dir: Some(PathBuf::from("matchings")),
+                reject_sink: None,
+                create_target_root: true,
+                symlink_policy: SymlinkPolicy::Follow,
+                lenient_removes: true,
+                sort_files: true,
+                whitespace: WhitespaceMode::Fix,
+                max_in_flight: Some(8),
+                report_file: Some(PathBuf::from("report.diff")),
+                duplicate_targets: DuplicateTargetPolicy::Merge,
+                case_insensitive_paths: true,
+                normalize_inserted_endings: true,
+                skip_already_applied: true,
+                expected_source_hashes: HashMap::from([(
+                    PathBuf::from("main.c"),
+                    "76a6ff4a080b3649".to_string()
+                )]),
+                max_total_edits: Some(100),
+                group_rejects: true,
+                comment_prefixes: vec!["//".to_string(), "#".to_string()],
+                skip_whitespace_hunks: true,
+                cancel_flag: Some(cancel_flag),
+                final_newline: FinalNewline::Always,
+            },
+            options
+        );
+    }
+
+    #[test]
+    fn apply_aligned_patch_with_built_options() {
+        let target = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec!["{".to_string(), "}".to_string()],
+        );
+        let patch = AlignedPatch::for_test(vec![Change::test_add("call();", 2, 0)], target);
+
+        let options = PatchOptions::builder()
+            .dryrun(true)
+            .add_line_indent("    ")
+            .build();
+        let outcome = patch.clone().apply(&options).unwrap();
+        assert_eq!(["{", "    call();", "}"], outcome.patched_file().lines());
+
+        // The dryrun shortcut applies the default options
+        let outcome = patch.apply_dryrun().unwrap();
+        assert_eq!(["{", "call();", "}"], outcome.patched_file().lines());
+    }
 }
@@ -1,6 +1,66 @@
 use crate::{AlignedPatch, FilePatch, Matching};
 
-use super::{FileChangeType, FilteredPatch, LineChangeType};
+use super::{Change, ConflictSet, FileChangeType, FilteredPatch, LineChangeType, RejectReason};
+
+/// Options that configure how a patch is aligned to a target file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlignmentOptions {
+    /// The policy used to order changes that are aligned to the same target line.
+    pub tie_break: TieBreak,
+    /// The policy used to align adds for which no anchor in the target file can be found.
+    pub unanchored_adds: UnanchoredAddPolicy,
+    /// The policy used to re-anchor adds whose immediate anchor does not exist in the target
+    /// file.
+    pub missing_anchor: MissingAnchorPolicy,
+    /// Whether the aligned patch retains the aligned changes in the order in which they appear in
+    /// the diff (see `AlignedPatch::authored_order`).
+    pub retain_authored_order: bool,
+}
+
+/// An UnanchoredAddPolicy decides what happens to an add if none of the source lines above it
+/// has a match in the target file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnanchoredAddPolicy {
+    /// Insert the add before the first line of the target file.
+    #[default]
+    Prepend,
+    /// Insert the add after the last line of the target file.
+    Append,
+    /// Reject the add.
+    Reject,
+}
+
+/// A MissingAnchorPolicy decides where an add is anchored if the source line before which it is
+/// inserted has no match in the target file, e.g., because the line has been deleted in the
+/// target variant. In this case, the add is re-anchored to the nearest source line that has a
+/// match in the target file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingAnchorPolicy {
+    /// Insert the add after the target line of the nearest matched source line above it.
+    #[default]
+    Above,
+    /// Insert the add before the target line of the nearest matched source line below it. If
+    /// there is no such line, the add is anchored above.
+    Below,
+    /// Anchor the add to whichever of the nearest matched source lines above and below it is
+    /// closer in the source file. Ties are anchored above.
+    Nearest,
+}
+
+/// A TieBreak decides the order of changes that have been aligned to the same target line and
+/// have the same change type. This happens, for example, if the adds of two hunks are anchored to
+/// the same line, because a code block has been relocated in the target file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Order the changes by their id (i.e., by their order in the diff).
+    #[default]
+    ChangeId,
+    /// Order the changes by the line number in the source file to which they originally
+    /// belonged. Changes with the same source line number are ordered by their id.
+    SourceLine,
+    /// Keep the order in which the changes were encountered in the patch.
+    Encountered,
+}
 
 /// Consumes and aligns the patch to a specific target file based on a matching.
 /// The source file in the matching must also be the source file of the FileDiff from which
@@ -19,28 +79,56 @@ use super::{FileChangeType, FilteredPatch, LineChangeType};
 pub fn align_filtered_patch_to_target(
     patch: FilteredPatch,
     target_matching: Matching,
+) -> AlignedPatch {
+    align_filtered_patch_with_options(patch, target_matching, &AlignmentOptions::default())
+}
+
+/// Consumes and aligns the patch to a specific target file based on a matching, using the given
+/// options to configure the alignment. See `align_filtered_patch_to_target` for more
+/// information on how changes are aligned.
+pub fn align_filtered_patch_with_options(
+    patch: FilteredPatch,
+    target_matching: Matching,
+    options: &AlignmentOptions,
 ) -> AlignedPatch {
     if patch.change_type == FileChangeType::Create {
         // Files that are to be created are aligned by definition
+        let authored_order = if options.retain_authored_order {
+            patch.changes.clone()
+        } else {
+            vec![]
+        };
         return AlignedPatch {
             changes: patch.changes,
             rejected_changes: patch.rejected_changes,
             target: target_matching.into_target(),
             change_type: patch.change_type,
+            mode_change: patch.mode_change,
+            conflicts: vec![],
+            authored_order,
         };
     }
 
     // Align all changes
+    // Each aligned change is stored together with its original source line number and its
+    // position in the patch, because the tie-break policy might require them for sorting.
     let mut changes = Vec::with_capacity(patch.changes.len());
     let mut rejected_changes = patch.rejected_changes;
-    for mut change in patch.changes {
+    for (position, mut change) in patch.changes.into_iter().enumerate() {
+        let source_line_number = change.line_number;
         // Determine the best target line for each change
         let target_line_number = match change.change_type {
-            LineChangeType::Add => target_matching
-                .target_index_fuzzy(change.line_number)
-                .0
-                // Adds without a match are mapped to line 0 (i.e., prepend line)
-                .or(Some(0)),
+            LineChangeType::Add => {
+                anchor_add(&target_matching, change.line_number, options.missing_anchor).or(
+                    match options.unanchored_adds {
+                        // Line 0 is before the first line (i.e., prepend line)
+                        UnanchoredAddPolicy::Prepend => Some(0),
+                        // Adds anchored after the last line are appended to the file
+                        UnanchoredAddPolicy::Append => Some(target_matching.target().len() + 1),
+                        UnanchoredAddPolicy::Reject => None,
+                    },
+                )
+            }
             LineChangeType::Remove => {
                 // Removals without a match are automatically rejected
                 target_matching.target_index(change.line_number).flatten()
@@ -49,27 +137,108 @@ pub fn align_filtered_patch_to_target(
         if let Some(target_line_number) = target_line_number {
             // Align the change, if a suitable location has been found
             change.line_number = target_line_number;
-            changes.push(change);
+            changes.push((source_line_number, position, change));
         } else {
             // Otherwise, reject the change
-            rejected_changes.push(change);
+            rejected_changes.push(change.into_reject(RejectReason::NoMatch));
         }
     }
 
+    // The changes are still in the order of the diff
+    let authored_order = match options.retain_authored_order {
+        true => changes
+            .iter()
+            .map(|(_, _, change)| change.clone())
+            .collect(),
+        false => vec![],
+    };
+
     // During the alignment it is possible that changes switch their order because code chunks
     // might have been switched in the target file. This causes issues when applying changes,
     // because the change application assumes that the changes are ordered by line number.
     // Therefore, we sort all changes to ensure that they are applied in the correct order.
-    changes.sort();
+    // Changes anchored to the same target line are ordered by the configured tie-break policy.
+    changes.sort_by(|(a_source, a_position, a), (b_source, b_position, b)| {
+        a.line_number
+            .cmp(&b.line_number)
+            .then(a.change_type.cmp(&b.change_type))
+            .then_with(|| match options.tie_break {
+                TieBreak::ChangeId => a.change_id.cmp(&b.change_id),
+                TieBreak::SourceLine => a_source.cmp(b_source).then(a.change_id.cmp(&b.change_id)),
+                TieBreak::Encountered => a_position.cmp(b_position),
+            })
+    });
+
+    let conflicts = detect_conflicting_adds(&changes);
 
     AlignedPatch {
-        changes,
+        changes: changes.into_iter().map(|(_, _, change)| change).collect(),
         rejected_changes,
         target: target_matching.into_target(),
         change_type: patch.change_type,
+        mode_change: patch.mode_change,
+        conflicts,
+        authored_order,
     }
 }
 
+/// Determines the target line before which an add with the given source line number is inserted.
+/// If the source line has no match, the add is re-anchored according to the given policy.
+fn anchor_add(
+    target_matching: &Matching,
+    line_number: usize,
+    policy: MissingAnchorPolicy,
+) -> Option<usize> {
+    let (above, above_offset) = target_matching.target_index_fuzzy(line_number);
+    if above_offset.0 == 0 || policy == MissingAnchorPolicy::Above {
+        // The immediate anchor exists, or it is searched above as usual
+        return above;
+    }
+    let (below, below_offset) = target_matching.target_index_fuzzy_below(line_number);
+    match (above, below) {
+        (Some(above), Some(_))
+            // The offset above counts the unmatched line before which the add is inserted, which
+            // lies below the add
+            if policy == MissingAnchorPolicy::Nearest && above_offset.0 - 1 <= below_offset.0 =>
+        {
+            Some(above)
+        }
+        (above, below) => below.or(above),
+    }
+}
+
+/// Detects adds that were aligned to the same target line, but originate from different source
+/// lines and differ in content. The given changes must be sorted by their target line number and
+/// are paired with their source line number and their position in the patch.
+fn detect_conflicting_adds(changes: &[(usize, usize, Change)]) -> Vec<ConflictSet> {
+    let adds: Vec<(usize, &Change)> = changes
+        .iter()
+        .filter(|(_, _, change)| change.change_type == LineChangeType::Add)
+        .map(|(source_line_number, _, change)| (*source_line_number, change))
+        .collect();
+
+    let mut conflicts = vec![];
+    for anchored in adds.chunk_by(|(_, a), (_, b)| a.line_number == b.line_number) {
+        // Group the adds by the source location from which they originate; adds from the same
+        // location form one block of added lines
+        let mut blocks: Vec<(usize, Vec<&str>)> = vec![];
+        for (source_line_number, change) in anchored {
+            match blocks.iter_mut().find(|(l, _)| l == source_line_number) {
+                Some((_, block)) => block.push(change.line()),
+                None => blocks.push((*source_line_number, vec![change.line()])),
+            }
+        }
+        // Blocks that add the same content are not considered to be conflicting
+        if blocks.windows(2).any(|pair| pair[0].1 != pair[1].1) {
+            conflicts.push(ConflictSet {
+                target_line: anchored[0].1.line_number,
+                change_ids: anchored.iter().map(|(_, c)| c.change_id).collect(),
+            });
+        }
+    }
+    conflicts
+}
+
 /// Consumes and aligns the patch to a specific target file based on a matching.
 /// The source file in the matching must also be the source file of the FileDiff from which
 /// the FilePatch has been created. This means that it is the version of the source file
@@ -89,12 +258,93 @@ pub fn align_patch_to_target(patch: FilePatch, target_matching: Matching) -> Ali
         FilteredPatch {
             changes: patch.changes,
             change_type: patch.change_type,
+            mode_change: patch.mode_change,
             rejected_changes: vec![],
         },
         target_matching,
     )
 }
 
+/// An estimate of the risk that applying a patch to a target file produces rejects or misplaced
+/// changes (see `estimate_risk`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiskReport {
+    match_ratio: f64,
+    fuzzy_adds: usize,
+    unmatched_removes: usize,
+    score: f64,
+}
+
+impl RiskReport {
+    /// Returns the match ratio of the matching (see `Matching::match_ratio`).
+    pub fn match_ratio(&self) -> f64 {
+        self.match_ratio
+    }
+
+    /// Returns the number of adds whose immediate anchor has no match in the target file, so
+    /// that they are anchored to a more distant line or not at all.
+    pub fn fuzzy_adds(&self) -> usize {
+        self.fuzzy_adds
+    }
+
+    /// Returns the number of removes whose line has no match in the target file. These removes
+    /// are rejected during the alignment.
+    pub fn unmatched_removes(&self) -> usize {
+        self.unmatched_removes
+    }
+
+    /// Returns the risk score in the interval \[0,1\]; see `estimate_risk` for its definition.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// Estimates the risk of applying the patch to the target of the matching without aligning or
+/// applying it. The source file in the matching must be the source file of the patch.
+///
+/// The score is the mean of two fractions, so that it lies in the interval \[0,1\]:
+/// - the fraction of unmatched lines in the matching, i.e., `1 - match_ratio`, and
+/// - the fraction of changes that are fuzzy-anchored adds or unmatched removes.
+///
+/// A score of 0 means that all lines are matched and every change has an exact anchor. A patch
+/// without changes has a score of 0 for the second fraction. The score is deterministic for a
+/// given patch and matching.
+pub fn estimate_risk(patch: &FilePatch, matching: &Matching) -> RiskReport {
+    let mut fuzzy_adds = 0;
+    let mut unmatched_removes = 0;
+    for change in &patch.changes {
+        match change.change_type {
+            LineChangeType::Add => {
+                let (anchor, offset) = matching.target_index_fuzzy(change.line_number);
+                if anchor.is_none() || offset.0 > 0 {
+                    fuzzy_adds += 1;
+                }
+            }
+            LineChangeType::Remove => {
+                if matching
+                    .target_index(change.line_number)
+                    .flatten()
+                    .is_none()
+                {
+                    unmatched_removes += 1;
+                }
+            }
+        }
+    }
+
+    let match_ratio = matching.match_ratio();
+    let risky_fraction = match patch.changes.len() {
+        0 => 0.0,
+        len => (fuzzy_adds + unmatched_removes) as f64 / len as f64,
+    };
+    RiskReport {
+        match_ratio,
+        fuzzy_adds,
+        unmatched_removes,
+        score: ((1.0 - match_ratio) + risky_fraction) / 2.0,
+    }
+}
+
 /// Clones the patch for each given matching and aligns it to the corresponding target of each
 /// matching.
 /// The source file in each matching must also be the source file of the FileDiff from which
@@ -120,3 +370,263 @@ pub fn align_to_multiple_targets(
     }
     aligned_patches
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        patch::{Change, FilteredPatch, LineChangeType},
+        AlignedPatch, FileArtifact, FilePatch, LCSMatcher, Matcher, Matching, VersionDiff,
+    };
+
+    use super::{
+        align_filtered_patch_with_options, estimate_risk, AlignmentOptions, MissingAnchorPolicy,
+        TieBreak, UnanchoredAddPolicy,
+    };
+
+    fn colliding_adds() -> (FilteredPatch, Matching) {
+        let add =
+            |line: &str, line_number, change_id| Change::test_add(line, line_number, change_id);
+        let patch = FilteredPatch::for_test(vec![add("X", 5, 2), add("Y", 9, 0), add("Z", 1, 1)]);
+        // No source line is matched; thus, all adds are anchored to the start of the target
+        let source = FileArtifact::from_lines(PathBuf::from("source"), vec![String::new(); 10]);
+        let target = FileArtifact::from_lines(PathBuf::from("target"), vec!["target".to_string()]);
+        let matching = Matching::new(source, target, vec![None; 10], vec![None]);
+        (patch, matching)
+    }
+
+    fn aligned_order(tie_break: TieBreak) -> Vec<String> {
+        let (patch, matching) = colliding_adds();
+        let options = AlignmentOptions {
+            tie_break,
+            ..Default::default()
+        };
+        align_filtered_patch_with_options(patch, matching, &options)
+            .changes()
+            .iter()
+            .map(|c| c.line().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tie_break_by_change_id() {
+        assert_eq!(vec!["Y", "Z", "X"], aligned_order(TieBreak::ChangeId));
+    }
+
+    #[test]
+    fn tie_break_by_source_line() {
+        assert_eq!(vec!["Z", "X", "Y"], aligned_order(TieBreak::SourceLine));
+    }
+
+    #[test]
+    fn tie_break_by_encounter() {
+        assert_eq!(vec!["X", "Y", "Z"], aligned_order(TieBreak::Encountered));
+    }
+
+    #[test]
+    fn retain_authored_order() {
+        let change =
+            |line: &str, change_type, change_id| Change::for_test(line, change_type, 2, change_id);
+        // The diff adds the new line before it removes the old line at the same location
+        let patch = || {
+            FilteredPatch::for_test(vec![
+                change("new", LineChangeType::Add, 0),
+                change("old", LineChangeType::Remove, 1),
+            ])
+        };
+        let lines = vec!["a".to_string(), "old".to_string(), "c".to_string()];
+        let matching = || {
+            Matching::new(
+                FileArtifact::from_lines(PathBuf::from("source"), lines.clone()),
+                FileArtifact::from_lines(PathBuf::from("target"), lines.clone()),
+                vec![Some(1), Some(2), Some(3)],
+                vec![Some(1), Some(2), Some(3)],
+            )
+        };
+        let line_contents = |changes: &[Change]| -> Vec<String> {
+            changes.iter().map(|c| c.line.clone()).collect()
+        };
+
+        let options = AlignmentOptions {
+            retain_authored_order: true,
+            ..Default::default()
+        };
+        let aligned = align_filtered_patch_with_options(patch(), matching(), &options);
+        assert_eq!(vec!["old", "new"], line_contents(aligned.changes()));
+        assert_eq!(vec!["new", "old"], line_contents(aligned.authored_order()));
+
+        // By default, the authored order is not retained
+        let aligned = align_filtered_patch_with_options(patch(), matching(), &Default::default());
+        assert!(aligned.authored_order().is_empty());
+    }
+
+    #[test]
+    fn detect_conflicting_adds() {
+        let add =
+            |line: &str, line_number, change_id| Change::test_add(line, line_number, change_id);
+        // Only the first source line is matched; thus, adds after the second and the third
+        // source line are both anchored to the only target line
+        let matching = || {
+            Matching::new(
+                FileArtifact::from_lines(
+                    PathBuf::from("source"),
+                    vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                ),
+                FileArtifact::from_lines(PathBuf::from("target"), vec!["a".to_string()]),
+                vec![Some(0), None, None],
+                vec![Some(0)],
+            )
+        };
+
+        let patch = FilteredPatch::for_test(vec![add("ours", 3, 0), add("theirs", 4, 1)]);
+        let aligned = align_filtered_patch_with_options(patch, matching(), &Default::default());
+        assert_eq!(1, aligned.conflicts().len());
+        let conflict = &aligned.conflicts()[0];
+        // The adds are inserted after the first target line
+        assert_eq!(2, conflict.target_line());
+        assert_eq!(&[0, 1], conflict.change_ids());
+        // The conflicting adds are still aligned
+        assert_eq!(2, aligned.changes().len());
+
+        // Adds with the same content do not conflict
+        let patch = FilteredPatch::for_test(vec![add("same", 3, 0), add("same", 4, 1)]);
+        let aligned = align_filtered_patch_with_options(patch, matching(), &Default::default());
+        assert!(!aligned.has_conflicts());
+    }
+
+    fn align_unanchored_add(policy: UnanchoredAddPolicy) -> AlignedPatch {
+        let patch = FilteredPatch::for_test(vec![Change::test_add("unanchored", 2, 0)]);
+        // The only source line above the add has no match in the target file
+        let matching = Matching::new(
+            FileArtifact::from_lines(PathBuf::from("source"), vec!["a".to_string()]),
+            // The target must exist on disk for the patch to be applicable (in a dryrun)
+            FileArtifact::from_lines(
+                PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+                vec!["b".to_string(), "c".to_string()],
+            ),
+            vec![None],
+            vec![None, None],
+        );
+        let options = AlignmentOptions {
+            unanchored_adds: policy,
+            ..Default::default()
+        };
+        align_filtered_patch_with_options(patch, matching, &options)
+    }
+
+    #[test]
+    fn prepend_unanchored_add() {
+        let aligned = align_unanchored_add(UnanchoredAddPolicy::Prepend);
+        assert_eq!(0, aligned.changes()[0].line_number());
+        let outcome = aligned.apply_dryrun().unwrap();
+        assert_eq!(&["unanchored", "b", "c"], outcome.patched_file().lines());
+    }
+
+    #[test]
+    fn append_unanchored_add() {
+        let aligned = align_unanchored_add(UnanchoredAddPolicy::Append);
+        assert_eq!(3, aligned.changes()[0].line_number());
+        let outcome = aligned.apply_dryrun().unwrap();
+        assert_eq!(&["b", "c", "unanchored"], outcome.patched_file().lines());
+    }
+
+    #[test]
+    fn reject_unanchored_add() {
+        let aligned = align_unanchored_add(UnanchoredAddPolicy::Reject);
+        assert!(aligned.changes().is_empty());
+        assert_eq!("unanchored", aligned.rejected_changes[0].line());
+    }
+
+    fn align_add_with_deleted_anchor(
+        line_number: usize,
+        policy: MissingAnchorPolicy,
+    ) -> AlignedPatch {
+        let patch = FilteredPatch::for_test(vec![Change::test_add("new", line_number, 0)]);
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect();
+        // All lines between "a" and "f" have been deleted in the target
+        let matching = Matching::new(
+            FileArtifact::from_lines(
+                PathBuf::from("source"),
+                lines(&["a", "b", "c", "d", "e", "f"]),
+            ),
+            // The target must exist on disk for the patch to be applicable (in a dryrun)
+            FileArtifact::from_lines(
+                PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+                lines(&["a", "w", "x", "y", "z", "f"]),
+            ),
+            vec![Some(0), None, None, None, None, Some(5)],
+            vec![Some(0), None, None, None, None, Some(5)],
+        );
+        let options = AlignmentOptions {
+            missing_anchor: policy,
+            ..Default::default()
+        };
+        align_filtered_patch_with_options(patch, matching, &options)
+    }
+
+    #[test]
+    fn reanchor_add_with_deleted_anchor() {
+        // The add is inserted between "c" and "d"; by default, it is anchored after the nearest
+        // matched line above
+        let aligned = align_add_with_deleted_anchor(4, MissingAnchorPolicy::Above);
+        assert_eq!(2, aligned.changes()[0].line_number());
+        let outcome = aligned.apply_dryrun().unwrap();
+        assert_eq!(
+            &["a", "new", "w", "x", "y", "z", "f"],
+            outcome.patched_file().lines()
+        );
+
+        let aligned = align_add_with_deleted_anchor(4, MissingAnchorPolicy::Below);
+        assert_eq!(6, aligned.changes()[0].line_number());
+        let outcome = aligned.apply_dryrun().unwrap();
+        assert_eq!(
+            &["a", "w", "x", "y", "z", "new", "f"],
+            outcome.patched_file().lines()
+        );
+
+        // The matched lines above and below are equally close, so the add is anchored above
+        let aligned = align_add_with_deleted_anchor(4, MissingAnchorPolicy::Nearest);
+        assert_eq!(2, aligned.changes()[0].line_number());
+
+        // Inserted between "d" and "e", the matched line below is closer
+        let aligned = align_add_with_deleted_anchor(5, MissingAnchorPolicy::Nearest);
+        assert_eq!(6, aligned.changes()[0].line_number());
+    }
+
+    #[test]
+    fn estimate_risk_of_clean_and_mismatched_patches() {
+        let diff = VersionDiff::try_from(
+            "diff -Naur version-0/main.c version-1/main.c
+--- version-0/main.c
++++ version-1/main.c
+@@ -1,3 +1,3 @@
+ a
+-b
++x
+ c
+"
+            .to_string(),
+        )
+        .unwrap();
+        let patch = FilePatch::from(diff.file_diffs()[0].clone());
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect();
+        let source = FileArtifact::from_lines(PathBuf::from("source"), lines(&["a", "b", "c"]));
+
+        // The target is identical to the source
+        let target = FileArtifact::from_lines(PathBuf::from("target"), lines(&["a", "b", "c"]));
+        let clean = estimate_risk(&patch, &LCSMatcher.match_files(source.clone(), target));
+        assert_eq!(1.0, clean.match_ratio());
+        assert_eq!(0, clean.fuzzy_adds());
+        assert_eq!(0, clean.unmatched_removes());
+        assert_eq!(0.0, clean.score());
+
+        // Only the first line of the target matches the source
+        let target = FileArtifact::from_lines(PathBuf::from("target"), lines(&["a", "y", "z"]));
+        let mismatched = estimate_risk(&patch, &LCSMatcher.match_files(source, target));
+        assert_eq!(1, mismatched.fuzzy_adds());
+        assert_eq!(1, mismatched.unmatched_removes());
+        assert!(mismatched.score() > 0.8);
+        assert!(clean.score() < mismatched.score());
+    }
+}
@@ -1,15 +1,20 @@
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, fs, path::Path};
 
-use crate::{AlignedPatch, Error, FileArtifact, PatchOutcome};
+use crate::{AlignedPatch, Error, ErrorKind, FileArtifact, LineEnding, PatchOptions, PatchOutcome};
 
-use super::{FileChangeType, LineChangeType};
+use super::{
+    group_by_location, Change, Conflict, FileChangeType, LineChangeType, PlacementStrategy,
+    RejectReason, SymlinkPolicy, WhitespaceMode,
+};
 
 /// Consumes and applies the patch to the target file artifact.
 /// This function differentiates between the three different FileChangeTypes: Create, Remove,
 /// and Modify.
 ///
 /// In case of Create, a new file is created and the entire content of the patch
-/// added to it. The patch fails if the file already exists.
+/// added to it. The patch fails if the file already exists. A Create patch without any changes
+/// only creates an empty file if the diff intended it to be empty. If all of its changes have been
+/// rejected (e.g., by a filter), no file is created.
 ///
 /// In case of Remove, the file and its entire content is removed, even if the file has more content
 /// than specified in the patch. The patch is rejected if the file does not exist.
@@ -17,54 +22,294 @@ use super::{FileChangeType, LineChangeType};
 /// In case of Modify, the changes in the patch are applied in order. The patch is rejected if
 /// the file does not exist.
 ///
+/// If the patch changes the mode of the file (see `FilePatch::mode_change`), the permission bits
+/// of a created or modified file are set once it has been written. On platforms other than Unix,
+/// the mode change is skipped.
+///
 /// If dryrun is set to true, the changes are not saved to the file. This is useful when
 /// looking for rejects without wanting to modify the target file.
 ///
 /// ## Error
-/// Returns an Error if the necessary file operations cannot be performed.
-pub fn apply_patch(mut patch: AlignedPatch, dryrun: bool) -> Result<PatchOutcome, Error> {
-    // Check file existance; it must not exist when it is to be created and it must exist
-    // when it is to be modified or removed
-    let reject_patch = if patch.change_type == FileChangeType::Create {
-        Path::exists(patch.target.path())
-    } else {
-        !Path::exists(patch.target.path())
+/// Returns an Error if the necessary file operations cannot be performed. Returns an Error of kind
+/// PatchError that carries the offending change if a removed line does not exist in the target
+/// file or has unexpected content.
+pub fn apply_patch(patch: AlignedPatch, dryrun: bool) -> Result<PatchOutcome, Error> {
+    let options = PatchOptions {
+        dryrun,
+        ..Default::default()
     };
-    if reject_patch {
-        reject_all(&mut patch);
+    apply_patch_with_options(patch, &options)
+}
+
+/// Consumes and applies the patch to the target file artifact like `apply_patch_with_options`, but
+/// treats the target as text that only exists in memory: the patch is applied as a modification
+/// of the target, whether the target file exists is not checked, and nothing is written to disk.
+pub(crate) fn apply_in_memory(
+    patch: AlignedPatch,
+    options: &PatchOptions,
+) -> Result<PatchOutcome, Error> {
+    apply_file_modification(patch, options, None)
+}
+
+/// Consumes and applies the patch to the target file artifact using the given options. See
+/// `apply_patch` for more information on how the different FileChangeTypes are applied. Whether
+/// the patched file is saved is determined by `PatchOptions::dryrun`.
+///
+/// ## Error
+/// Returns an Error if the necessary file operations cannot be performed. Unless
+/// `PatchOptions::lenient_removes` is set, returns an Error of kind PatchError if a removed line
+/// does not exist in the target file or has unexpected content.
+pub fn apply_patch_with_options(
+    patch: AlignedPatch,
+    options: &PatchOptions,
+) -> Result<PatchOutcome, Error> {
+    let patch_outcome = apply_without_writing(patch, options)?;
+    if !options.dryrun {
+        write_outcome(&patch_outcome, options)?;
+    }
+    Ok(patch_outcome)
+}
+
+/// Consumes and applies the patch like `apply_patch_with_options`, but never writes to disk,
+/// regardless of `PatchOptions::dryrun`. The outcome can be written with `write_outcome`.
+pub(crate) fn apply_without_writing(
+    mut patch: AlignedPatch,
+    options: &PatchOptions,
+) -> Result<PatchOutcome, Error> {
+    if let Some(reason) = target_reject_reason(&patch) {
+        reject_all(&mut patch, reason);
         return Ok(PatchOutcome {
             patched_file: patch.target,
+            applied_changes: vec![],
             rejected_changes: patch.rejected_changes,
             change_type: patch.change_type,
+            mode_change: None,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        });
+    }
+    match patch.change_type {
+        FileChangeType::Create => Ok(apply_file_creation(patch, options)),
+        FileChangeType::Remove => Ok(apply_file_removal(patch)),
+        FileChangeType::Modify => apply_file_modification(patch, options, None),
+    }
+}
+
+/// A Resolution decides how a change that would be rejected during the application of a patch is
+/// handled (see `apply_patch_with_resolver`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Reject the change as usual.
+    Skip,
+    /// Apply the change anyway. An add is inserted with its line; a remove removes the target
+    /// line, even though its content differs from the removed line.
+    ForceApply,
+    /// Apply the change with the given line instead. An add inserts the given line; a remove
+    /// replaces the target line with the given line.
+    Edit(String),
+}
+
+/// Consumes and applies the patch like `apply_patch_with_options`, but lets the given resolver
+/// decide how to handle each change that would be rejected during the application, e.g., to
+/// resolve rejects interactively. The resolver is called with the change and the target file
+/// before the patch application.
+///
+/// The resolver is only called for the changes of a modified file that are rejected during the
+/// application: adds that are rejected because of trailing whitespace (see
+/// `WhitespaceMode::Error`) and removes whose target line differs from the removed line (see
+/// `PatchOptions::lenient_removes`). Changes that have already been rejected during the filtering
+/// or alignment, and removes for which no target line exists, remain rejected.
+///
+/// ## Error
+/// Returns an Error under the same conditions as `apply_patch_with_options`.
+pub fn apply_patch_with_resolver(
+    patch: AlignedPatch,
+    options: &PatchOptions,
+    mut on_reject: impl FnMut(&Change, &FileArtifact) -> Resolution,
+) -> Result<PatchOutcome, Error> {
+    if patch.change_type != FileChangeType::Modify || target_reject_reason(&patch).is_some() {
+        return apply_patch_with_options(patch, options);
+    }
+    // The resolver is called with the target before the application, which is therefore kept
+    let resolver = RejectResolver {
+        target: patch.target.clone(),
+        on_reject: &mut on_reject,
+    };
+    let patch_outcome = apply_file_modification(patch, options, Some(resolver))?;
+    if !options.dryrun {
+        write_outcome(&patch_outcome, options)?;
+    }
+    Ok(patch_outcome)
+}
+
+/// Consumes and applies several independent patches that are aligned to the same target file in
+/// one pass. The targets of the patches themselves are ignored in favor of the given target.
+///
+/// The changes of the patches are combined by their target line numbers. If several patches
+/// change the same target line in the same way (e.g., they add the identical lines), the change is
+/// applied once. If their changes at the same target line differ, the changes collide; none of
+/// them is applied, and they are reported as a Conflict instead. The colliding changes are also
+/// rejected with `RejectReason::Conflict`. The outcome contains the applied and rejected changes
+/// of all patches; note that the change ids of different patches might overlap. The combined
+/// patch is applied with the given options like with `apply_patch_with_options`.
+///
+/// ## Error
+/// Returns an Error of kind PatchError if the patches have different change types. See
+/// `apply_patch_with_options` for further errors.
+pub fn apply_many(
+    target: FileArtifact,
+    patches: Vec<AlignedPatch>,
+    options: &PatchOptions,
+) -> Result<(PatchOutcome, Vec<Conflict>), Error> {
+    let change_type = patches
+        .first()
+        .map_or(FileChangeType::Modify, |p| p.change_type);
+    let mode_change = patches.iter().find_map(|p| p.mode_change);
+    if patches.iter().any(|p| p.change_type != change_type) {
+        return Err(Error::new(
+            "cannot apply patches with different change types together",
+            ErrorKind::PatchError,
+        ));
+    }
+
+    let mut rejected_changes = vec![];
+    // The changes of each patch at each target location, paired with the index of the patch
+    let mut locations: BTreeMap<_, Vec<(usize, Vec<Change>)>> = BTreeMap::new();
+    for (index, patch) in patches.into_iter().enumerate() {
+        rejected_changes.extend(patch.rejected_changes);
+        for (location, group) in group_by_location(patch.changes) {
+            locations.entry(location).or_default().push((index, group));
+        }
+    }
+
+    let mut changes = vec![];
+    let mut conflicts = vec![];
+    for ((line_number, change_type), mut groups) in locations {
+        let (_, first) = &groups[0];
+        let identical = groups[1..].iter().all(|(_, group)| {
+            group.len() == first.len() && group.iter().zip(first).all(|(c, f)| c.line == f.line)
         });
+        if identical {
+            changes.extend(groups.swap_remove(0).1);
+        } else {
+            rejected_changes.extend(
+                groups
+                    .iter()
+                    .flat_map(|(_, group)| group.iter().cloned())
+                    .map(|change| change.into_reject(RejectReason::Conflict)),
+            );
+            conflicts.push(Conflict {
+                line_number,
+                change_type,
+                changes: groups,
+            });
+        }
     }
+    rejected_changes.sort_by_key(|r| r.line_number);
+
+    let patch = AlignedPatch {
+        changes,
+        rejected_changes,
+        target,
+        change_type,
+        mode_change,
+        conflicts: vec![],
+        authored_order: vec![],
+    };
+    Ok((apply_patch_with_options(patch, options)?, conflicts))
+}
+
+/// Returns the changes of the patch that would be rejected when applying it, without applying
+/// the patch. This comprises the changes rejected before the application and, if the state of the
+/// target file does not permit applying the patch, all other changes.
+pub(crate) fn preview_rejects(mut patch: AlignedPatch) -> Vec<Change> {
+    if let Some(reason) = target_reject_reason(&patch) {
+        reject_all(&mut patch, reason);
+    }
+    patch.rejected_changes
+}
+
+/// Checks the existance of the target file; it must not exist when it is to be created and it
+/// must exist when it is to be modified or removed. Returns the reason for rejecting the entire
+/// patch, if the target file does not meet these requirements.
+fn target_reject_reason(patch: &AlignedPatch) -> Option<RejectReason> {
+    let target_exists = Path::exists(patch.target.path());
     match patch.change_type {
-        FileChangeType::Create => apply_file_creation(patch, dryrun),
-        FileChangeType::Remove => apply_file_removal(patch, dryrun),
-        FileChangeType::Modify => apply_file_modification(patch, dryrun),
+        FileChangeType::Create if target_exists => Some(RejectReason::TargetExists),
+        FileChangeType::Modify | FileChangeType::Remove if !target_exists => {
+            Some(RejectReason::TargetMissing)
+        }
+        _ => None,
     }
 }
 
-/// Rejects all changes in the patch.
-fn reject_all(patch: &mut AlignedPatch) {
+/// Rejects all changes in the patch for the given reason. Changes that have already been
+/// rejected keep their original reason.
+fn reject_all(patch: &mut AlignedPatch, reason: RejectReason) {
     let mut rejects = vec![];
     while let Some(change) = patch.changes.pop() {
-        rejects.push(change);
+        rejects.push(change.into_reject(reason));
     }
     while let Some(reject) = patch.rejected_changes.pop() {
         rejects.push(reject);
     }
-    rejects.sort_by(|a, b| a.line_number.cmp(&b.line_number));
+    rejects.sort_by_key(|r| r.line_number);
     patch.changes = vec![];
     patch.rejected_changes = rejects;
 }
 
-/// Applies a modification patch.
-fn apply_file_modification(patch: AlignedPatch, dryrun: bool) -> Result<PatchOutcome, Error> {
-    let ((path, lines), mut changes) = (
-        (patch.target.into_path_and_lines()),
-        patch.changes.into_iter().peekable(),
-    );
+/// A RejectResolver holds the resolver of `apply_patch_with_resolver` together with the target file
+/// before the patch application, with which the resolver is called.
+struct RejectResolver<'a> {
+    target: FileArtifact,
+    on_reject: &'a mut dyn FnMut(&Change, &FileArtifact) -> Resolution,
+}
+
+/// Applies a modification patch. Changes that would be rejected during the application are passed
+/// to the resolver, if there is one; otherwise, they are rejected.
+fn apply_file_modification(
+    patch: AlignedPatch,
+    options: &PatchOptions,
+    mut resolver: Option<RejectResolver>,
+) -> Result<PatchOutcome, Error> {
+    let is_symlink = fs::symlink_metadata(patch.target.path()).is_ok_and(|m| m.is_symlink());
+    if is_symlink && options.symlink_policy == SymlinkPolicy::Refuse {
+        return Err(Error::new(
+            &format!(
+                "the target file {} is a symbolic link",
+                patch.target.path().display()
+            ),
+            ErrorKind::PatchError,
+        ));
+    }
+
+    // A patch without changes (e.g., one that only changes the mode of the target file) leaves
+    // the content of the target file as it is
+    if patch.changes.is_empty() && patch.rejected_changes.is_empty() {
+        return Ok(PatchOutcome {
+            patched_file: patch.target,
+            applied_changes: vec![],
+            rejected_changes: vec![],
+            change_type: patch.change_type,
+            mode_change: patch.mode_change,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        });
+    }
+
+    let mut rejected_changes = patch.rejected_changes;
+    let line_ending = if options.normalize_inserted_endings {
+        patch.target.line_ending()
+    } else {
+        LineEnding::Lf
+    };
+    let target_has_final_newline = patch.target.has_final_newline();
+    let (path, lines) = patch.target.into_path_and_lines();
+    let mut changes = place_changes(patch.changes, &lines, options.placement)
+        .into_iter()
+        .peekable();
 
     // The number of the currently processed line in the target file (before modification)
     // The line number is used to identify the edit locations that were previously determined
@@ -72,8 +317,12 @@ fn apply_file_modification(patch: AlignedPatch, dryrun: bool) -> Result<PatchOut
     // We start at 0 to account for line insertions before the first line
     let mut target_line_number = 1;
     let mut patched_lines = vec![];
+    let mut applied_changes = Vec::with_capacity(changes.len());
+    // The index of each applied change in the patched lines; a removed line is located at the
+    // index of the line that follows it
+    let mut applied_line_indices = Vec::with_capacity(changes.len());
     'lines_loop: for line in lines {
-        while changes.peek().map_or(false, |c| match c.change_type {
+        while changes.peek().is_some_and(|c| match c.change_type {
             // Adds are anchored to the context line above (i.e., lower than target_line_number)
             LineChangeType::Add => c.line_number <= target_line_number,
             // Removes are anchored to actual line being removed (i.e. the line being currently
@@ -82,16 +331,46 @@ fn apply_file_modification(patch: AlignedPatch, dryrun: bool) -> Result<PatchOut
         }) {
             let change = changes.next().expect("there should be a change to extract");
             match change.change_type {
-                LineChangeType::Add => {
-                    // add this line to the vector of patched lines
-                    patched_lines.push(change.line);
-                }
+                LineChangeType::Add => match handle_whitespace(change, options.whitespace)
+                    .or_else(|reject| resolve_add(reject, &mut resolver))
+                {
+                    Ok(change) => {
+                        // add this line to the vector of patched lines
+                        applied_line_indices.push(patched_lines.len());
+                        patched_lines.push(added_line(change.line.clone(), options));
+                        applied_changes.push(change);
+                    }
+                    Err(reject) => rejected_changes.push(reject),
+                },
                 LineChangeType::Remove => {
                     // remove this line by skipping it
-                    assert_eq!(
-                        line, change.line,
-                        "unexpected line difference in line {target_line_number}"
-                    );
+                    if !is_removed_line(&line, &change.line, &options.comment_prefixes) {
+                        if options.lenient_removes {
+                            let reject = change.into_reject(RejectReason::LineMismatch);
+                            match resolve_reject(reject, &mut resolver) {
+                                Ok((change, replacement)) => {
+                                    // remove the line anyway, or replace it with the edited line
+                                    applied_line_indices.push(patched_lines.len());
+                                    patched_lines.extend(replacement);
+                                    applied_changes.push(change);
+                                    target_line_number += 1;
+                                    continue 'lines_loop;
+                                }
+                                Err(reject) => {
+                                    // keep the line and continue with the next change
+                                    rejected_changes.push(reject);
+                                    continue;
+                                }
+                            }
+                        }
+                        return Err(Error::with_change(
+                            &format!("unexpected line difference in line {target_line_number}"),
+                            ErrorKind::PatchError,
+                            change,
+                        ));
+                    }
+                    applied_line_indices.push(patched_lines.len());
+                    applied_changes.push(change);
                     target_line_number += 1;
                     continue 'lines_loop;
                 }
@@ -107,79 +386,391 @@ fn apply_file_modification(patch: AlignedPatch, dryrun: bool) -> Result<PatchOut
     // Apply the remaining changes
     for change in changes {
         match change.change_type {
-            LineChangeType::Add => {
-                // add this line to the vector of patched lines
-                patched_lines.push(change.line);
+            LineChangeType::Add => match handle_whitespace(change, options.whitespace)
+                .or_else(|reject| resolve_add(reject, &mut resolver))
+            {
+                Ok(change) => {
+                    // add this line to the vector of patched lines
+                    applied_line_indices.push(patched_lines.len());
+                    patched_lines.push(added_line(change.line.clone(), options));
+                    applied_changes.push(change);
+                }
+                Err(reject) => rejected_changes.push(reject),
+            },
+            LineChangeType::Remove if options.lenient_removes => {
+                rejected_changes.push(change.into_reject(RejectReason::LineMismatch));
             }
             LineChangeType::Remove => {
-                eprint!("{}: {change}", change.line_number);
-                panic!("there were unprocessed changes in the patch");
+                return Err(Error::with_change(
+                    &format!(
+                        "the line {} to be removed does not exist",
+                        change.line_number
+                    ),
+                    ErrorKind::PatchError,
+                    change,
+                ));
             }
         }
     }
 
-    let patched_file = FileArtifact::from_lines(path, patched_lines);
+    let line_starts = line_start_offsets(&patched_lines, line_ending);
+    let applied_change_offsets = applied_changes
+        .iter()
+        .zip(applied_line_indices)
+        .map(|(change, index)| match change.change_type {
+            LineChangeType::Add => (
+                line_starts[index],
+                line_starts[index] + patched_lines[index].len(),
+            ),
+            LineChangeType::Remove => (line_starts[index], line_starts[index]),
+        })
+        .collect();
 
-    if !dryrun {
-        patched_file.write()?;
-    }
+    let final_newline = options
+        .final_newline
+        .ends_with_newline(target_has_final_newline);
+    let patched_file = FileArtifact::from_lines(path, patched_lines)
+        .with_line_ending(line_ending)
+        .with_final_newline(final_newline);
+    rejected_changes.sort_by_key(|r| r.line_number);
 
     Ok(PatchOutcome {
         patched_file,
-        rejected_changes: patch.rejected_changes,
+        applied_changes,
+        rejected_changes,
         change_type: patch.change_type,
+        mode_change: patch.mode_change,
+        applied_change_offsets,
+        matcher_description: None,
+        filter_description: None,
     })
 }
 
-/// Applies the creation of a new file.
-fn apply_file_creation(patch: AlignedPatch, dryrun: bool) -> Result<PatchOutcome, Error> {
-    let (path, lines) = (
-        patch.target.path().to_path_buf(),
-        patch.changes.into_iter().map(|c| c.line).collect(),
-    );
+/// Determines the final locations of the changes in the target file according to the given
+/// placement strategy. Removes that are annotated with an occurrence are placed at it first.
+fn place_changes(
+    mut changes: Vec<Change>,
+    lines: &[String],
+    placement: PlacementStrategy,
+) -> Vec<Change> {
+    let mut relocated = place_occurrences(&mut changes, lines, placement);
+    let max_distance = match placement {
+        PlacementStrategy::Exact => 0,
+        PlacementStrategy::Nearby(max_distance) => max_distance,
+    };
 
-    if !dryrun {
-        // Create all parent directories
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    let is_removed = |changes: &[Change], line_number: usize| {
+        changes
+            .iter()
+            .any(|c| c.change_type == LineChangeType::Remove && c.line_number == line_number)
+    };
+    let content_matches = |line_number: usize, content: &str| {
+        line_number >= 1 && lines.get(line_number - 1).is_some_and(|l| l == content)
+    };
+
+    for index in 0..changes.len() {
+        let change = &changes[index];
+        if change.change_type != LineChangeType::Remove
+            || content_matches(change.line_number, &change.line)
+        {
+            continue;
+        }
+        // Search the closest line with the same content that is not removed by another change;
+        // lines above the anchor are preferred over lines below it at the same distance
+        let anchor = change.line_number;
+        let candidate = (1..=max_distance)
+            .flat_map(|distance| [anchor.checked_sub(distance), Some(anchor + distance)])
+            .flatten()
+            .find(|&line_number| {
+                content_matches(line_number, &changes[index].line)
+                    && !is_removed(&changes, line_number)
+            });
+        if let Some(line_number) = candidate {
+            changes[index].line_number = line_number;
+            relocated = true;
         }
     }
 
-    let patched_file = FileArtifact::from_lines(path, lines);
-    if !dryrun {
-        patched_file.write()?;
+    if relocated {
+        // Restore the order in which the changes are applied; the sort is stable and retains
+        // the order of changes at the same location
+        changes.sort_by_key(|c| (c.line_number, c.change_type));
     }
+    changes
+}
 
-    Ok(PatchOutcome {
+/// Moves each remove that is annotated with an occurrence to that occurrence of its line among the
+/// target lines in its region (see `Change::with_occurrence`). A remove whose occurrence does not
+/// exist keeps its location. Returns true if a remove has been moved.
+fn place_occurrences(
+    changes: &mut [Change],
+    lines: &[String],
+    placement: PlacementStrategy,
+) -> bool {
+    let mut relocated = false;
+    for change in changes.iter_mut() {
+        let Some(occurrence) = change.occurrence else {
+            continue;
+        };
+        if change.change_type != LineChangeType::Remove || occurrence == 0 {
+            continue;
+        }
+        let region = match placement {
+            PlacementStrategy::Exact => 1..=lines.len(),
+            PlacementStrategy::Nearby(max_distance) => {
+                change.line_number.saturating_sub(max_distance).max(1)
+                    ..=(change.line_number + max_distance).min(lines.len())
+            }
+        };
+        let line_number = region
+            .filter(|&line_number| lines[line_number - 1] == change.line)
+            .nth(occurrence - 1);
+        if let Some(line_number) = line_number {
+            relocated |= line_number != change.line_number;
+            change.line_number = line_number;
+        }
+    }
+    relocated
+}
+
+/// Prepares the content of an added line according to the given options.
+fn added_line(line: String, options: &PatchOptions) -> String {
+    match &options.add_line_indent {
+        Some(indent) => format!("{indent}{line}"),
+        None => line,
+    }
+}
+
+/// Lets the resolver decide how to handle a change that would be rejected. Returns the change
+/// that is applied together with the edited line that is applied in its place, if any, or the
+/// rejected change if there is no resolver or the resolver skips it.
+fn resolve_reject(
+    mut reject: Change,
+    resolver: &mut Option<RejectResolver>,
+) -> Result<(Change, Option<String>), Change> {
+    let Some(resolver) = resolver else {
+        return Err(reject);
+    };
+    match (resolver.on_reject)(&reject, &resolver.target) {
+        Resolution::Skip => Err(reject),
+        Resolution::ForceApply => {
+            reject.reject_reason = None;
+            Ok((reject, None))
+        }
+        Resolution::Edit(line) => {
+            reject.reject_reason = None;
+            Ok((reject, Some(line)))
+        }
+    }
+}
+
+/// Lets the resolver decide how to handle an add that would be rejected (see `resolve_reject`).
+/// An edited add is applied with the edited line.
+fn resolve_add(reject: Change, resolver: &mut Option<RejectResolver>) -> Result<Change, Change> {
+    resolve_reject(reject, resolver).map(|(mut change, edited_line)| {
+        if let Some(edited_line) = edited_line {
+            change.line = edited_line;
+        }
+        change
+    })
+}
+
+/// Returns true if the target line is the removed line. A trailing comment of the target line
+/// that starts with one of the comment prefixes is ignored (see `PatchOptions::comment_prefixes`).
+fn is_removed_line(target_line: &str, removed_line: &str, comment_prefixes: &[String]) -> bool {
+    target_line == removed_line
+        || comment_prefixes.iter().any(|prefix| {
+            target_line
+                .match_indices(prefix.as_str())
+                .any(|(index, _)| {
+                    let code = target_line[..index].trim_end();
+                    // A line that only consists of a comment has no trailing comment
+                    !code.trim_start().is_empty() && code == removed_line.trim_end()
+                })
+        })
+}
+
+/// Handles trailing whitespace in the line of an add change according to the given mode. Returns
+/// the change that is to be applied, or the rejected change if the mode does not permit applying
+/// it.
+fn handle_whitespace(mut change: Change, mode: WhitespaceMode) -> Result<Change, Change> {
+    let trimmed_length = change.line.trim_end().len();
+    if trimmed_length == change.line.len() {
+        return Ok(change);
+    }
+    match mode {
+        WhitespaceMode::NoWarn => Ok(change),
+        WhitespaceMode::Warn => {
+            eprintln!(
+                "warning: trailing whitespace in added line {}: {:?}",
+                change.line_number, change.line
+            );
+            Ok(change)
+        }
+        WhitespaceMode::Fix => {
+            change.line.truncate(trimmed_length);
+            Ok(change)
+        }
+        WhitespaceMode::Error => Err(change.into_reject(RejectReason::Whitespace)),
+    }
+}
+
+/// Returns the byte offset at which each of the given lines starts in the content of a file that
+/// consists of the lines separated by the given line ending. The last offset is the length of the
+/// content, so that it can be used as the start of a line that follows the last line.
+fn line_start_offsets(lines: &[String], line_ending: LineEnding) -> Vec<usize> {
+    let separator_length = line_ending.as_str().len();
+    let mut line_starts = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
+    for line in lines {
+        line_starts.push(offset);
+        offset += line.len() + separator_length;
+    }
+    // The last line is not terminated
+    line_starts.push(offset.saturating_sub(separator_length));
+    line_starts
+}
+
+/// Applies the creation of a new file.
+fn apply_file_creation(patch: AlignedPatch, options: &PatchOptions) -> PatchOutcome {
+    if patch.changes.is_empty() && !patch.rejected_changes.is_empty() {
+        // The patch has been emptied by filtering or alignment; creating an empty file would not
+        // reflect the intention of the diff
+        return PatchOutcome {
+            patched_file: patch.target,
+            applied_changes: vec![],
+            rejected_changes: patch.rejected_changes,
+            change_type: patch.change_type,
+            mode_change: None,
+            applied_change_offsets: vec![],
+            matcher_description: None,
+            filter_description: None,
+        };
+    }
+
+    let (path, lines) = (
+        patch.target.path().to_path_buf(),
+        patch.changes.iter().map(|c| c.line.clone()).collect(),
+    );
+
+    // A created file has no target file whose final newline could be preserved
+    let patched_file = FileArtifact::from_lines(path, lines)
+        .with_final_newline(options.final_newline.ends_with_newline(false));
+    // Each change is one line of the created file
+    let line_starts = line_start_offsets(patched_file.lines(), patched_file.line_ending());
+    let applied_change_offsets = patched_file
+        .lines()
+        .iter()
+        .zip(line_starts)
+        .map(|(line, start)| (start, start + line.len()))
+        .collect();
+
+    PatchOutcome {
         patched_file,
+        applied_changes: patch.changes,
         rejected_changes: patch.rejected_changes,
         change_type: patch.change_type,
-    })
+        mode_change: patch.mode_change,
+        applied_change_offsets,
+        matcher_description: None,
+        filter_description: None,
+    }
+}
+
+/// Writes the outcome of a patch application that has been computed without writing (see
+/// `apply_without_writing`): a created or modified file is written and its mode is changed, and a
+/// removed file is removed. A file whose patch only changes the mode keeps its content. Nothing is
+/// written if the entire patch has been rejected because of the state of the target file, or if a
+/// created file has been emptied by rejects.
+pub(crate) fn write_outcome(
+    patch_outcome: &PatchOutcome,
+    options: &PatchOptions,
+) -> Result<(), Error> {
+    let path = patch_outcome.patched_file.path();
+    let target_exists = path.exists();
+    let (applied_changes, rejected_changes) = (
+        &patch_outcome.applied_changes,
+        &patch_outcome.rejected_changes,
+    );
+    match patch_outcome.change_type {
+        FileChangeType::Remove => {
+            if target_exists {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        FileChangeType::Create => {
+            if target_exists || (applied_changes.is_empty() && !rejected_changes.is_empty()) {
+                return Ok(());
+            }
+            // Create all parent directories
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            patch_outcome.patched_file.write()?;
+        }
+        FileChangeType::Modify => {
+            if !target_exists {
+                return Ok(());
+            }
+            let is_without_changes = applied_changes.is_empty() && rejected_changes.is_empty();
+            if !is_without_changes {
+                let is_symlink = fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink());
+                if is_symlink && options.symlink_policy == SymlinkPolicy::Replace {
+                    // Remove the link, so that a regular file is written in its place
+                    fs::remove_file(path)?;
+                }
+                patch_outcome.patched_file.write()?;
+            }
+        }
+    }
+    if let Some(mode) = patch_outcome.mode_change {
+        set_mode(path, mode)?;
+    }
+    Ok(())
+}
+
+/// Sets the permission bits of the file under the given path. File modes only exist on Unix; on
+/// other platforms, the mode is not changed.
+fn set_mode(path: &Path, mode: u32) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
 }
 
 /// Applies the removal of an existing file.
-fn apply_file_removal(patch: AlignedPatch, dryrun: bool) -> Result<PatchOutcome, Error> {
+fn apply_file_removal(patch: AlignedPatch) -> PatchOutcome {
     // there are no lines in the removed file
     let path = patch.target.path().to_path_buf();
 
-    if !dryrun {
-        fs::remove_file(&path)?;
-    }
-
-    Ok(PatchOutcome {
+    PatchOutcome {
         patched_file: FileArtifact::from_lines(path, vec![]),
+        // All removed lines are located at the start of the empty file
+        applied_change_offsets: vec![(0, 0); patch.changes.len()],
+        applied_changes: patch.changes,
         rejected_changes: patch.rejected_changes,
         change_type: patch.change_type,
-    })
+        mode_change: None,
+        matcher_description: None,
+        filter_description: None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{fs, path::PathBuf};
 
     use crate::{
-        patch::{Change, LineChangeType},
-        AlignedPatch, FileArtifact, FilePatch, VersionDiff,
+        patch::{
+            Change, FinalNewline, LineChangeType, PlacementStrategy, RejectReason, SymlinkPolicy,
+            WhitespaceMode,
+        },
+        AlignedPatch, ErrorKind, FileArtifact, FilePatch, PatchOptions, VersionDiff,
     };
 
     #[test]
@@ -188,19 +779,20 @@ mod tests {
         let file_diff = file_diff.file_diffs().first().unwrap().clone();
         let patch = FilePatch::from(file_diff);
         let mut patch = AlignedPatch {
-            changes: patch.changes,
-            rejected_changes: vec![Change {
-                line: "additional reject".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 99,
-                change_id: 4,
-            }],
-            target: FileArtifact::new(PathBuf::from("empty")),
-            change_type: super::FileChangeType::Modify,
+            rejected_changes: vec![Change::test_add("additional reject", 99, 4)],
+            ..AlignedPatch::for_test(patch.changes, FileArtifact::new(PathBuf::from("empty")))
         };
 
-        super::reject_all(&mut patch);
+        super::reject_all(&mut patch, RejectReason::TargetMissing);
         assert_eq!(5, patch.rejected_changes.len());
+        assert_eq!(
+            4,
+            patch
+                .rejected_changes
+                .iter()
+                .filter(|c| c.reject_reason() == Some(RejectReason::TargetMissing))
+                .count()
+        );
     }
 
     #[test]
@@ -210,29 +802,14 @@ mod tests {
             vec!["first line".to_string()],
         );
         let changes = vec![
-            Change {
-                line: "second line".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 2,
-                change_id: 0,
-            },
-            Change {
-                line: "third line".to_string(),
-                change_type: LineChangeType::Add,
-                line_number: 2,
-                change_id: 1,
-            },
+            Change::test_add("second line", 2, 0),
+            Change::test_add("third line", 2, 1),
         ];
 
-        let patch = AlignedPatch {
-            changes,
-            rejected_changes: vec![],
-            target: artifact,
-            change_type: super::FileChangeType::Modify,
-        };
+        let patch = AlignedPatch::for_test(changes, artifact);
 
-        let patch_outcome = super::apply_patch(patch, true).unwrap();
-        assert!(patch_outcome.rejected_changes().is_empty());
+        let patch_outcome = patch.apply_dryrun().unwrap();
+        assert!(patch_outcome.is_clean());
 
         let patched_file = patch_outcome.patched_file();
         assert_eq!(3, patched_file.len());
@@ -242,26 +819,546 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "there were unprocessed changes")]
     fn try_to_remove_lines_after_end() {
         let artifact = FileArtifact::from_lines(
             PathBuf::from("tests/samples/target_variant/version-0/main.c"),
             vec!["first line".to_string()],
         );
-        let changes = vec![Change {
-            line: "second line".to_string(),
-            change_type: LineChangeType::Remove,
-            line_number: 2,
-            change_id: 0,
-        }];
+        let change = Change::test_remove("second line", 2, 0);
+
+        let patch = AlignedPatch::for_test(vec![change.clone()], artifact);
+
+        let error = patch.apply_dryrun().unwrap_err();
+        assert_eq!(ErrorKind::PatchError, *error.kind());
+        assert_eq!(Some(&change), error.change());
+    }
+
+    #[test]
+    fn try_to_remove_unexpected_line() {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec!["first line".to_string()],
+        );
+        let change = Change::test_remove("other line", 1, 3);
+
+        let patch = AlignedPatch::for_test(vec![change.clone()], artifact);
+
+        let error = patch.apply_dryrun().unwrap_err();
+        assert_eq!(ErrorKind::PatchError, *error.kind());
+        assert_eq!("unexpected line difference in line 1", error.message());
+        assert_eq!(Some(&change), error.change());
+    }
+
+    #[test]
+    fn reject_missing_removed_lines_leniently() {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            ["int a;", "int b;", "int extra;", "int c;", "int d;"]
+                .map(String::from)
+                .to_vec(),
+        );
+        // The block b, c, d has been aligned without accounting for the extra line
+        let removes = [("int b;", 2), ("int c;", 3), ("int d;", 4), ("int e;", 6)];
+        let changes: Vec<Change> = removes
+            .iter()
+            .enumerate()
+            .map(|(change_id, (line, line_number))| {
+                Change::test_remove(line, *line_number, change_id)
+            })
+            .collect();
+
+        let patch = AlignedPatch::for_test(changes.clone(), artifact);
+        let options = PatchOptions {
+            dryrun: true,
+            lenient_removes: true,
+            ..Default::default()
+        };
+
+        let patch_outcome = super::apply_patch_with_options(patch, &options).unwrap();
+        assert_eq!(
+            ["int a;", "int extra;", "int c;", "int d;"],
+            patch_outcome.patched_file().lines()
+        );
+        assert_eq!(&changes[..1], patch_outcome.applied_changes());
+        let rejects = patch_outcome.rejected_changes();
+        assert_eq!(3, rejects.len());
+        for (reject, change) in rejects.iter().zip(&changes[1..]) {
+            assert_eq!(change.line(), reject.line());
+            assert_eq!(Some(RejectReason::LineMismatch), reject.reject_reason());
+        }
+    }
+
+    fn trailing_whitespace_patch() -> AlignedPatch {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec!["{".to_string(), "}".to_string()],
+        );
+        let add = |line: &str, change_id| Change::test_add(line, 2, change_id);
+        AlignedPatch::for_test(vec![add("call(); \t", 0), add("other();", 1)], artifact)
+    }
+
+    #[test]
+    fn fix_trailing_whitespace() {
+        let options = PatchOptions {
+            dryrun: true,
+            whitespace: WhitespaceMode::Fix,
+            ..Default::default()
+        };
+        let patch_outcome =
+            super::apply_patch_with_options(trailing_whitespace_patch(), &options).unwrap();
+        assert!(patch_outcome.is_clean());
+        assert_eq!(
+            ["{", "call();", "other();", "}"],
+            patch_outcome.patched_file().lines()
+        );
+        assert_eq!("call();", patch_outcome.applied_changes()[0].line());
+    }
+
+    #[test]
+    fn reject_trailing_whitespace() {
+        let options = PatchOptions {
+            dryrun: true,
+            whitespace: WhitespaceMode::Error,
+            ..Default::default()
+        };
+        let patch_outcome =
+            super::apply_patch_with_options(trailing_whitespace_patch(), &options).unwrap();
+        assert_eq!(["{", "other();", "}"], patch_outcome.patched_file().lines());
+        let rejects = patch_outcome.rejected_changes();
+        assert_eq!(1, rejects.len());
+        assert_eq!("call(); \t", rejects[0].line());
+        assert_eq!(Some(RejectReason::Whitespace), rejects[0].reject_reason());
+    }
+
+    #[test]
+    fn resolve_rejects_with_callback() {
+        let options = PatchOptions {
+            dryrun: true,
+            whitespace: WhitespaceMode::Error,
+            ..Default::default()
+        };
+        let mut resolved = vec![];
+        let patch_outcome =
+            super::apply_patch_with_resolver(trailing_whitespace_patch(), &options, |change, _| {
+                resolved.push(change.line().to_string());
+                super::Resolution::ForceApply
+            })
+            .unwrap();
+        assert!(patch_outcome.is_clean());
+        assert_eq!(["call(); \t"], resolved.as_slice());
+        assert_eq!(
+            ["{", "call(); \t", "other();", "}"],
+            patch_outcome.patched_file().lines()
+        );
+
+        let patch_outcome =
+            super::apply_patch_with_resolver(trailing_whitespace_patch(), &options, |_, _| {
+                super::Resolution::Edit("call();".to_string())
+            })
+            .unwrap();
+        assert!(patch_outcome.is_clean());
+        assert_eq!(
+            ["{", "call();", "other();", "}"],
+            patch_outcome.patched_file().lines()
+        );
+    }
+
+    #[test]
+    fn insert_lines_with_line_ending_of_target() {
+        let dir = std::env::temp_dir().join("mpatch_insert_lines_with_line_ending_of_target");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.c");
+        fs::write(&path, "{\r\n}\r\n").unwrap();
+        let mut patch = trailing_whitespace_patch();
+        patch.target = FileArtifact::read(&path).unwrap();
+
+        let options = PatchOptions::builder()
+            .normalize_inserted_endings(true)
+            .build();
+        let patch_outcome = patch.apply(&options).unwrap();
+        assert!(patch_outcome.is_clean());
+        assert_eq!(
+            "{\r\ncall(); \t\r\nother();\r\n}",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_final_newline_as_configured() {
+        for (final_newline, expected) in [
+            (FinalNewline::Preserve, "a\nb\nc\nd\ne"),
+            (FinalNewline::Always, "a\nb\nc\nd\ne\n"),
+            (FinalNewline::Never, "a\nb\nc\nd\ne"),
+        ] {
+            // The target has no final newline and the added line is appended
+            let target = many_patches_target("mpatch_write_final_newline_as_configured.c");
+            let patch = aligned_patch(&target, &[("e", LineChangeType::Add, 5)]);
+            let options = PatchOptions::builder().final_newline(final_newline).build();
+            patch.apply(&options).unwrap();
+            assert_eq!(expected, fs::read_to_string(target.path()).unwrap());
+        }
+
+        // A target with a final newline keeps it if it is preserved
+        let path = std::env::temp_dir().join("mpatch_write_final_newline_as_configured.c");
+        fs::write(&path, "a\nb\nc\nd\n").unwrap();
+        let target = FileArtifact::read(&path).unwrap();
+        let patch = aligned_patch(&target, &[("e", LineChangeType::Add, 5)]);
+        let options = PatchOptions::builder()
+            .final_newline(FinalNewline::Preserve)
+            .build();
+        patch.apply(&options).unwrap();
+        assert_eq!("a\nb\nc\nd\ne\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn report_byte_offsets_of_applied_changes() {
+        let target = many_patches_target("mpatch_report_byte_offsets_of_applied_changes.c");
+        let patch = aligned_patch(
+            &target,
+            &[
+                ("b", LineChangeType::Remove, 2),
+                ("x", LineChangeType::Add, 3),
+                ("yy", LineChangeType::Add, 5),
+            ],
+        );
+
+        let patch_outcome = patch.apply_dryrun().unwrap();
+        let content = patch_outcome.patched_file().to_string();
+        assert_eq!("a\nx\nc\nd\nyy", content);
+        assert_eq!(
+            [(2, 2), (2, 3), (8, 10)],
+            patch_outcome.applied_change_offsets()
+        );
+        for (change, &(start, end)) in patch_outcome
+            .applied_changes()
+            .iter()
+            .zip(patch_outcome.applied_change_offsets())
+        {
+            match change.change_type() {
+                LineChangeType::Add => assert_eq!(change.line(), &content[start..end]),
+                LineChangeType::Remove => assert_eq!(start, end),
+            }
+        }
+        fs::remove_file(target.path()).unwrap();
+    }
+
+    #[test]
+    fn ignore_trailing_comment_of_removed_line() {
+        let remove =
+            |line: &str, line_number, change_id| Change::test_remove(line, line_number, change_id);
+        let patch = || {
+            AlignedPatch::for_test(
+                vec![remove("call();", 2, 0), remove("", 3, 1)],
+                FileArtifact::from_lines(
+                    PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+                    vec![
+                        "{".to_string(),
+                        "call(); // keep calling".to_string(),
+                        "# only a comment".to_string(),
+                        "}".to_string(),
+                    ],
+                ),
+            )
+        };
+
+        // By default, the trailing comment blocks the removal
+        let options = PatchOptions::builder().dryrun(true).lenient_removes(true);
+        let patch_outcome = patch().apply(&options.clone().build()).unwrap();
+        assert_eq!(2, patch_outcome.rejected_changes().len());
+
+        let options = options.comment_prefixes(["//", "#"]).build();
+        let patch_outcome = patch().apply(&options).unwrap();
+        assert_eq!(
+            ["{", "# only a comment", "}"],
+            patch_outcome.patched_file().lines()
+        );
+        // A line that only consists of a comment does not match an empty removed line
+        assert_eq!(1, patch_outcome.rejected_changes().len());
+        assert_eq!("", patch_outcome.rejected_changes()[0].line());
+    }
+
+    fn many_patches_target(file_name: &str) -> FileArtifact {
+        let path = std::env::temp_dir().join(file_name);
+        fs::write(&path, "a\nb\nc\nd").unwrap();
+        FileArtifact::read(path).unwrap()
+    }
+
+    fn aligned_patch(
+        target: &FileArtifact,
+        changes: &[(&str, LineChangeType, usize)],
+    ) -> AlignedPatch {
+        AlignedPatch::for_test(
+            changes
+                .iter()
+                .enumerate()
+                .map(|(change_id, (line, change_type, line_number))| {
+                    Change::for_test(line, *change_type, *line_number, change_id)
+                })
+                .collect(),
+            target.clone(),
+        )
+    }
+
+    #[test]
+    fn apply_many_independent_patches() {
+        let target = many_patches_target("mpatch_apply_many_independent_patches.c");
+        let patches = vec![
+            aligned_patch(
+                &target,
+                &[
+                    ("b", LineChangeType::Remove, 2),
+                    ("x", LineChangeType::Add, 3),
+                ],
+            ),
+            // The identical remove of line 2 is only applied once
+            aligned_patch(
+                &target,
+                &[
+                    ("b", LineChangeType::Remove, 2),
+                    ("y", LineChangeType::Add, 5),
+                ],
+            ),
+        ];
+
+        let (outcome, conflicts) =
+            super::apply_many(target.clone(), patches, &PatchOptions::default()).unwrap();
+        assert!(conflicts.is_empty());
+        assert!(outcome.is_clean());
+        assert_eq!(["a", "x", "c", "d", "y"], outcome.patched_file().lines());
+        assert_eq!(3, outcome.applied_changes().len());
+        fs::remove_file(target.path()).unwrap();
+    }
+
+    #[test]
+    fn apply_many_conflicting_patches() {
+        let target = many_patches_target("mpatch_apply_many_conflicting_patches.c");
+        let patches = vec![
+            aligned_patch(
+                &target,
+                &[
+                    ("x", LineChangeType::Add, 2),
+                    ("c", LineChangeType::Remove, 3),
+                ],
+            ),
+            aligned_patch(&target, &[("y", LineChangeType::Add, 2)]),
+        ];
+
+        let (outcome, conflicts) =
+            super::apply_many(target.clone(), patches, &PatchOptions::default()).unwrap();
+        // Only the remove, which does not collide, is applied
+        assert_eq!(["a", "b", "d"], outcome.patched_file().lines());
+        assert!(!outcome.is_clean());
+        let rejects: Vec<(&str, Option<RejectReason>)> = outcome
+            .rejected_changes()
+            .iter()
+            .map(|r| (r.line(), r.reject_reason()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("x", Some(RejectReason::Conflict)),
+                ("y", Some(RejectReason::Conflict))
+            ],
+            rejects
+        );
+        assert_eq!(1, conflicts.len());
+        let conflict = &conflicts[0];
+        assert_eq!(2, conflict.line_number());
+        assert_eq!(LineChangeType::Add, conflict.change_type());
+        let lines: Vec<(usize, &str)> = conflict
+            .changes()
+            .iter()
+            .map(|(index, changes)| (*index, changes[0].line()))
+            .collect();
+        assert_eq!(vec![(0, "x"), (1, "y")], lines);
+        fs::remove_file(target.path()).unwrap();
+    }
 
+    #[test]
+    fn create_intentionally_empty_file() {
+        let path = std::env::temp_dir().join("mpatch_intentionally_empty_file.c");
+        let _ = fs::remove_file(&path);
         let patch = AlignedPatch {
-            changes,
-            rejected_changes: vec![],
-            target: artifact,
-            change_type: super::FileChangeType::Modify,
+            change_type: super::FileChangeType::Create,
+            ..AlignedPatch::for_test(vec![], FileArtifact::new(path.clone()))
+        };
+
+        let patch_outcome = patch.apply(&PatchOptions::default()).unwrap();
+        assert!(patch_outcome.is_clean());
+        assert!(patch_outcome.patched_file().is_empty());
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skip_creation_of_filtered_file() {
+        let path = std::env::temp_dir().join("mpatch_filtered_file.c");
+        let _ = fs::remove_file(&path);
+        let patch = AlignedPatch {
+            rejected_changes: vec![Change::test_add("filtered line", 0, 0)],
+            change_type: super::FileChangeType::Create,
+            ..AlignedPatch::for_test(vec![], FileArtifact::new(path.clone()))
         };
 
-        super::apply_patch(patch, true).unwrap();
+        let patch_outcome = patch.apply(&PatchOptions::default()).unwrap();
+        assert_eq!(1, patch_outcome.rejected_changes().len());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn indent_added_lines() {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec!["{".to_string(), "}".to_string()],
+        );
+        let changes = vec![Change::test_add("call();", 2, 0)];
+        let patch = AlignedPatch::for_test(changes, artifact);
+        let options = PatchOptions::builder()
+            .add_line_indent("    ")
+            .dryrun(true)
+            .build();
+
+        let patch_outcome = super::apply_patch_with_options(patch, &options).unwrap();
+        assert_eq!(
+            ["{", "    call();", "}"],
+            patch_outcome.patched_file().lines()
+        );
+    }
+
+    #[test]
+    fn relocate_remove_of_duplicate_line() {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec![
+                "// duplicate".to_string(),
+                "int a;".to_string(),
+                "int b;".to_string(),
+                "// duplicate".to_string(),
+                "int c;".to_string(),
+            ],
+        );
+        // The alignment anchored the removal next to the closer instance of the duplicated line
+        let changes = vec![Change::test_remove("// duplicate", 3, 0)];
+        let patch = AlignedPatch::for_test(changes, artifact);
+
+        let options = PatchOptions::builder()
+            .placement(PlacementStrategy::Nearby(3))
+            .dryrun(true)
+            .build();
+        let outcome = patch.apply(&options).unwrap();
+        assert_eq!(
+            &["// duplicate", "int a;", "int b;", "int c;"],
+            outcome.patched_file().lines()
+        );
+    }
+
+    #[test]
+    fn remove_annotated_occurrence_of_duplicate_line() {
+        let artifact = FileArtifact::from_lines(
+            PathBuf::from("tests/samples/target_variant/version-0/main.c"),
+            vec![
+                "int a;".to_string(),
+                "// duplicate".to_string(),
+                "int b;".to_string(),
+                "// duplicate".to_string(),
+                "int c;".to_string(),
+            ],
+        );
+        // The alignment anchored the removal at the first instance of the duplicated line
+        let remove = Change::test_remove("// duplicate", 2, 0);
+        let patch = |remove: Change| AlignedPatch::for_test(vec![remove], artifact.clone());
+
+        let options = PatchOptions::builder().dryrun(true).build();
+        let outcome = patch(remove.clone().with_occurrence(2))
+            .apply(&options)
+            .unwrap();
+        assert!(outcome.is_clean());
+        assert_eq!(
+            &["int a;", "// duplicate", "int b;", "int c;"],
+            outcome.patched_file().lines()
+        );
+
+        // The second occurrence lies outside of the region of the nearby placement, so that the
+        // remove keeps its location
+        let options = PatchOptions::builder()
+            .placement(PlacementStrategy::Nearby(1))
+            .dryrun(true)
+            .build();
+        let outcome = patch(remove.with_occurrence(2)).apply(&options).unwrap();
+        assert_eq!(
+            &["int a;", "int b;", "// duplicate", "int c;"],
+            outcome.patched_file().lines()
+        );
+    }
+
+    /// Creates a target file with a single line and a symbolic link to it in a fresh directory.
+    /// Returns the destination, the link, and a patch for the link that appends a line.
+    #[cfg(unix)]
+    fn symlinked_target(dir_name: &str) -> (PathBuf, PathBuf, AlignedPatch) {
+        let dir = std::env::temp_dir().join(dir_name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let destination = dir.join("destination.c");
+        let link = dir.join("link.c");
+        fs::write(&destination, "int a;").unwrap();
+        std::os::unix::fs::symlink(&destination, &link).unwrap();
+
+        let patch = AlignedPatch::for_test(
+            vec![Change::test_add("int b;", 2, 0)],
+            FileArtifact::read(&link).unwrap(),
+        );
+        (destination, link, patch)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn follow_symlinked_target() {
+        let (destination, link, patch) = symlinked_target("mpatch_follow_symlinked_target");
+        let options = PatchOptions::builder()
+            .symlink_policy(SymlinkPolicy::Follow)
+            .build();
+        patch.apply(&options).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!("int a;\nint b;", fs::read_to_string(&destination).unwrap());
+        fs::remove_dir_all(destination.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuse_symlinked_target() {
+        let (destination, link, patch) = symlinked_target("mpatch_refuse_symlinked_target");
+        let options = PatchOptions::builder()
+            .symlink_policy(SymlinkPolicy::Refuse)
+            .build();
+        let error = patch.clone().apply(&options).unwrap_err();
+        assert_eq!(ErrorKind::PatchError, *error.kind());
+        // A dryrun is refused as well
+        let options = PatchOptions {
+            dryrun: true,
+            ..options
+        };
+        assert!(patch.apply(&options).is_err());
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!("int a;", fs::read_to_string(&destination).unwrap());
+        fs::remove_dir_all(destination.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_symlinked_target() {
+        let (destination, link, patch) = symlinked_target("mpatch_replace_symlinked_target");
+        let options = PatchOptions::builder()
+            .symlink_policy(SymlinkPolicy::Replace)
+            .build();
+        patch.apply(&options).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_file());
+        assert_eq!("int a;\nint b;", fs::read_to_string(&link).unwrap());
+        assert_eq!("int a;", fs::read_to_string(&destination).unwrap());
+        fs::remove_dir_all(destination.parent().unwrap()).unwrap();
     }
 }
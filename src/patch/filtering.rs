@@ -1,9 +1,35 @@
-use crate::{FilePatch, Matching};
+use std::collections::HashSet;
 
-use super::{Change, FilteredPatch, LineChangeType};
+use crate::{Error, FilePatch, Matching};
+
+use super::{describe_type, Change, FilteredPatch, LineChangeType, RejectReason};
 
 pub trait Filter {
-    fn apply_filter(&mut self, patch: FilePatch, matching: &Matching) -> FilteredPatch;
+    /// Filters the changes of the given patch, rejecting the changes that should not be applied.
+    ///
+    /// ## Error
+    /// Returns an Error of kind FilterError if the filter cannot be applied, e.g., because its
+    /// parameters are invalid.
+    fn apply_filter(
+        &mut self,
+        patch: FilePatch,
+        matching: &Matching,
+    ) -> Result<FilteredPatch, Error>;
+
+    /// Returns true if this filter considers the matching between source and target file. Filters
+    /// that do not require the matching might be applied with an empty matching before the source
+    /// and target file are read, so that no files are read for patches whose changes are all
+    /// rejected.
+    fn requires_matching(&self) -> bool {
+        true
+    }
+
+    /// Returns a description of this filter and its parameters, which is recorded in the
+    /// outcomes of patch applications for provenance. By default, this is the name of the type
+    /// of the filter.
+    fn describe(&self) -> String {
+        describe_type::<Self>()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -26,7 +52,11 @@ impl DistanceFilter {
 }
 
 impl Filter for DistanceFilter {
-    fn apply_filter(&mut self, patch: FilePatch, matching: &Matching) -> FilteredPatch {
+    fn apply_filter(
+        &mut self,
+        patch: FilePatch,
+        matching: &Matching,
+    ) -> Result<FilteredPatch, Error> {
         let mut changes = vec![];
         let mut rejected_changes = vec![];
 
@@ -34,14 +64,19 @@ impl Filter for DistanceFilter {
             if self.keep_change(&c, matching) {
                 changes.push(c);
             } else {
-                rejected_changes.push(c);
+                rejected_changes.push(c.into_reject(RejectReason::Filtered));
             };
         });
-        FilteredPatch {
+        Ok(FilteredPatch {
             change_type: patch.change_type,
+            mode_change: patch.mode_change,
             changes,
             rejected_changes,
-        }
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("DistanceFilter({})", self.0)
     }
 }
 
@@ -49,11 +84,146 @@ impl Filter for DistanceFilter {
 pub struct KeepAllFilter;
 
 impl Filter for KeepAllFilter {
-    fn apply_filter(&mut self, patch: FilePatch, _: &Matching) -> FilteredPatch {
-        FilteredPatch {
+    fn apply_filter(&mut self, patch: FilePatch, _: &Matching) -> Result<FilteredPatch, Error> {
+        Ok(FilteredPatch {
             changes: patch.changes,
             change_type: patch.change_type,
+            mode_change: patch.mode_change,
             rejected_changes: vec![],
+        })
+    }
+
+    fn requires_matching(&self) -> bool {
+        false
+    }
+}
+
+/// The configuration of the target variant that is patched. It consists of the features that
+/// are enabled in the target variant.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetConfiguration {
+    features: HashSet<String>,
+}
+
+impl TargetConfiguration {
+    /// Creates a new TargetConfiguration in which the given features are enabled.
+    pub fn new<S: Into<String>>(features: impl IntoIterator<Item = S>) -> TargetConfiguration {
+        TargetConfiguration {
+            features: features.into_iter().map(Into::into).collect(),
         }
     }
+
+    /// Returns true if the given feature is enabled in this configuration.
+    pub fn is_enabled(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+/// A ChangeFilter decides whether a single change is applicable to the given target
+/// configuration.
+pub trait ChangeFilter {
+    fn is_applicable(&self, change: &Change, config: &TargetConfiguration) -> bool;
+}
+
+impl<F> ChangeFilter for F
+where
+    F: Fn(&Change, &TargetConfiguration) -> bool,
+{
+    fn is_applicable(&self, change: &Change, config: &TargetConfiguration) -> bool {
+        self(change, config)
+    }
+}
+
+/// A ChangeFilter that considers a change applicable if all of its feature tags are enabled in the
+/// target configuration. Changes without feature tags are always applicable. See
+/// `FilePatch::from_file_diff` for how the tags are extracted.
+#[derive(Debug)]
+pub struct FeatureTagFilter;
+
+impl ChangeFilter for FeatureTagFilter {
+    fn is_applicable(&self, change: &Change, config: &TargetConfiguration) -> bool {
+        change
+            .feature_tags()
+            .iter()
+            .all(|feature| config.is_enabled(feature))
+    }
+}
+
+/// A Filter that rejects all changes that are not applicable to the target configuration
+/// according to the given ChangeFilter.
+#[derive(Debug)]
+pub struct ConfigurationFilter<C: ChangeFilter> {
+    change_filter: C,
+    config: TargetConfiguration,
+}
+
+impl<C: ChangeFilter> ConfigurationFilter<C> {
+    pub fn new(change_filter: C, config: TargetConfiguration) -> ConfigurationFilter<C> {
+        ConfigurationFilter {
+            change_filter,
+            config,
+        }
+    }
+}
+
+impl<C: ChangeFilter> Filter for ConfigurationFilter<C> {
+    fn apply_filter(&mut self, patch: FilePatch, _: &Matching) -> Result<FilteredPatch, Error> {
+        let (changes, rejected_changes): (Vec<Change>, Vec<Change>) = patch
+            .changes
+            .into_iter()
+            .partition(|c| self.change_filter.is_applicable(c, &self.config));
+        Ok(FilteredPatch {
+            change_type: patch.change_type,
+            mode_change: patch.mode_change,
+            changes,
+            rejected_changes: rejected_changes
+                .into_iter()
+                .map(|c| c.into_reject(RejectReason::Filtered))
+                .collect(),
+        })
+    }
+
+    fn requires_matching(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{patch::Change, FileArtifact, FilePatch, Matching};
+
+    use super::{ConfigurationFilter, FeatureTagFilter, Filter, TargetConfiguration};
+
+    #[test]
+    fn gate_changes_by_feature_tag() {
+        let change = |line: &str, change_id, feature_tags: &[&str]| Change {
+            feature_tags: feature_tags.iter().map(|t| t.to_string()).collect(),
+            ..Change::test_add(line, 1, change_id)
+        };
+        let patch = FilePatch::for_test(vec![
+            change("untagged();", 0, &[]),
+            change("logging(); // #feature:LOGGING", 1, &["LOGGING"]),
+            change(
+                "debug(); // #feature:LOGGING #feature:DEBUG",
+                2,
+                &["LOGGING", "DEBUG"],
+            ),
+        ]);
+        let matching = Matching::new(
+            FileArtifact::new(PathBuf::from("source")),
+            FileArtifact::new(PathBuf::from("target")),
+            vec![],
+            vec![],
+        );
+
+        let mut filter =
+            ConfigurationFilter::new(FeatureTagFilter, TargetConfiguration::new(["LOGGING"]));
+        let filtered = filter.apply_filter(patch, &matching).unwrap();
+
+        let ids = |changes: &[Change]| changes.iter().map(|c| c.change_id).collect::<Vec<_>>();
+        assert_eq!(vec![0, 1], ids(&filtered.changes));
+        assert_eq!(vec![2], ids(&filtered.rejected_changes));
+    }
 }
@@ -1,6 +1,15 @@
-use similar::{Change, TextDiff};
+use std::{collections::HashMap, ops::Range, path::Path};
 
-use crate::io::FileArtifact;
+#[cfg(feature = "regex")]
+use regex::Regex;
+use similar::{capture_diff_slices, Algorithm, Change, DiffOp as SimilarDiffOp, TextDiff};
+
+use crate::{
+    diffs::{FileDiff, LineType, VersionDiff},
+    io::{FileArtifact, StrippedPath},
+};
+
+use super::describe_type;
 
 /// A trait for defining a common interface for matchers that match lines between two files.
 ///
@@ -125,6 +134,20 @@ pub trait Matcher {
     /// }
     ///# }
     fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching;
+
+    /// Determines the matching between the two given files without taking ownership of them.
+    /// The files are cloned, so that the returned matching owns its own copies; this allows
+    /// matching the same files repeatedly, e.g., with different matchers.
+    fn match_files_ref(&mut self, source: &FileArtifact, target: &FileArtifact) -> Matching {
+        self.match_files(source.clone(), target.clone())
+    }
+
+    /// Returns a description of this matcher and its parameters, which is recorded in the
+    /// outcomes of patch applications for provenance. By default, this is the name of the type
+    /// of the matcher.
+    fn describe(&self) -> String {
+        describe_type::<Self>()
+    }
 }
 
 /// A matching holds the information about lines that have been matched between a source and a
@@ -142,6 +165,22 @@ pub struct Matching {
     target_to_source: Vec<MatchId>,
 }
 
+/// The matching algorithms that can be selected for `Matching::compute`, e.g., from a
+/// configuration. Each algorithm corresponds to one of the matchers in this module; matchers that
+/// require further input, such as the diff of a ContextMatcher, are not available here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchAlgorithm {
+    /// Matches the files with an LCSMatcher.
+    #[default]
+    Lcs,
+    /// Matches the files with a BlankLineCollapsingMatcher.
+    BlankLineCollapsing,
+    /// Matches the files with a ContextHashMatcher with the given radius.
+    ContextHash(usize),
+    /// Matches the files with a UniqueExactMatcher.
+    UniqueExact,
+}
+
 /// A MatchId is simply an `Option<usize>` where the usize is a line number in the interval \[1,n\].
 pub type MatchId = Option<usize>;
 
@@ -170,6 +209,26 @@ impl Matching {
         }
     }
 
+    /// Computes the matching of the given source and target files with the given algorithm. This
+    /// is a shorthand for instantiating the corresponding matcher and calling
+    /// `Matcher::match_files`.
+    pub fn compute(
+        source: FileArtifact,
+        target: FileArtifact,
+        algorithm: MatchAlgorithm,
+    ) -> Matching {
+        match algorithm {
+            MatchAlgorithm::Lcs => LCSMatcher.match_files(source, target),
+            MatchAlgorithm::BlankLineCollapsing => {
+                BlankLineCollapsingMatcher.match_files(source, target)
+            }
+            MatchAlgorithm::ContextHash(radius) => {
+                ContextHashMatcher::new(radius).match_files(source, target)
+            }
+            MatchAlgorithm::UniqueExact => UniqueExactMatcher.match_files(source, target),
+        }
+    }
+
     /// Returns the match in the target file for a line number of the source file.
     ///
     /// ## Input
@@ -228,6 +287,75 @@ impl Matching {
         self.target
     }
 
+    /// Consumes this matching and returns its inverse, in which the roles of source and target
+    /// are swapped. This means that the target index of a line in the inverted matching is the
+    /// source index of the same line in this matching, and vice versa.
+    pub fn inverted(self) -> Matching {
+        Matching {
+            source: self.target,
+            target: self.source,
+            source_to_target: self.target_to_source,
+            target_to_source: self.source_to_target,
+        }
+    }
+
+    /// Returns the ratio of matched lines to all lines of the source and target file. The ratio
+    /// is 1.0 if every line has a match, and 0.0 if no line has a match or both files are empty.
+    /// The ratio can be used to compare how well different source files match the same target.
+    pub fn match_ratio(&self) -> f64 {
+        let line_count = self.source_to_target.len() + self.target_to_source.len();
+        if line_count == 0 {
+            return 0.0;
+        }
+        let match_count = self
+            .source_to_target
+            .iter()
+            .chain(&self.target_to_source)
+            .filter(|m| m.is_some())
+            .count();
+        match_count as f64 / line_count as f64
+    }
+
+    /// Returns the contiguous regions of the target file whose lines have no match in the source
+    /// file, e.g., blocks of lines that have been inserted into the target. Each region is an
+    /// inclusive range of line numbers `(first, last)`. The regions are ordered by their position
+    /// in the target file.
+    pub fn unmatched_target_regions(&self) -> Vec<(usize, usize)> {
+        let mut regions: Vec<(usize, usize)> = vec![];
+        for (index, source) in self.target_to_source.iter().enumerate() {
+            if source.is_some() {
+                continue;
+            }
+            let line_number = index + 1;
+            match regions.last_mut() {
+                // Extend the region that ends at the previous line
+                Some((_, last)) if *last + 1 == line_number => *last = line_number,
+                _ => regions.push((line_number, line_number)),
+            }
+        }
+        regions
+    }
+
+    /// Returns the matching as CSV with the columns `source_line,target_line`. The first part
+    /// of the CSV contains a row for every line of the source file; the target line is empty if
+    /// the source line has no match. The second part contains a row for every target line that
+    /// has no match in the source file, with an empty source line.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("source_line,target_line\n");
+        for (index, target) in self.source_to_target.iter().enumerate() {
+            match target {
+                Some(target) => csv.push_str(&format!("{},{}\n", index + 1, target + 1)),
+                None => csv.push_str(&format!("{},\n", index + 1)),
+            }
+        }
+        for (index, source) in self.target_to_source.iter().enumerate() {
+            if source.is_none() {
+                csv.push_str(&format!(",{}\n", index + 1));
+            }
+        }
+        csv
+    }
+
     /// Searches for closest line above the given source line that has a match in the target file.
     /// This means considers the source lines above the given line number until a line with a match
     /// in the target file is found. It then returns the match id of the corresponding target line.
@@ -268,6 +396,24 @@ impl Matching {
             }
         }
     }
+
+    /// Searches for the closest line below the given source line that has a match in the target
+    /// file. This is the counterpart of `target_index_fuzzy`: a change that is inserted before
+    /// the given line is inserted before the returned target line instead. If the given line
+    /// number has a match itself, this match is returned.
+    ///
+    /// ## Output
+    /// Returns None if there is no matched line at or below the given line number. Returns
+    /// Some(usize) with the target line number if a match has been found.
+    pub(crate) fn target_index_fuzzy_below(&self, line_number: usize) -> (MatchId, MatchOffset) {
+        // Line numbers start at '1', so there is no valid target index for '0'
+        for candidate in line_number.max(1)..=self.source.len() {
+            if let Some(target_line) = self.target_index(candidate).flatten() {
+                return (Some(target_line), MatchOffset(candidate - line_number));
+            }
+        }
+        (None, MatchOffset(0))
+    }
 }
 
 // The match offset of a fuzzy match search.
@@ -281,19 +427,22 @@ impl LCSMatcher {
     pub fn new() -> Self {
         LCSMatcher
     }
-}
-
-impl Default for LCSMatcher {
-    fn default() -> Self {
-        Self::new()
-    }
-}
 
-impl Matcher for LCSMatcher {
-    fn match_files(&mut self, left: FileArtifact, right: FileArtifact) -> Matching {
+    /// Matches the two files like [`Matcher::match_files`] and additionally returns the diff
+    /// operations computed by the underlying LCS algorithm.
+    ///
+    /// The operations cover the lines of both files in order. They are useful for debugging a
+    /// matching or for building custom visualizations without recomputing the diff. All indices
+    /// are 0-based line indices.
+    pub fn match_with_ops(
+        &mut self,
+        left: FileArtifact,
+        right: FileArtifact,
+    ) -> (Matching, Vec<DiffOp>) {
         let left_text = left.to_string();
         let right_text = right.to_string();
         let text_diff = TextDiff::from_lines(&left_text, &right_text);
+        let ops = text_diff.ops().iter().map(DiffOp::from).collect();
 
         let mut left_to_right = Vec::with_capacity(left.len());
         let mut right_to_left = Vec::with_capacity(right.len());
@@ -352,7 +501,591 @@ impl Matcher for LCSMatcher {
             // Both matched files are empty, there is nothing to match
             (None, None) => { /* do nothing */ }
         }
-        Matching::new(left, right, left_to_right, right_to_left)
+
+        // A file that consists of a single empty line has the same text as an empty file, so that
+        // its line is not part of the textual diff. The line is only matched if the other file
+        // consists of a single empty line as well.
+        let left_unprocessed = left_to_right.len() < left.len();
+        let right_unprocessed = right_to_left.len() < right.len();
+        if left_unprocessed && right_unprocessed {
+            left_to_right.push(Some(0));
+            right_to_left.push(Some(0));
+        } else if left_unprocessed {
+            left_to_right.push(None);
+        } else if right_unprocessed {
+            right_to_left.push(None);
+        }
+        (
+            Matching::new(left, right, left_to_right, right_to_left),
+            ops,
+        )
+    }
+}
+
+impl Default for LCSMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Matcher for LCSMatcher {
+    fn match_files(&mut self, left: FileArtifact, right: FileArtifact) -> Matching {
+        self.match_with_ops(left, right).0
+    }
+}
+
+/// A diff operation computed by the [`LCSMatcher`].
+///
+/// Each operation describes a range of lines in the left (old) and right (new) file. All indices
+/// are 0-based line indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffOp {
+    /// `len` lines starting at `old_index` are equal to `len` lines starting at `new_index`.
+    Equal {
+        old_index: usize,
+        new_index: usize,
+        len: usize,
+    },
+    /// `old_len` lines starting at `old_index` have been deleted. They were located before
+    /// `new_index` in the right file.
+    Delete {
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+    },
+    /// `new_len` lines starting at `new_index` have been inserted before `old_index` of the left
+    /// file.
+    Insert {
+        old_index: usize,
+        new_index: usize,
+        new_len: usize,
+    },
+    /// `old_len` lines starting at `old_index` have been replaced by `new_len` lines starting at
+    /// `new_index`.
+    Replace {
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+        new_len: usize,
+    },
+}
+
+impl From<&SimilarDiffOp> for DiffOp {
+    fn from(op: &SimilarDiffOp) -> Self {
+        match *op {
+            SimilarDiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            },
+            SimilarDiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            },
+            SimilarDiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            },
+            SimilarDiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            },
+        }
+    }
+}
+
+/// A matcher that treats runs of consecutive blank lines as a single blank line while matching.
+///
+/// Variants frequently differ only in the number of blank lines between two blocks of code. With
+/// plain LCS matching, such differences can shift the match of a blank line to an unrelated
+/// location, which in turn mis-anchors changes. This matcher collapses each run of blank lines
+/// (i.e., lines that are empty or only contain whitespace) into a single line before matching.
+/// If two collapsed runs are matched, their blank lines are matched pairwise from the start of
+/// the runs; the surplus lines of the longer run remain unmatched.
+///
+/// The content of the matched files is not modified.
+#[derive(Debug, Default)]
+pub struct BlankLineCollapsingMatcher;
+
+impl BlankLineCollapsingMatcher {
+    /// Creates a new BlankLineCollapsingMatcher
+    pub fn new() -> Self {
+        BlankLineCollapsingMatcher
+    }
+
+    /// Collapses runs of blank lines into a single empty line. Returns the collapsed lines
+    /// together with the index range of the original lines that each collapsed line represents.
+    fn collapse(lines: &[String]) -> (Vec<&str>, Vec<Range<usize>>) {
+        let mut collapsed: Vec<&str> = Vec::with_capacity(lines.len());
+        let mut ranges: Vec<Range<usize>> = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            let is_blank = line.trim().is_empty();
+            match ranges.last_mut() {
+                // Extend the current run of blank lines
+                Some(range) if is_blank && collapsed.last() == Some(&"") => range.end = index + 1,
+                _ => {
+                    collapsed.push(if is_blank { "" } else { line });
+                    ranges.push(index..index + 1);
+                }
+            }
+        }
+        (collapsed, ranges)
+    }
+}
+
+impl Matcher for BlankLineCollapsingMatcher {
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        let mut source_to_target = vec![None; source.len()];
+        let mut target_to_source = vec![None; target.len()];
+
+        let (source_lines, source_ranges) = Self::collapse(source.lines());
+        let (target_lines, target_ranges) = Self::collapse(target.lines());
+
+        for op in capture_diff_slices(Algorithm::Myers, &source_lines, &target_lines) {
+            if let SimilarDiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } = op
+            {
+                for offset in 0..len {
+                    // Match the original lines of both collapsed lines pairwise
+                    let source_range = source_ranges[old_index + offset].clone();
+                    let target_range = target_ranges[new_index + offset].clone();
+                    for (source_index, target_index) in source_range.zip(target_range) {
+                        source_to_target[source_index] = Some(target_index);
+                        target_to_source[target_index] = Some(source_index);
+                    }
+                }
+            }
+        }
+
+        Matching::new(source, target, source_to_target, target_to_source)
+    }
+}
+
+/// A matcher that only matches lines whose surrounding lines match as well.
+///
+/// Common lines such as closing braces or blank lines appear many times in a file, and plain
+/// line-based matching can match any of them with any other. This matcher reduces such spurious
+/// matches by comparing each line together with its neighbors: every line is represented by a
+/// composite key consisting of the line and the `radius` lines before and after it. The keys of
+/// both files are then diffed, and each pair of equal keys results in a match of the two lines at
+/// the center of the keys. At the start and end of a file, the keys contain fewer neighbors.
+///
+/// As a consequence, lines within `radius` lines of a difference between the files remain
+/// unmatched. A radius of 0 matches the files line by line.
+#[derive(Debug, Default)]
+pub struct ContextHashMatcher {
+    radius: usize,
+}
+
+impl ContextHashMatcher {
+    /// Creates a new ContextHashMatcher that considers the given number of neighbors on each side
+    /// of a line.
+    pub fn new(radius: usize) -> Self {
+        ContextHashMatcher { radius }
+    }
+
+    /// Returns the composite key of each line. A key consists of the position of the line within
+    /// its neighborhood and the neighborhood itself.
+    fn keys<'a>(&self, lines: &'a [String]) -> Vec<(usize, &'a [String])> {
+        (0..lines.len())
+            .map(|index| {
+                let start = index.saturating_sub(self.radius);
+                let end = (index + self.radius + 1).min(lines.len());
+                (index - start, &lines[start..end])
+            })
+            .collect()
+    }
+}
+
+impl Matcher for ContextHashMatcher {
+    fn describe(&self) -> String {
+        format!("ContextHashMatcher({})", self.radius)
+    }
+
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        let mut source_to_target = vec![None; source.len()];
+        let mut target_to_source = vec![None; target.len()];
+
+        let source_keys = self.keys(source.lines());
+        let target_keys = self.keys(target.lines());
+        for op in capture_diff_slices(Algorithm::Myers, &source_keys, &target_keys) {
+            if let SimilarDiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } = op
+            {
+                for offset in 0..len {
+                    source_to_target[old_index + offset] = Some(new_index + offset);
+                    target_to_source[new_index + offset] = Some(old_index + offset);
+                }
+            }
+        }
+
+        Matching::new(source, target, source_to_target, target_to_source)
+    }
+}
+
+/// A very conservative matcher that only matches identical lines which are unique in both files.
+///
+/// This matcher intentionally produces many unmatched lines: every line that occurs more than
+/// once in the source or target file (e.g., blank lines, closing braces, or repeated statements)
+/// remains unmatched, even if an LCS-based matcher could match it with high confidence. As a
+/// result, the alignment can only place changes that are anchored by a unique line; all other
+/// changes are rejected. This makes the matcher suitable for cases in which applying a change at
+/// the wrong location is worse than not applying it at all.
+#[derive(Debug, Default)]
+pub struct UniqueExactMatcher;
+
+impl UniqueExactMatcher {
+    /// Creates a new UniqueExactMatcher
+    pub fn new() -> Self {
+        UniqueExactMatcher
+    }
+
+    /// Returns the index of each line that occurs exactly once in the given lines.
+    fn unique_lines(lines: &[String]) -> HashMap<&str, usize> {
+        let mut occurrences: HashMap<&str, Option<usize>> = HashMap::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            occurrences
+                .entry(line.as_str())
+                // A line that occurs a second time is no longer unique
+                .and_modify(|occurrence| *occurrence = None)
+                .or_insert(Some(index));
+        }
+        occurrences
+            .into_iter()
+            .filter_map(|(line, index)| index.map(|index| (line, index)))
+            .collect()
+    }
+}
+
+impl Matcher for UniqueExactMatcher {
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        let mut source_to_target = vec![None; source.len()];
+        let mut target_to_source = vec![None; target.len()];
+
+        let target_lines = Self::unique_lines(target.lines());
+        for (line, source_index) in Self::unique_lines(source.lines()) {
+            if let Some(&target_index) = target_lines.get(line) {
+                source_to_target[source_index] = Some(target_index);
+                target_to_source[target_index] = Some(source_index);
+            }
+        }
+
+        Matching::new(source, target, source_to_target, target_to_source)
+    }
+}
+
+/// A matcher that locates the changes of a diff in the target file by the context lines of the
+/// diff, similar to the classic `patch` tool. This allows patching a target file without access
+/// to the source file from which the diff was created.
+///
+/// Instead of reading the source file, the matcher reconstructs a pseudo-source from the context
+/// and removed lines of the hunks in the diff; all other lines of the source are unknown and
+/// remain unmatched. Each hunk is then located in the target file by searching for an exact
+/// occurrence of its context and removed lines. If there are several occurrences, the one
+/// closest to the expected location (i.e., the location in the source file shifted by the offset
+/// of the previously located hunk) is chosen. Hunks that cannot be located remain unmatched,
+/// which causes their changes to be rejected during alignment.
+///
+/// The file diff of a matched source file is determined by its path: the matcher selects the
+/// file diff whose source path (stripped by any number of leading components) is the longest
+/// suffix of the path of the source file. The content of the given source file is ignored.
+#[derive(Debug, Clone)]
+pub struct ContextMatcher {
+    file_diffs: Vec<FileDiff>,
+}
+
+impl ContextMatcher {
+    /// Creates a new ContextMatcher that locates the changes of the given diff.
+    pub fn new(diff: VersionDiff) -> Self {
+        ContextMatcher {
+            file_diffs: diff.into_iter().collect(),
+        }
+    }
+
+    /// Returns the file diff whose source path is the longest suffix of the given path.
+    fn find_file_diff(&self, source_path: &Path) -> Option<&FileDiff> {
+        self.file_diffs
+            .iter()
+            .filter_map(|file_diff| {
+                let path = file_diff.source_file_header().path_cloned();
+                (0..path.components().count())
+                    .map(|strip| path.strip_cloned(strip))
+                    .find(|stripped| source_path.ends_with(stripped))
+                    .map(|stripped| (stripped.components().count(), file_diff))
+            })
+            .max_by_key(|(suffix_length, _)| *suffix_length)
+            .map(|(_, file_diff)| file_diff)
+    }
+}
+
+impl Matcher for ContextMatcher {
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        // The context and removed lines of each hunk together with their source line numbers
+        let hunks: Vec<Vec<(usize, &str)>> = self
+            .find_file_diff(source.path())
+            .map(|file_diff| file_diff.hunks())
+            .unwrap_or_default()
+            .iter()
+            .map(|hunk| {
+                hunk.lines()
+                    .iter()
+                    .filter(|l| matches!(l.line_type(), LineType::Context | LineType::Remove))
+                    .map(|l| {
+                        let content = l.content().get(1..).unwrap_or_default();
+                        (l.source_line().real_location(), content)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Reconstruct the pseudo-source; lines that are not part of any hunk are left empty
+        let source_length = hunks.iter().flatten().map(|(n, _)| *n).max().unwrap_or(0);
+        let mut source_lines = vec![String::new(); source_length];
+        for &(line_number, line) in hunks.iter().flatten() {
+            source_lines[line_number - 1] = line.to_string();
+        }
+
+        let mut source_to_target = vec![None; source_length];
+        let mut target_to_source = vec![None; target.len()];
+        // The offset by which the previously located hunk is shifted in the target file
+        let mut offset: isize = 0;
+        // Hunks must not overlap; thus, a hunk is only searched after the previous one
+        let mut first_candidate = 0;
+        for lines in hunks.iter().filter(|lines| !lines.is_empty()) {
+            let source_start = lines[0].0 - 1;
+            let expected_start = (source_start as isize + offset).max(0) as usize;
+            let start = target
+                .lines()
+                .windows(lines.len())
+                .enumerate()
+                .skip(first_candidate)
+                .filter(|(_, window)| window.iter().zip(lines).all(|(t, (_, s))| t == s))
+                .map(|(start, _)| start)
+                .min_by_key(|start| start.abs_diff(expected_start));
+            if let Some(start) = start {
+                for (index, (line_number, _)) in lines.iter().enumerate() {
+                    source_to_target[line_number - 1] = Some(start + index);
+                    target_to_source[start + index] = Some(line_number - 1);
+                }
+                offset = start as isize - source_start as isize;
+                first_candidate = start + lines.len();
+            }
+        }
+
+        let source = FileArtifact::from_lines(source.path().to_path_buf(), source_lines);
+        Matching::new(source, target, source_to_target, target_to_source)
+    }
+}
+
+/// The content that replaces ignored lines before they are passed to the inner matcher of an
+/// IgnorePatternMatcher.
+#[cfg(feature = "regex")]
+const IGNORED_LINE_PLACEHOLDER: &str = "\0mpatch:ignored\0";
+
+/// A matcher that ignores lines matching any of a set of patterns while matching.
+///
+/// Auto-generated lines such as timestamps or version stamps often differ between variants and
+/// prevent them from being matched. This matcher wraps another matcher and treats each ignored
+/// line as a wildcard: ignored lines can be matched to each other regardless of their content,
+/// but never to other lines.
+///
+/// Ignored lines are never anchors of the matching. The match of an ignored line is only kept if
+/// the closest lines before or after the two ignored lines that are not ignored are matched to
+/// each other (or if there are no such lines in either file). Thus, wildcards only fill the gaps
+/// between the matches that the inner matcher found for the remaining lines.
+#[cfg(feature = "regex")]
+#[derive(Debug)]
+pub struct IgnorePatternMatcher<M: Matcher> {
+    patterns: Vec<Regex>,
+    inner: M,
+}
+
+#[cfg(feature = "regex")]
+impl<M: Matcher> IgnorePatternMatcher<M> {
+    /// Creates a new IgnorePatternMatcher that ignores all lines matching any of the given
+    /// patterns and matches the remaining lines with the inner matcher.
+    pub fn new(patterns: Vec<Regex>, inner: M) -> Self {
+        IgnorePatternMatcher { patterns, inner }
+    }
+
+    /// Returns for each line of the file whether it is ignored.
+    fn ignored_lines(&self, file: &FileArtifact) -> Vec<bool> {
+        file.lines()
+            .iter()
+            .map(|line| self.patterns.iter().any(|p| p.is_match(line)))
+            .collect()
+    }
+
+    /// Returns a copy of the file in which all ignored lines are replaced by the placeholder.
+    fn mask(file: &FileArtifact, ignored: &[bool]) -> FileArtifact {
+        let lines = file
+            .lines()
+            .iter()
+            .zip(ignored)
+            .map(|(line, &ignored)| match ignored {
+                true => IGNORED_LINE_PLACEHOLDER.to_string(),
+                false => line.clone(),
+            })
+            .collect();
+        FileArtifact::from_lines(file.path().to_path_buf(), lines)
+            .with_line_ending(file.line_ending())
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<M: Matcher> Matcher for IgnorePatternMatcher<M> {
+    fn describe(&self) -> String {
+        format!("IgnorePatternMatcher({})", self.inner.describe())
+    }
+
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        let source_ignored = self.ignored_lines(&source);
+        let target_ignored = self.ignored_lines(&target);
+
+        let Matching {
+            mut source_to_target,
+            mut target_to_source,
+            ..
+        } = self.inner.match_files(
+            Self::mask(&source, &source_ignored),
+            Self::mask(&target, &target_ignored),
+        );
+
+        // Returns whether the closest lines that are not ignored are matched to each other
+        let anchored = |source_line: Option<usize>, target_line: Option<usize>| match (
+            source_line,
+            target_line,
+        ) {
+            (None, None) => true,
+            (Some(source_line), Some(target_line)) => {
+                source_to_target.get(source_line) == Some(&Some(target_line))
+            }
+            _ => false,
+        };
+
+        let mut unanchored = vec![];
+        for (source_line, &ignored) in source_ignored.iter().enumerate() {
+            let Some(Some(target_line)) = source_to_target.get(source_line).copied() else {
+                continue;
+            };
+            if !ignored {
+                continue;
+            }
+            let before = anchored(
+                source_ignored[..source_line].iter().rposition(|i| !i),
+                target_ignored[..target_line].iter().rposition(|i| !i),
+            );
+            let after = anchored(
+                source_ignored[source_line + 1..]
+                    .iter()
+                    .position(|i| !i)
+                    .map(|i| source_line + 1 + i),
+                target_ignored[target_line + 1..]
+                    .iter()
+                    .position(|i| !i)
+                    .map(|i| target_line + 1 + i),
+            );
+            if !before && !after {
+                unanchored.push((source_line, target_line));
+            }
+        }
+        for (source_line, target_line) in unanchored {
+            source_to_target[source_line] = None;
+            target_to_source[target_line] = None;
+        }
+
+        Matching::new(source, target, source_to_target, target_to_source)
+    }
+}
+
+/// The content that replaces each run of digits before the lines are passed to the inner matcher
+/// of a NumericWildcardMatcher.
+const NUMBER_PLACEHOLDER: &str = "\0";
+
+/// A matcher that treats numeric literals as wildcards while matching.
+///
+/// Config-like files of different variants often only differ in their numeric values (e.g.,
+/// `timeout = 30` and `timeout = 45`). With plain matching, such lines remain unmatched, so that
+/// changes next to them cannot be anchored. This matcher wraps another matcher and replaces every
+/// run of ASCII digits with the same placeholder before the lines are matched; thus, lines that
+/// only differ in their numbers are matched to each other. The returned matching holds the
+/// original files.
+#[derive(Debug, Default)]
+pub struct NumericWildcardMatcher<M: Matcher> {
+    inner: M,
+}
+
+impl<M: Matcher> NumericWildcardMatcher<M> {
+    /// Creates a new NumericWildcardMatcher that matches the masked lines with the inner matcher.
+    pub fn new(inner: M) -> Self {
+        NumericWildcardMatcher { inner }
+    }
+
+    /// Returns a copy of the file in which each run of digits is replaced by the placeholder.
+    fn mask(file: &FileArtifact) -> FileArtifact {
+        let lines = file
+            .lines()
+            .iter()
+            .map(|line| {
+                let mut masked = String::with_capacity(line.len());
+                let mut in_number = false;
+                for c in line.chars() {
+                    if !c.is_ascii_digit() {
+                        masked.push(c);
+                    } else if !in_number {
+                        masked.push_str(NUMBER_PLACEHOLDER);
+                    }
+                    in_number = c.is_ascii_digit();
+                }
+                masked
+            })
+            .collect();
+        FileArtifact::from_lines(file.path().to_path_buf(), lines)
+            .with_line_ending(file.line_ending())
+    }
+}
+
+impl<M: Matcher> Matcher for NumericWildcardMatcher<M> {
+    fn describe(&self) -> String {
+        format!("NumericWildcardMatcher({})", self.inner.describe())
+    }
+
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> Matching {
+        let Matching {
+            source_to_target,
+            target_to_source,
+            ..
+        } = self
+            .inner
+            .match_files(Self::mask(&source), Self::mask(&target));
+        Matching::new(source, target, source_to_target, target_to_source)
     }
 }
 
@@ -371,7 +1104,12 @@ impl HasNewline for Change<&str> {
 mod tests {
     use std::{path::PathBuf, str::FromStr};
 
-    use crate::{io::FileArtifact, LCSMatcher, Matcher};
+    use crate::{io::FileArtifact, LCSMatcher, Matcher, VersionDiff};
+
+    use super::{
+        BlankLineCollapsingMatcher, ContextHashMatcher, ContextMatcher, DiffOp, MatchAlgorithm,
+        Matching, NumericWildcardMatcher, UniqueExactMatcher,
+    };
 
     #[test]
     fn simple_matching() {
@@ -403,6 +1141,125 @@ mod tests {
         assert_eq!(Some(2), matching.source_index(2).unwrap());
     }
 
+    #[test]
+    fn expose_lcs_diff_ops() {
+        let left = FileArtifact::from_lines(
+            PathBuf::from_str("left").unwrap(),
+            vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "".to_string(),
+            ],
+        );
+        let right = FileArtifact::from_lines(
+            PathBuf::from_str("right").unwrap(),
+            vec![
+                "a".to_string(),
+                "x".to_string(),
+                "c".to_string(),
+                "d".to_string(),
+                "".to_string(),
+            ],
+        );
+
+        let (matching, ops) = LCSMatcher::new().match_with_ops(left, right);
+        assert_eq!(
+            ops,
+            vec![
+                DiffOp::Equal {
+                    old_index: 0,
+                    new_index: 0,
+                    len: 1
+                },
+                DiffOp::Replace {
+                    old_index: 1,
+                    old_len: 1,
+                    new_index: 1,
+                    new_len: 1
+                },
+                DiffOp::Equal {
+                    old_index: 2,
+                    new_index: 2,
+                    len: 1
+                },
+                DiffOp::Insert {
+                    old_index: 3,
+                    new_index: 3,
+                    new_len: 1
+                },
+            ]
+        );
+
+        // The equal ranges of the ops are exactly the matched lines
+        for op in ops {
+            if let DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } = op
+            {
+                for offset in 0..len {
+                    assert_eq!(
+                        Some(new_index + offset + 1),
+                        matching.target_index(old_index + offset + 1).unwrap()
+                    );
+                }
+            }
+        }
+        assert_eq!(None, matching.target_index(2).unwrap());
+        assert_eq!(None, matching.source_index(4).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn ignore_timestamp_lines() {
+        use regex::Regex;
+
+        use super::IgnorePatternMatcher;
+
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let source = FileArtifact::from_lines(
+            PathBuf::from_str("source").unwrap(),
+            lines(&[
+                "// Generated: 2024-01-01 10:00",
+                "fn main() {",
+                "    run();",
+                "}",
+                "// Generated: 2024-01-01 10:00",
+                "",
+            ]),
+        );
+        let target = FileArtifact::from_lines(
+            PathBuf::from_str("target").unwrap(),
+            lines(&[
+                "// Generated: 2024-06-30 17:45",
+                "fn main() {",
+                "    init();",
+                "    run();",
+                "}",
+                "",
+            ]),
+        );
+
+        // Plain LCS matching cannot match the differing timestamps
+        let matching = LCSMatcher::new().match_files(source.clone(), target.clone());
+        assert_eq!(None, matching.target_index(1).unwrap());
+
+        let timestamp = Regex::new(r"^// Generated: ").unwrap();
+        let mut matcher = IgnorePatternMatcher::new(vec![timestamp], LCSMatcher::new());
+        assert_eq!("IgnorePatternMatcher(LCSMatcher)", matcher.describe());
+        let matching = matcher.match_files(source, target);
+        assert_eq!(Some(1), matching.target_index(1).unwrap());
+        assert_eq!(Some(2), matching.target_index(2).unwrap());
+        assert_eq!(Some(4), matching.target_index(3).unwrap());
+        assert_eq!(Some(5), matching.target_index(4).unwrap());
+        assert_eq!(None, matching.source_index(3).unwrap());
+        // The trailing timestamp has no counterpart in the target
+        assert_eq!(None, matching.target_index(5).unwrap());
+        assert_eq!(Some(6), matching.target_index(6).unwrap());
+    }
+
     #[test]
     fn no_source_line_and_target_with_newline() {
         // Initialze some simple FileArtifacts
@@ -521,4 +1378,289 @@ mod tests {
         assert_eq!(Some(Some(2)), matching.target_index(2));
         assert_eq!(Some(Some(2)), matching.source_index(2));
     }
+
+    #[test]
+    fn single_empty_line() {
+        let empty_line = |name: &str| {
+            FileArtifact::from_lines(PathBuf::from_str(name).unwrap(), vec!["".to_string()])
+        };
+        let mut matcher = LCSMatcher::new();
+        let matching = matcher.match_files(empty_line("file_a"), empty_line("file_b"));
+        assert_eq!(Some(Some(1)), matching.target_index(1));
+        assert_eq!(Some(Some(1)), matching.source_index(1));
+
+        // The line remains unmatched if the other file is empty
+        let file_b = FileArtifact::from_lines(PathBuf::from_str("file_b").unwrap(), vec![]);
+        let matching = matcher.match_files(empty_line("file_a"), file_b);
+        assert_eq!(Some(None), matching.target_index(1));
+        assert_eq!(None, matching.source_index(1));
+    }
+
+    #[test]
+    fn collapse_blank_lines() {
+        let file_a = FileArtifact::from_lines(
+            PathBuf::from_str("file_a").unwrap(),
+            vec![
+                "int a;".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "int b;".to_string(),
+                "".to_string(),
+                "int c;".to_string(),
+            ],
+        );
+        let file_b = FileArtifact::from_lines(
+            PathBuf::from_str("file_b").unwrap(),
+            vec![
+                "int a;".to_string(),
+                "".to_string(),
+                "int b;".to_string(),
+                "".to_string(),
+                "  ".to_string(),
+                "int c;".to_string(),
+            ],
+        );
+        let mut matcher = BlankLineCollapsingMatcher::new();
+        let matching = matcher.match_files(file_a, file_b);
+
+        // All non-blank lines are matched
+        assert_eq!(Some(Some(1)), matching.target_index(1));
+        assert_eq!(Some(Some(3)), matching.target_index(5));
+        assert_eq!(Some(Some(6)), matching.target_index(7));
+        // The first blank lines of each run are matched, the surplus lines remain unmatched
+        assert_eq!(Some(Some(2)), matching.target_index(2));
+        assert_eq!(Some(None), matching.target_index(3));
+        assert_eq!(Some(None), matching.target_index(4));
+        assert_eq!(Some(Some(4)), matching.target_index(6));
+        assert_eq!(Some(None), matching.source_index(5));
+    }
+
+    #[test]
+    fn matching_to_csv() {
+        let matching = Matching::new(
+            FileArtifact::new(PathBuf::from("source")),
+            FileArtifact::new(PathBuf::from("target")),
+            vec![Some(0), None, Some(2)],
+            vec![Some(0), None, Some(2), None],
+        );
+        assert_eq!(
+            "source_line,target_line\n1,1\n2,\n3,3\n,2\n,4\n",
+            matching.to_csv()
+        );
+    }
+
+    #[test]
+    fn find_unmatched_target_regions() {
+        let artifact = |name: &str, lines: &[&str]| {
+            FileArtifact::from_lines(
+                PathBuf::from(name),
+                lines.iter().map(|l| l.to_string()).collect(),
+            )
+        };
+        let source = artifact("source", &["a", "b", "c", "d"]);
+        let target = artifact("target", &["a", "new 1", "new 2", "b", "c", "new 3", "d"]);
+        let matching = LCSMatcher.match_files(source.clone(), target);
+        assert_eq!(vec![(2, 3), (6, 6)], matching.unmatched_target_regions());
+
+        let matching = LCSMatcher.match_files(source.clone(), source);
+        assert!(matching.unmatched_target_regions().is_empty());
+    }
+
+    #[test]
+    fn match_unique_lines_only() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let file_a = FileArtifact::from_lines(
+            PathBuf::from_str("file_a").unwrap(),
+            lines(&["int a;", "{", "}", "int b;", "{", "}", "int c;", "int d;"]),
+        );
+        let file_b = FileArtifact::from_lines(
+            PathBuf::from_str("file_b").unwrap(),
+            lines(&["int a;", "{", "}", "int c;", "int d;", "int d;", "int e;"]),
+        );
+        let mut matcher = UniqueExactMatcher::new();
+        let matching = matcher.match_files(file_a, file_b);
+
+        // Lines that are unique in both files are matched
+        assert_eq!(Some(Some(1)), matching.target_index(1));
+        assert_eq!(Some(Some(4)), matching.target_index(7));
+        assert_eq!(Some(Some(1)), matching.source_index(1));
+        assert_eq!(Some(Some(7)), matching.source_index(4));
+        // Lines that are duplicated in the source file remain unmatched
+        assert_eq!(Some(None), matching.target_index(2));
+        assert_eq!(Some(None), matching.target_index(3));
+        assert_eq!(Some(None), matching.source_index(2));
+        assert_eq!(Some(None), matching.source_index(3));
+        // Lines that are duplicated in the target file remain unmatched
+        assert_eq!(Some(None), matching.target_index(8));
+        assert_eq!(Some(None), matching.source_index(5));
+        assert_eq!(Some(None), matching.source_index(6));
+        // Lines without a counterpart remain unmatched
+        assert_eq!(Some(None), matching.target_index(4));
+        assert_eq!(Some(None), matching.source_index(7));
+    }
+
+    #[test]
+    fn locate_hunks_by_context() {
+        let diff = VersionDiff::try_from(
+            "diff -Naur version-A/file.c version-B/file.c
+--- version-A/file.c
++++ version-B/file.c
+@@ -2,3 +2,3 @@
+ int b;
+-int c;
++int C;
+ int d;"
+                .to_string(),
+        )
+        .unwrap();
+        // The target contains the context twice; the occurrence closest to the source location
+        // is chosen
+        let target = FileArtifact::from_lines(
+            PathBuf::from("target/file.c"),
+            [
+                "int x;", "int b;", "int c;", "int d;", "int y;", "int b;", "int c;", "int d;",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+        let mut matcher = ContextMatcher::new(diff);
+        // The source file does not exist
+        let source = FileArtifact::new(PathBuf::from("missing/file.c"));
+        let matching = matcher.match_files(source, target);
+
+        assert_eq!(Some(None), matching.target_index(1));
+        assert_eq!(Some(Some(2)), matching.target_index(2));
+        assert_eq!(Some(Some(3)), matching.target_index(3));
+        assert_eq!(Some(Some(4)), matching.target_index(4));
+        assert_eq!(None, matching.target_index(5));
+        assert_eq!(Some(None), matching.source_index(6));
+        assert_eq!("int c;", matching.source().lines()[2]);
+    }
+
+    #[test]
+    fn context_matcher_ignores_unrelated_files() {
+        let diff = VersionDiff::read("tests/diffs/mixed.diff").unwrap();
+        let target = FileArtifact::from_lines(
+            PathBuf::from("target/other.c"),
+            vec!["int number;".to_string()],
+        );
+        let mut matcher = ContextMatcher::new(diff);
+        let matching = matcher.match_files(FileArtifact::new(PathBuf::from("other.c")), target);
+        assert!(matching.source().is_empty());
+        assert_eq!(Some(None), matching.source_index(1));
+    }
+
+    #[test]
+    fn compute_match_ratio() {
+        let matching = Matching::new(
+            FileArtifact::new(PathBuf::from("source")),
+            FileArtifact::new(PathBuf::from("target")),
+            vec![Some(0), None, Some(2)],
+            vec![Some(0), None, Some(2), None],
+        );
+        assert_eq!(4.0 / 7.0, matching.match_ratio());
+
+        let empty = Matching::new(
+            FileArtifact::new(PathBuf::from("source")),
+            FileArtifact::new(PathBuf::from("target")),
+            vec![],
+            vec![],
+        );
+        assert_eq!(0.0, empty.match_ratio());
+    }
+
+    #[test]
+    fn match_braces_by_context() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let file_a = FileArtifact::from_lines(
+            PathBuf::from_str("file_a").unwrap(),
+            lines(&["a();", "}", "b();", "}", "c();", "}", "d();"]),
+        );
+        let file_b = FileArtifact::from_lines(
+            PathBuf::from_str("file_b").unwrap(),
+            lines(&["a();", "}", "e();", "}", "c();", "}", "d();"]),
+        );
+
+        // Line-based matching matches all braces
+        let matching = LCSMatcher.match_files(file_a.clone(), file_b.clone());
+        for line_number in [2, 4, 6] {
+            assert_eq!(Some(Some(line_number)), matching.target_index(line_number));
+        }
+
+        let mut matcher = ContextHashMatcher::new(1);
+        let matching = matcher.match_files(file_a, file_b);
+        // Only the brace between the unchanged calls c() and d() is matched
+        assert_eq!(Some(None), matching.target_index(2));
+        assert_eq!(Some(None), matching.target_index(4));
+        assert_eq!(Some(Some(6)), matching.target_index(6));
+        // The calls are only matched if their neighbors are identical as well
+        assert_eq!(Some(Some(1)), matching.target_index(1));
+        assert_eq!(Some(None), matching.target_index(3));
+        assert_eq!(Some(Some(5)), matching.target_index(5));
+        assert_eq!(Some(Some(7)), matching.target_index(7));
+    }
+
+    #[test]
+    fn compute_matching_with_algorithm() {
+        let source = FileArtifact::from_lines(
+            PathBuf::from("source"),
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        );
+        let target = FileArtifact::from_lines(
+            PathBuf::from("target"),
+            vec!["x".to_string(), "a".to_string(), "c".to_string()],
+        );
+
+        let computed = Matching::compute(source.clone(), target.clone(), MatchAlgorithm::Lcs);
+        let expected = LCSMatcher.match_files(source.clone(), target.clone());
+        assert_eq!(expected.source_to_target, computed.source_to_target);
+        assert_eq!(expected.target_to_source, computed.target_to_source);
+
+        let computed = Matching::compute(
+            source.clone(),
+            target.clone(),
+            MatchAlgorithm::ContextHash(1),
+        );
+        let expected = ContextHashMatcher::new(1).match_files(source, target);
+        assert_eq!(expected.source_to_target, computed.source_to_target);
+        assert_eq!(expected.target_to_source, computed.target_to_source);
+    }
+
+    #[test]
+    fn match_lines_that_differ_in_numbers() {
+        let source = FileArtifact::from_lines(
+            PathBuf::from("source.conf"),
+            vec![
+                "[server]".to_string(),
+                "timeout = 30".to_string(),
+                "port = 8080".to_string(),
+                "host = example.org".to_string(),
+            ],
+        );
+        let target = FileArtifact::from_lines(
+            PathBuf::from("target.conf"),
+            vec![
+                "[server]".to_string(),
+                "timeout = 45".to_string(),
+                "port = 80".to_string(),
+                "host = localhost".to_string(),
+            ],
+        );
+
+        let matching = LCSMatcher.match_files(source.clone(), target.clone());
+        assert_eq!(Some(None), matching.target_index(2));
+
+        let mut matcher = NumericWildcardMatcher::new(LCSMatcher);
+        assert_eq!("NumericWildcardMatcher(LCSMatcher)", matcher.describe());
+        let matching = matcher.match_files(source, target);
+        assert_eq!(Some(Some(1)), matching.target_index(1));
+        assert_eq!(Some(Some(2)), matching.target_index(2));
+        assert_eq!(Some(Some(3)), matching.target_index(3));
+        // Lines that differ in more than their numbers remain unmatched
+        assert_eq!(Some(None), matching.target_index(4));
+        // The matching holds the original lines
+        assert_eq!("timeout = 30", matching.source().lines()[1]);
+        assert_eq!("timeout = 45", matching.target().lines()[1]);
+    }
 }
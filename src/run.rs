@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use crate::{
+    patch::{apply_all_with_options, ApplyStats, PatchPaths},
+    DistanceFilter, Error, KeepAllFilter, LCSMatcher, PatchOptions,
+};
+
+/// The filter that is applied to the patches by `run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterChoice {
+    /// Filter the changes with a DistanceFilter with the given maximum distance.
+    Distance(usize),
+    /// Keep all changes (see KeepAllFilter).
+    KeepAll,
+}
+
+impl Default for FilterChoice {
+    /// Returns the filter that is used by the mpatch binary.
+    fn default() -> Self {
+        FilterChoice::Distance(2)
+    }
+}
+
+/// The configuration of a patch application with `run`. The configuration mirrors the
+/// command-line arguments of the mpatch binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunConfig {
+    /// The directory of the source variant, i.e., the variant from which the diff was created.
+    pub source_dir: PathBuf,
+    /// The directory of the target variant to which the diff is applied.
+    pub target_dir: PathBuf,
+    /// The diff file that is applied.
+    pub patch_file: PathBuf,
+    /// The file to which the rejects are written. If there is no such file, the rejects are
+    /// printed (unless another sink is configured in the options).
+    pub rejects_file: Option<PathBuf>,
+    /// The number of leading path components that are stripped from the paths in the diff.
+    pub strip: usize,
+    /// The filter that is applied to the patches.
+    pub filter: FilterChoice,
+    /// The options of the patch application.
+    pub options: PatchOptions,
+}
+
+impl RunConfig {
+    /// Creates a new RunConfig that applies the diff file in the given source directory to the
+    /// target directory with the defaults of the mpatch binary.
+    pub fn new(
+        source_dir: impl Into<PathBuf>,
+        target_dir: impl Into<PathBuf>,
+        patch_file: impl Into<PathBuf>,
+    ) -> Self {
+        RunConfig {
+            source_dir: source_dir.into(),
+            target_dir: target_dir.into(),
+            patch_file: patch_file.into(),
+            rejects_file: None,
+            strip: 0,
+            filter: FilterChoice::default(),
+            options: PatchOptions::default(),
+        }
+    }
+}
+
+/// Applies a diff as configured, exactly like the mpatch binary does. The source and target
+/// files are matched with an LCSMatcher and the patches are filtered with the chosen filter (see
+/// `apply_all_with_options`).
+///
+/// ## Returns
+/// Returns statistics about the applied and rejected changes.
+///
+/// ## Error
+/// Returns an Error under the same conditions as `apply_all_with_options`.
+pub fn run(config: RunConfig) -> Result<ApplyStats, Error> {
+    let patch_paths = PatchPaths::new(
+        config.source_dir,
+        config.target_dir,
+        config.patch_file,
+        config.rejects_file,
+    );
+    match config.filter {
+        FilterChoice::Distance(distance) => apply_all_with_options(
+            patch_paths,
+            config.strip,
+            LCSMatcher,
+            DistanceFilter::new(distance),
+            &config.options,
+        ),
+        FilterChoice::KeepAll => apply_all_with_options(
+            patch_paths,
+            config.strip,
+            LCSMatcher,
+            KeepAllFilter,
+            &config.options,
+        ),
+    }
+}
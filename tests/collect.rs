@@ -0,0 +1,263 @@
+pub mod test_utils;
+
+use std::path::PathBuf;
+
+use mpatch::{
+    patch::PatchPaths, CancelFlag, DistanceFilter, ErrorKind, KeepAllFilter, LCSMatcher,
+    PatchOptions,
+};
+use test_utils::setup_work_dir;
+
+const SOURCE_DIR: &str = "tests/collect/source_variant/version-0";
+const TARGET_DIR: &str = "tests/collect/target_variant/version-0";
+const DIFF: &str = "tests/collect/diffs/collect.diff";
+
+#[test]
+fn unreadable_source_does_not_abort() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from(SOURCE_DIR),
+        PathBuf::from(TARGET_DIR),
+        PathBuf::from(DIFF),
+        None,
+    );
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results =
+        mpatch::apply_all_collect(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+    assert_eq!(3, results.len());
+
+    let (path, first) = &results[0];
+    assert_eq!(&PathBuf::from(TARGET_DIR).join("first.c"), path);
+    let first = first.as_ref().unwrap();
+    assert_eq!(
+        ["int a;", "int added;", "int b;"],
+        first.patched_file().lines()
+    );
+
+    let (path, unreadable) = &results[1];
+    assert_eq!(&PathBuf::from(TARGET_DIR).join("unreadable.c"), path);
+    assert_eq!(ErrorKind::IOError, *unreadable.as_ref().unwrap_err().kind());
+
+    let (path, last) = &results[2];
+    assert_eq!(&PathBuf::from(TARGET_DIR).join("last.c"), path);
+    let last = last.as_ref().unwrap();
+    assert_eq!(["int x;"], last.patched_file().lines());
+}
+
+#[test]
+fn read_unreadable_source_lossily() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from(SOURCE_DIR),
+        PathBuf::from(TARGET_DIR),
+        PathBuf::from(DIFF),
+        None,
+    );
+    let options = PatchOptions::builder()
+        .dryrun(true)
+        .lossy_read(true)
+        .build();
+    let results =
+        mpatch::apply_all_collect(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+
+    // The invalid UTF-8 sequence in the source is replaced, so that the file can be patched
+    let (path, unreadable) = &results[1];
+    assert_eq!(&PathBuf::from(TARGET_DIR).join("unreadable.c"), path);
+    let unreadable = unreadable.as_ref().unwrap();
+    assert!(unreadable.is_clean());
+    assert_eq!(
+        ["int a;", "int added;", "int b;"],
+        unreadable.patched_file().lines()
+    );
+}
+
+#[test]
+fn record_matcher_and_filter() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from(SOURCE_DIR),
+        PathBuf::from(TARGET_DIR),
+        PathBuf::from(DIFF),
+        None,
+    );
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results =
+        mpatch::apply_all_collect(patch_paths, 1, LCSMatcher, DistanceFilter::new(2), &options)
+            .unwrap();
+
+    let first = results[0].1.as_ref().unwrap();
+    assert_eq!(Some("LCSMatcher"), first.matcher_description());
+    assert_eq!(Some("DistanceFilter(2)"), first.filter_description());
+}
+
+#[test]
+fn record_overlapping_hunks_as_error() {
+    let diff = "diff -Naur version-0/a.c version-1/a.c
+--- version-0/a.c
++++ version-1/a.c
+@@ -1,2 +1,2 @@
+ int a;
+-int b;
++int B;
+@@ -2,2 +2,2 @@
+-int b;
++int B;
+ int c;
+diff -Naur version-0/b.c version-1/b.c
+--- version-0/b.c
++++ version-1/b.c
+@@ -1,1 +1,2 @@
+ int a;
++int added;
+";
+    let files = [("a.c", "int a;\nint b;\nint c;\n"), ("b.c", "int a;\n")];
+    let work_dir = setup_work_dir("record_overlapping_hunks_as_error", &files, diff);
+
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results = mpatch::apply_all_collect(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(2, results.len());
+    let error = results[0].1.as_ref().unwrap_err();
+    assert_eq!(ErrorKind::DiffParseError, *error.kind());
+    assert!(error
+        .message()
+        .starts_with("the source locations of two hunks overlap"));
+    // The other file is patched nonetheless
+    let patched = results[1].1.as_ref().unwrap();
+    assert_eq!(["int a;", "int added;"], patched.patched_file().lines());
+    work_dir.remove();
+}
+
+/// Two file diffs that add a line to `b.c` and `a.c`, in this order.
+const UNSORTED_DIFF: &str = "diff -Naur version-0/b.c version-1/b.c
+--- version-0/b.c
++++ version-1/b.c
+@@ -1,1 +1,2 @@
+ int b;
++int added;
+diff -Naur version-0/a.c version-1/a.c
+--- version-0/a.c
++++ version-1/a.c
+@@ -1,1 +1,2 @@
+ int a;
++int added;
+";
+
+#[test]
+fn collect_with_patch_options() {
+    let files = [("a.c", "int a;\n"), ("b.c", "int b;\n")];
+    let work_dir = setup_work_dir("collect_with_patch_options", &files, UNSORTED_DIFF);
+    // The patch has already been applied to b.c
+    std::fs::write(work_dir.target_dir.join("b.c"), "int b;\nint added;\n").unwrap();
+
+    let report_file = work_dir.root.join("report.diff");
+    let options = PatchOptions::builder()
+        .sort_files(true)
+        .skip_already_applied(true)
+        .report_file(&report_file)
+        .build();
+    let results = mpatch::apply_all_collect(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    // The files are patched in sorted order and b.c is skipped
+    assert_eq!(1, results.len());
+    assert_eq!(work_dir.target_dir.join("a.c"), results[0].0);
+    assert_eq!("int a;\nint added;", work_dir.read_target("a.c"));
+    let report = std::fs::read_to_string(&report_file).unwrap();
+    assert!(report.contains("+int added;"));
+
+    // A cancelled patch application fails instead of collecting errors
+    let cancel_flag = CancelFlag::new();
+    cancel_flag.cancel();
+    let options = PatchOptions::builder()
+        .dryrun(true)
+        .cancel_flag(cancel_flag)
+        .build();
+    let error = mpatch::apply_all_collect(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::Cancelled, *error.kind());
+
+    // The edit budget is shared by all files
+    let options = PatchOptions::builder()
+        .dryrun(true)
+        .max_total_edits(0)
+        .build();
+    let error = mpatch::apply_all_collect(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::PatchError, *error.kind());
+    work_dir.remove();
+}
+
+#[test]
+fn group_identical_rejects_across_files() {
+    const FILE_NAMES: [&str; 3] = ["a.c", "b.c", "c.c"];
+    let mut diff = String::new();
+    for name in FILE_NAMES {
+        diff.push_str(&format!(
+            "diff -Naur version-0/{name} version-1/{name}\n\
+             --- version-0/{name}\n\
+             +++ version-1/{name}\n\
+             @@ -1,2 +1,1 @@\n\
+             -int x;\n \
+             int y;\n"
+        ));
+    }
+    let files = FILE_NAMES.map(|name| (name, "int x;\nint y;\n"));
+    let work_dir = setup_work_dir("group_identical_rejects_across_files", &files, &diff);
+    for name in FILE_NAMES {
+        // The removed line does not exist in any of the targets
+        std::fs::write(work_dir.target_dir.join(name), "int z;\nint y;\n").unwrap();
+    }
+
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results = mpatch::apply_all_collect(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    assert!(results
+        .iter()
+        .all(|(_, result)| result.as_ref().unwrap().rejected_changes().len() == 1));
+
+    let groups = mpatch::group_rejects(&results);
+    assert_eq!(1, groups.len());
+    assert_eq!("int x;", groups[0].reject().line());
+    let files: Vec<PathBuf> = FILE_NAMES
+        .iter()
+        .map(|name| work_dir.target_dir.join(name))
+        .collect();
+    assert_eq!(files, groups[0].files());
+    assert_eq!(
+        format!(
+            "-int x; (rejected in 3 files: {}, {}, {})",
+            files[0].display(),
+            files[1].display(),
+            files[2].display()
+        ),
+        groups[0].to_string()
+    );
+    work_dir.remove();
+}
@@ -1,6 +1,8 @@
 use std::fs;
 
-use mpatch::diffs::{ChangedLines, FileDiff, LineLocation, LineType, VersionDiff};
+use mpatch::diffs::{ChangedLines, FileDiff, Hunk, LineLocation, LineType, VersionDiff};
+use mpatch::patch::FileChangeType;
+use mpatch::{ErrorKind, LineEnding};
 
 const DIFF_FILE: &str = "tests/diffs/base_patch.diff";
 
@@ -77,17 +79,17 @@ fn parse_time() {
     let diff = file_diffs.first().unwrap();
     assert_eq!(
         diff.source_file_header().timestamp(),
-        "2023-11-03 16:26:28.701847364 +0100"
+        Some("2023-11-03 16:26:28.701847364 +0100")
     );
     let diff = file_diffs.get(1).unwrap();
     assert_eq!(
         diff.source_file_header().timestamp(),
-        "2023-11-03 16:39:35.953263076 +0100"
+        Some("2023-11-03 16:39:35.953263076 +0100")
     );
     let diff = file_diffs.get(2).unwrap();
     assert_eq!(
         diff.source_file_header().timestamp(),
-        "2023-11-03 16:26:28.701847364 +0100"
+        Some("2023-11-03 16:26:28.701847364 +0100")
     );
 }
 
@@ -140,6 +142,21 @@ fn unparse_commit_diff() {
     assert_eq!(diff.to_string(), diff_text.trim_end());
 }
 
+#[test]
+fn unparse_commit_diff_with_crlf() {
+    let diff = VersionDiff::read(DIFF_FILE).unwrap();
+    let diff_text = fs::read_to_string(DIFF_FILE).unwrap();
+
+    let crlf_text = diff.to_string_with(LineEnding::Crlf);
+    assert_eq!(crlf_text, diff_text.trim_end().replace('\n', "\r\n"));
+    assert_eq!(
+        diff.to_string().lines().count(),
+        crlf_text.matches("\r\n").count() + 1
+    );
+    assert!(!crlf_text.replace("\r\n", "").contains('\n'));
+    assert_eq!(diff.to_string(), diff.to_string_with(LineEnding::Lf));
+}
+
 #[test]
 fn unparse_file_diffs() {
     let file_diffs = load_diffs();
@@ -291,3 +308,322 @@ fn change_locations(changes: ChangedLines) -> Vec<(LineLocation, LineLocation)>
     }
     locations
 }
+
+#[test]
+fn iterate_hunks_of_file_diff() {
+    let file_diffs = load_diffs();
+    let diff = file_diffs.get(2).unwrap();
+
+    let starts: Vec<usize> = diff
+        .into_iter()
+        .map(|h| h.source_location().hunk_start())
+        .collect();
+    assert_eq!(vec![1, 23], starts);
+
+    let hunks: Vec<Hunk> = diff.clone().into_iter().collect();
+    assert_eq!(diff.hunks(), hunks.as_slice());
+}
+
+#[test]
+fn classify_file_diffs() {
+    let file_diffs = load_diffs();
+
+    // single.txt is created
+    assert!(file_diffs[0].is_creation());
+    assert!(!file_diffs[0].is_removal());
+    assert!(!file_diffs[0].is_modification());
+    assert_eq!(FileChangeType::Create, file_diffs[0].change_type());
+
+    // double_end.txt and long.txt are modified
+    for file_diff in &file_diffs[1..] {
+        assert!(!file_diff.is_creation());
+        assert!(!file_diff.is_removal());
+        assert!(file_diff.is_modification());
+        assert_eq!(FileChangeType::Modify, file_diff.change_type());
+    }
+}
+
+#[test]
+fn detect_duplicate_targets() {
+    let diff = VersionDiff::read(DIFF_FILE).unwrap();
+    assert!(!diff.has_duplicate_targets());
+
+    let text = diff.to_string();
+    let duplicated = VersionDiff::try_from(format!("{text}\n{text}")).unwrap();
+    assert_eq!(6, duplicated.len());
+    assert!(duplicated.has_duplicate_targets());
+}
+
+#[test]
+fn parse_svn_diff() {
+    let content = "Index: trunk/main.c
+===================================================================
+--- trunk/main.c\t(revision 12)
++++ trunk/main.c\t(working copy)
+@@ -1,2 +1,2 @@
+ int main() {
+-  return 1;
++  return 0;
+Index: trunk/util.c
+===================================================================
+--- trunk/util.c\t(revision 12)
++++ trunk/util.c\t(working copy)
+@@ -3,1 +3,2 @@
+ void util();
++void helper();";
+    let diff = VersionDiff::try_from(content.to_string()).unwrap();
+    assert_eq!(2, diff.len());
+
+    let paths: Vec<_> = diff
+        .file_diffs()
+        .iter()
+        .map(|d| d.diff_command().index_path().unwrap().to_str().unwrap())
+        .collect();
+    assert_eq!(vec!["trunk/main.c", "trunk/util.c"], paths);
+    let util = &diff.file_diffs()[1];
+    assert_eq!("trunk/util.c", util.changed_file_path().to_str().unwrap());
+    assert_eq!(1, util.hunks().len());
+    assert_eq!(1, util.changes().count());
+
+    // Diffs created with diff do not have an index path
+    let file_diffs = load_diffs();
+    assert!(file_diffs[0].diff_command().index_path().is_none());
+
+    assert_eq!(content, diff.to_string());
+}
+
+const SINGLE_FILE_DIFF: &str = "diff -Naur version-0/main.c version-1/main.c
+--- version-0/main.c
++++ version-1/main.c
+@@ -1 +1,2 @@
+ int main() {
++  return 0;";
+
+#[test]
+fn skip_byte_order_mark() {
+    let diff = VersionDiff::try_from(format!("\u{feff}{SINGLE_FILE_DIFF}")).unwrap();
+    assert_eq!(1, diff.len());
+    assert_eq!(SINGLE_FILE_DIFF, diff.to_string());
+}
+
+#[test]
+fn skip_leading_blank_lines() {
+    let diff = VersionDiff::try_from(format!("\n  \n\t\n{SINGLE_FILE_DIFF}")).unwrap();
+    assert_eq!(1, diff.len());
+    assert_eq!(SINGLE_FILE_DIFF, diff.to_string());
+
+    let diff = VersionDiff::try_from(format!("\u{feff}\r\n{SINGLE_FILE_DIFF}")).unwrap();
+    assert_eq!(SINGLE_FILE_DIFF, diff.to_string());
+}
+
+#[test]
+fn skip_malformed_file_diffs_leniently() {
+    let broken = "diff -Naur version-0/broken.c version-1/broken.c
+--- version-0/broken.c
+@@ -1 +1 @@
+-int a;
++int b;";
+    let util = SINGLE_FILE_DIFF.replace("main.c", "util.c");
+    let content = format!("{SINGLE_FILE_DIFF}\n{broken}\n{util}");
+    assert!(VersionDiff::try_from(content.clone()).is_err());
+
+    let (diff, errors) = VersionDiff::try_from_lenient(content);
+    assert_eq!(2, diff.len());
+    assert_eq!(format!("{SINGLE_FILE_DIFF}\n{util}"), diff.to_string());
+    assert_eq!(1, errors.len());
+    assert_eq!(&ErrorKind::DiffParseError, errors[0].kind());
+}
+
+#[test]
+fn round_trip_git_rename_diff() {
+    // Created with `git diff --cached -M` after renaming kept.txt and making it executable
+    let content = "diff --git a/kept.txt b/renamed.txt
+old mode 100644
+new mode 100755
+similarity index 50%
+rename from kept.txt
+rename to renamed.txt
+index 2fa992c..fe5841d
+--- a/kept.txt
++++ b/renamed.txt
+@@ -1 +1,2 @@
+ keep
++more";
+    let diff = VersionDiff::try_from(content.to_string()).unwrap();
+    assert_eq!(1, diff.len());
+
+    let file_diff = &diff.file_diffs()[0];
+    assert_eq!(
+        [
+            "old mode 100644",
+            "new mode 100755",
+            "similarity index 50%",
+            "rename from kept.txt",
+            "rename to renamed.txt",
+            "index 2fa992c..fe5841d"
+        ],
+        file_diff.extended_headers()
+    );
+    assert_eq!(
+        "a/kept.txt",
+        file_diff.source_file_header().path().to_str().unwrap()
+    );
+    assert_eq!(
+        "b/renamed.txt",
+        file_diff.target_file_header().path().to_str().unwrap()
+    );
+    assert_eq!(FileChangeType::Modify, file_diff.change_type());
+    assert_eq!(1, file_diff.hunks().len());
+    assert_eq!(content, diff.to_string());
+}
+
+#[test]
+fn parse_normal_diff_with_added_lines() {
+    let content = "diff -r v0/add.txt v1/add.txt
+5a6,7
+> f
+> g";
+    let diff = VersionDiff::from_normal(content.to_string()).unwrap();
+    assert_eq!(
+        "diff -r v0/add.txt v1/add.txt
+--- v0/add.txt
++++ v1/add.txt
+@@ -5,0 +6,2 @@
++f
++g",
+        diff.to_string()
+    );
+}
+
+#[test]
+fn parse_normal_diff_with_changed_lines() {
+    let content = "diff -r v0/change.txt v1/change.txt
+4c4
+< d
+---
+> D
+\\ No newline at end of file";
+    let diff = VersionDiff::from_normal(content.to_string()).unwrap();
+    let file_diff = &diff.file_diffs()[0];
+    assert_eq!(FileChangeType::Modify, file_diff.change_type());
+    assert_eq!(
+        "diff -r v0/change.txt v1/change.txt
+--- v0/change.txt
++++ v1/change.txt
+@@ -4,1 +4,1 @@
+-d
++D
+\\ No newline at end of file",
+        diff.to_string()
+    );
+}
+
+#[test]
+fn parse_normal_diff_with_deleted_lines() {
+    let content = "diff -r v0/delete.txt v1/delete.txt
+1,3d0
+< x
+< y
+< z
+diff -r v0/removed.txt v1/removed.txt
+1,2d0
+< first
+< 
+";
+    let diff = VersionDiff::from_normal(content.to_string()).unwrap();
+    assert_eq!(2, diff.len());
+    let hunk = &diff.file_diffs()[0].hunks()[0];
+    assert_eq!(1, hunk.source_location().hunk_start());
+    assert_eq!(3, hunk.source_location().hunk_length());
+    assert_eq!(0, hunk.target_location().hunk_length());
+    let lines: Vec<&str> = hunk.lines().iter().map(|l| l.content()).collect();
+    assert_eq!(["-x", "-y", "-z"], lines.as_slice());
+    assert_eq!(FileChangeType::Modify, diff.file_diffs()[1].change_type());
+}
+
+#[test]
+fn reject_malformed_normal_diffs() {
+    for content in [
+        // No diff command
+        "4c4\n< d\n---\n> D",
+        // Invalid command
+        "diff a b\n4x4\n< d",
+        // A range for the line after which lines are added
+        "diff a b\n4,5a6\n> f",
+        // Fewer lines than announced by the command
+        "diff a b\n1,3d0\n< x",
+        // A separator in a hunk that is not a change
+        "diff a b\n1d0\n< x\n---",
+    ] {
+        let error = VersionDiff::from_normal(content.to_string()).unwrap_err();
+        assert_eq!(&ErrorKind::DiffParseError, error.kind(), "{content}");
+    }
+}
+
+#[test]
+fn parse_diff_of_diff_file() {
+    let content = "diff -Naur version-0/fix.diff version-1/fix.diff
+--- version-0/fix.diff
++++ version-1/fix.diff
+@@ -1,8 +1,12 @@
+ diff -Naur a/main.c b/main.c
+ --- a/main.c
+ +++ b/main.c
+-@@ -1,3 +1,3 @@
++@@ -1,3 +1,4 @@
+  int a;
+ -int b;
+ +int c;
+++int e;
+  int d;
++@@ -10,2 +11,2 @@
++-- removed comment
+++++ added counter
+";
+    let diff = VersionDiff::try_from(content.to_string()).unwrap();
+    assert_eq!(1, diff.len());
+    let file_diff = &diff.file_diffs()[0];
+    assert_eq!(1, file_diff.hunks().len());
+    let hunk = &file_diff.hunks()[0];
+    assert_eq!(13, hunk.lines().len());
+    let last_lines: Vec<(&str, LineType)> = hunk.lines()[10..]
+        .iter()
+        .map(|l| (l.content(), l.line_type()))
+        .collect();
+    assert_eq!(
+        [
+            ("+@@ -10,2 +11,2 @@", LineType::Add),
+            ("+-- removed comment", LineType::Add),
+            ("++++ added counter", LineType::Add),
+        ],
+        last_lines.as_slice()
+    );
+    assert_eq!(content.trim_end(), diff.to_string());
+
+    let source = "diff -Naur a/main.c b/main.c
+--- a/main.c
++++ b/main.c
+@@ -1,3 +1,3 @@
+ int a;
+-int b;
++int c;
+ int d;
+";
+    let patched = mpatch::patch_str(content, source, source).unwrap();
+    assert_eq!(
+        "diff -Naur a/main.c b/main.c
+--- a/main.c
++++ b/main.c
+@@ -1,3 +1,4 @@
+ int a;
+-int b;
++int c;
++int e;
+ int d;
+@@ -10,2 +11,2 @@
+-- removed comment
++++ added counter
+",
+        patched
+    );
+}
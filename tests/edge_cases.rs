@@ -142,16 +142,8 @@ fn compare_actual_and_expected(path_actual: &str, path_expected: &str) -> Result
     let actual = FileArtifact::read(path_actual);
 
     if let Ok(expected) = expected {
-        let actual = actual.unwrap();
-        assert_eq!(expected.len(), actual.len());
-        for (i, (expected, actual)) in expected
-            .into_lines()
-            .into_iter()
-            .zip(actual.into_lines().into_iter())
-            .enumerate()
-        {
-            assert_eq!(expected, actual, "lines {} differ", i)
-        }
+        let mismatches = actual.unwrap().compare(&expected);
+        assert!(mismatches.is_empty(), "files differ: {mismatches:?}");
     } else {
         assert!(actual.is_err());
     }
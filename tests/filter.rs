@@ -1,7 +1,10 @@
 pub mod test_utils;
+use std::path::PathBuf;
+
 use mpatch::{
     filtering::{DistanceFilter, Filter},
-    FileArtifact, LCSMatcher, Matcher,
+    patch::{FilteredPatch, PatchPaths},
+    Error, ErrorKind, FileArtifact, FilePatch, LCSMatcher, Matcher, Matching,
 };
 use test_utils::{assert_change_equality, read_patch};
 
@@ -54,7 +57,7 @@ pub fn run_filter_test(
     let patch = read_patch(diff);
     let expected_patch = read_patch(expected_patch);
 
-    let filtered_patch = filter.apply_filter(patch, &matching);
+    let filtered_patch = filter.apply_filter(patch, &matching).unwrap();
 
     if expect_rejects {
         assert_eq!(
@@ -71,3 +74,28 @@ pub fn run_filter_test(
         assert_change_equality(expected, aligned);
     }
 }
+
+/// A filter with invalid parameters that fails for every patch.
+struct FailingFilter;
+
+impl Filter for FailingFilter {
+    fn apply_filter(&mut self, _: FilePatch, _: &Matching) -> Result<FilteredPatch, Error> {
+        Err(Error::new(
+            "the filter has invalid parameters",
+            ErrorKind::FilterError,
+        ))
+    }
+}
+
+#[test]
+fn propagate_filter_error() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/filter/samples/source_variant/version-0"),
+        PathBuf::from("tests/filter/samples/target_variant/version-0"),
+        PathBuf::from(DIFF),
+        None,
+    );
+    let error = mpatch::apply_all(patch_paths, 1, true, LCSMatcher, FailingFilter).unwrap_err();
+    assert_eq!(ErrorKind::FilterError, *error.kind());
+    assert_eq!("the filter has invalid parameters", error.message());
+}
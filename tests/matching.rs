@@ -1,4 +1,8 @@
-use mpatch::{FileArtifact, LCSMatcher, Matcher};
+use std::path::PathBuf;
+
+use mpatch::{FileArtifact, LCSMatcher, Matcher, Matching, UniqueExactMatcher};
+use proptest::prelude::*;
+use proptest::test_runner::TestRunner;
 
 const SOURCE_FILE_PATH: &str = "tests/samples/source_variant/version-0/main.c";
 const TARGET_FILE_PATH: &str = "tests/samples/target_variant/version-0/main.c";
@@ -98,3 +102,110 @@ fn right_to_left_found() {
         assert_eq!(matching.source_index(right).unwrap(), left);
     }
 }
+
+#[test]
+fn inverted_matching() {
+    let file_instance_a = FileArtifact::read(SOURCE_FILE_PATH).unwrap();
+    let file_instance_b = FileArtifact::read(TARGET_FILE_PATH).unwrap();
+
+    let mut matcher = LCSMatcher;
+    let matching = matcher.match_files(file_instance_a.clone(), file_instance_b.clone());
+    let inverted = matcher
+        .match_files(file_instance_a.clone(), file_instance_b.clone())
+        .inverted();
+
+    assert_eq!(&file_instance_b, inverted.source());
+    assert_eq!(&file_instance_a, inverted.target());
+    for index in 1..=file_instance_b.len() {
+        assert_eq!(matching.source_index(index), inverted.target_index(index));
+    }
+    for index in 1..=file_instance_a.len() {
+        assert_eq!(matching.target_index(index), inverted.source_index(index));
+    }
+}
+
+#[test]
+fn match_files_by_reference() {
+    let file_instance_a = FileArtifact::read(SOURCE_FILE_PATH).unwrap();
+    let file_instance_b = FileArtifact::read(TARGET_FILE_PATH).unwrap();
+
+    let mut matcher = LCSMatcher;
+    let matching = matcher.match_files_ref(&file_instance_a, &file_instance_b);
+    let repeated = matcher.match_files_ref(&file_instance_a, &file_instance_b);
+    assert_eq!(&file_instance_a, matching.source());
+    assert_eq!(&file_instance_b, matching.target());
+    for index in 1..=file_instance_a.len() {
+        assert_eq!(matching.target_index(index), repeated.target_index(index));
+    }
+
+    // The files are still available for other matchers
+    let unique = UniqueExactMatcher.match_files_ref(&file_instance_a, &file_instance_b);
+    assert_eq!(&file_instance_a, unique.source());
+}
+
+/// Returns a strategy for the lines of a file. The lines are drawn from a small set of texts, so
+/// that files share many lines and have duplicate lines, for which the matcher has to break ties.
+fn file_lines() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(
+        prop::sample::select(vec!["{", "}", "", "int a = 0;", "return a;", "a++;"]),
+        0..24,
+    )
+    .prop_map(|lines| lines.into_iter().map(String::from).collect())
+}
+
+/// Asserts that the given matching is monotonic and self-consistent: every line is processed, the
+/// matches of source lines are strictly increasing, and every match points back to its
+/// counterpart.
+fn assert_consistent_matching(matching: &Matching) -> Result<(), TestCaseError> {
+    let mut previous_match = 0;
+    for source_index in 1..=matching.source().len() {
+        let target_match = matching.target_index(source_index);
+        prop_assert!(
+            target_match.is_some(),
+            "source line {source_index} not processed"
+        );
+        if let Some(Some(target_index)) = target_match {
+            prop_assert!(target_index > previous_match, "matching is not monotonic");
+            prop_assert!(target_index <= matching.target().len());
+            prop_assert_eq!(
+                Some(Some(source_index)),
+                matching.source_index(target_index)
+            );
+            previous_match = target_index;
+        }
+    }
+    for target_index in 1..=matching.target().len() {
+        let source_match = matching.source_index(target_index);
+        prop_assert!(
+            source_match.is_some(),
+            "target line {target_index} not processed"
+        );
+        if let Some(Some(source_index)) = source_match {
+            prop_assert_eq!(
+                Some(Some(target_index)),
+                matching.target_index(source_index)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Runs the matchers created by the given function on randomly generated pairs of source and
+/// target files and asserts that each resulting matching is consistent. The test runner is
+/// deterministic, so that the generated files are the same in every run.
+fn assert_matcher_consistency<M: Matcher>(create_matcher: impl Fn() -> M) {
+    let mut runner = TestRunner::deterministic();
+    runner
+        .run(&(file_lines(), file_lines()), |(source, target)| {
+            let source = FileArtifact::from_lines(PathBuf::from("source"), source);
+            let target = FileArtifact::from_lines(PathBuf::from("target"), target);
+            let matching = create_matcher().match_files(source, target);
+            assert_consistent_matching(&matching)
+        })
+        .unwrap();
+}
+
+#[test]
+fn lcs_matcher_produces_consistent_matchings() {
+    assert_matcher_consistency(|| LCSMatcher);
+}
@@ -1,10 +1,19 @@
 pub mod test_utils;
 
+use std::path::{Path, PathBuf};
+
+use mpatch::alignment::align_filtered_patch_to_target;
+use mpatch::application::apply_patch;
+use mpatch::filtering::{DistanceFilter, Filter};
+use mpatch::patch::{FileChangeType, LineChangeType, RejectReason};
 use mpatch::{
-    patch::{alignment::align_patch_to_target, AlignedPatch},
-    FileArtifact, LCSMatcher, Matcher,
+    ContextMatcher, ErrorKind, FileArtifact, FilePatch, KeepAllFilter, LCSMatcher, Matcher,
+    PatchOptions, PatchPaths, VersionDiff,
+};
+use test_utils::{
+    get_aligned_patch, read_patch, run_alignment_test, run_application_test, setup_work_dir,
+    SINGLE_FILE_CONTENT, SINGLE_FILE_DIFF,
 };
-use test_utils::{get_aligned_patch, read_patch, run_alignment_test, run_application_test};
 
 // TODO: Test multi-alignment
 // TODO: Test file creation
@@ -32,6 +41,7 @@ const SUBSTRACTIVE_DIFF: &str = "tests/diffs/substractive.diff";
 const EXPECTED_SUBSTRACTIVE_PATCH: &str = "tests/expected_patches/substractive.diff";
 const EXPECTED_SUBSTRACTIVE_RESULT: &str = "tests/samples/target_variant/version-1/substractive.c";
 
+const MIXED_SOURCE_DIR: &str = "tests/samples/source_variant/version-0";
 const MIXED_SOURCE: &str = "tests/samples/source_variant/version-0/mixed.c";
 const MIXED_TARGET: &str = "tests/samples/target_variant/version-0/mixed.c";
 const MIXED_DIFF: &str = "tests/diffs/mixed.diff";
@@ -51,6 +61,8 @@ const APPENDING_DIFF: &str = "tests/diffs/appending.diff";
 const EXPECTED_APPENDING_PATCH: &str = "tests/expected_patches/appending.diff";
 const EXPECTED_APPENDING_RESULT: &str = "tests/samples/target_variant/version-1/appending.c";
 
+const CREATION_DIFF: &str = "tests/diffs/create_files.diff";
+
 #[test]
 fn invariant_alignment() {
     run_alignment_test(
@@ -143,3 +155,1291 @@ fn apply_appending() {
     let aligned_patch = get_aligned_patch(APPENDING_SOURCE, APPENDING_TARGET, APPENDING_DIFF);
     run_application_test(aligned_patch, EXPECTED_APPENDING_RESULT, 0);
 }
+
+#[test]
+fn account_for_applied_and_rejected_changes() {
+    let total = read_patch(NON_EXISTANT_DIFF).changes().len();
+    let aligned_patch =
+        get_aligned_patch(NON_EXISTANT_SOURCE, NON_EXISTANT_TARGET, NON_EXISTANT_DIFF);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+
+    // One of the two removed lines does not exist in the target file
+    assert_eq!(1, outcome.applied_changes().len());
+    assert_eq!(1, outcome.rejected_changes().len());
+    assert_eq!(
+        total,
+        outcome.applied_changes().len() + outcome.rejected_changes().len()
+    );
+    assert_eq!(
+        "  unsigned long long result;",
+        outcome.applied_changes()[0].line()
+    );
+}
+
+#[test]
+fn track_changes_by_origin_id() {
+    let patch = read_patch(NON_EXISTANT_DIFF);
+    let original_changes = patch.changes().to_vec();
+
+    let source = FileArtifact::read(NON_EXISTANT_SOURCE).unwrap();
+    let target = FileArtifact::read(NON_EXISTANT_TARGET).unwrap();
+    let matching = LCSMatcher.match_files(source, target);
+    let filtered_patch = DistanceFilter::new(2)
+        .apply_filter(patch, &matching)
+        .unwrap();
+    let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+    assert!(!outcome.rejected_changes().is_empty());
+
+    let mut origin_ids = vec![];
+    for change in outcome
+        .applied_changes()
+        .iter()
+        .chain(outcome.rejected_changes())
+    {
+        let original = &original_changes[change.origin_id()];
+        assert_eq!(original.line(), change.line());
+        assert_eq!(original.change_type(), change.change_type());
+        origin_ids.push(change.origin_id());
+    }
+    origin_ids.sort();
+    let expected: Vec<usize> = original_changes.iter().map(|c| c.origin_id()).collect();
+    assert_eq!(expected, origin_ids);
+}
+
+#[test]
+fn diff_target_file_before_and_after_patch() {
+    let aligned_patch = get_aligned_patch(MIXED_SOURCE, MIXED_TARGET, MIXED_DIFF);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+    let unified_diff = outcome.as_unified_diff();
+
+    let header = format!("--- {MIXED_TARGET}\n+++ {MIXED_TARGET}\n");
+    assert!(unified_diff.starts_with(&header));
+    let diff_lines = |prefix: char| -> Vec<&str> {
+        unified_diff
+            .lines()
+            .skip(2)
+            .filter_map(|l| l.strip_prefix(prefix))
+            .collect()
+    };
+    let applied_lines = |change_type| -> Vec<&str> {
+        outcome
+            .applied_changes()
+            .iter()
+            .filter(|c| c.change_type() == change_type)
+            .map(|c| c.line())
+            .collect()
+    };
+    assert_eq!(applied_lines(LineChangeType::Remove), diff_lines('-'));
+    assert_eq!(applied_lines(LineChangeType::Add), diff_lines('+'));
+}
+
+#[test]
+fn write_report_of_changed_files() {
+    let work_dir = setup_work_dir(
+        "write_report_of_changed_files",
+        &[
+            ("modified.txt", "line a\nline b\nline c\n"),
+            ("removed.txt", "removed a\nremoved b\n"),
+            ("unchanged.txt", "unchanged\n"),
+        ],
+        "diff -Naur version-0/modified.txt version-1/modified.txt
+--- version-0/modified.txt
++++ version-1/modified.txt
+@@ -1,3 +1,3 @@
+ line a
+-line b
++patched b
+ line c
+diff -Naur version-0/created.txt version-1/created.txt
+--- version-0/created.txt
++++ version-1/created.txt
+@@ -0,0 +1 @@
++created
+diff -Naur version-0/removed.txt version-1/removed.txt
+--- version-0/removed.txt
++++ version-1/removed.txt
+@@ -1,2 +0,0 @@
+-removed a
+-removed b
+",
+    );
+
+    let report_file = work_dir.root.join("report.diff");
+    let options = PatchOptions::builder()
+        .dryrun(true)
+        .report_file(&report_file)
+        .build();
+    mpatch::apply_all_with_options(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+
+    let path = |file_name| work_dir.target_dir.join(file_name).display().to_string();
+    let expected = format!(
+        "--- {modified}
++++ {modified}
+@@ -1,3 +1,3 @@
+ line a
+-line b
++patched b
+ line c
+--- /dev/null
++++ {created}
+@@ -0,0 +1 @@
++created
+--- {removed}
++++ /dev/null
+@@ -1,2 +0,0 @@
+-removed a
+-removed b
+",
+        modified = path("modified.txt"),
+        created = path("created.txt"),
+        removed = path("removed.txt"),
+    );
+    assert_eq!(expected, std::fs::read_to_string(&report_file).unwrap());
+    work_dir.remove();
+}
+
+/// Two file diffs of `file.txt`, the first of which is `SINGLE_FILE_DIFF`.
+const DUPLICATE_TARGETS_DIFF: &str = "diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -1,3 +1,3 @@
+ line a
+-line b
++patched b
+ line c
+diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -3,3 +3,3 @@
+ line c
+-line d
++patched d
+ line e
+";
+
+#[test]
+fn drop_whitespace_only_hunks() {
+    use mpatch::diffs::HunkClass;
+
+    let mut diff = VersionDiff::try_from(
+        "diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -1,3 +1,3 @@
+ line a
+-line  b
++line b
+ line c
+@@ -4,2 +4,2 @@
+-line d
++patched d
+ line e
+diff -Naur version-0/other.txt version-1/other.txt
+--- version-0/other.txt
++++ version-1/other.txt
+@@ -1,2 +1,3 @@
+ first
++
+ second
+"
+        .to_string(),
+    )
+    .unwrap();
+    let classes: Vec<HunkClass> = diff
+        .file_diffs()
+        .iter()
+        .flat_map(|d| d.hunks())
+        .map(|h| h.classify())
+        .collect();
+    assert_eq!(
+        vec![
+            HunkClass::Whitespace,
+            HunkClass::Modification,
+            HunkClass::Whitespace
+        ],
+        classes
+    );
+
+    diff.retain_hunks(|hunk| hunk.classify() != HunkClass::Whitespace);
+    assert_eq!(1, diff.len());
+    let file_diff = diff.file_diffs()[0].clone();
+    assert_eq!(1, file_diff.hunks().len());
+
+    // The remaining hunk still applies to the source file
+    let source_path = std::env::temp_dir().join("mpatch_drop_whitespace_only_hunks.txt");
+    std::fs::write(&source_path, "line a\nline  b\nline c\nline d\nline e\n").unwrap();
+    let source = FileArtifact::read(&source_path).unwrap();
+    let matching = LCSMatcher.match_files(source.clone(), source);
+    let filtered_patch = KeepAllFilter
+        .apply_filter(FilePatch::from(file_diff), &matching)
+        .unwrap();
+    let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+    assert!(outcome.rejected_changes().is_empty());
+    assert_eq!(
+        ["line a", "line  b", "line c", "patched d", "line e"],
+        outcome.patched_file().lines()
+    );
+    std::fs::remove_file(source_path).unwrap();
+}
+
+/// A matcher that counts how often it is invoked.
+struct CountingMatcher(std::rc::Rc<std::cell::Cell<usize>>);
+
+impl Matcher for CountingMatcher {
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> mpatch::Matching {
+        self.0.set(self.0.get() + 1);
+        LCSMatcher.match_files(source, target)
+    }
+}
+
+#[test]
+fn skip_matching_of_identical_target() {
+    let work_dir = setup_work_dir(
+        "skip_matching_of_identical_target",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        SINGLE_FILE_DIFF,
+    );
+
+    let invocations = std::rc::Rc::new(std::cell::Cell::new(0));
+    let apply = || {
+        let matcher = CountingMatcher(invocations.clone());
+        mpatch::apply_all(work_dir.patch_paths(), 1, false, matcher, KeepAllFilter).unwrap();
+    };
+
+    apply();
+    assert_eq!(0, invocations.get());
+    assert_eq!(
+        "line a\npatched b\nline c\nline d\nline e",
+        work_dir.read_target("file.txt")
+    );
+
+    // A target that differs from the source is matched
+    std::fs::write(
+        work_dir.target_dir.join("file.txt"),
+        format!("header\n{SINGLE_FILE_CONTENT}"),
+    )
+    .unwrap();
+    apply();
+    assert_eq!(1, invocations.get());
+    assert_eq!(
+        "header\nline a\npatched b\nline c\nline d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn handle_duplicate_targets() {
+    use mpatch::DuplicateTargetPolicy;
+
+    let work_dir = setup_work_dir(
+        "handle_duplicate_targets",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        DUPLICATE_TARGETS_DIFF,
+    );
+    assert!(VersionDiff::read(&work_dir.diff_file)
+        .unwrap()
+        .has_duplicate_targets());
+
+    let apply = |duplicate_targets| {
+        let options = PatchOptions::builder()
+            .duplicate_targets(duplicate_targets)
+            .build();
+        mpatch::apply_all_with_options(
+            work_dir.patch_paths(),
+            1,
+            LCSMatcher,
+            KeepAllFilter,
+            &options,
+        )
+    };
+
+    let error = apply(DuplicateTargetPolicy::Error).unwrap_err();
+    assert_eq!(ErrorKind::PatchError, *error.kind());
+    assert_eq!(SINGLE_FILE_CONTENT, work_dir.read_target("file.txt"));
+
+    apply(DuplicateTargetPolicy::Merge).unwrap();
+    assert_eq!(
+        "line a\npatched b\nline c\npatched d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn merge_concatenated_diffs_that_change_the_line_count() {
+    use mpatch::DuplicateTargetPolicy;
+
+    // The second file diff has been created for the file as patched by the first one
+    const CONCATENATED_DIFF: &str = "diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -1,2 +1,4 @@
+ line a
++added 1
++added 2
+ line b
+diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -4,3 +4,3 @@
+ line b
+-line c
++patched c
+ line d
+";
+    let work_dir = setup_work_dir(
+        "merge_concatenated_diffs",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        CONCATENATED_DIFF,
+    );
+
+    let options = PatchOptions::builder()
+        .duplicate_targets(DuplicateTargetPolicy::Merge)
+        .build();
+    mpatch::apply_all_with_options(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        "line a\nadded 1\nadded 2\nline b\npatched c\nline d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn resolve_differently_cased_target() {
+    let work_dir = setup_work_dir(
+        "resolve_differently_cased_target",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        SINGLE_FILE_DIFF,
+    );
+    // The target file only differs in case from the file in the diff
+    let target_file = work_dir.target_dir.join("FILE.txt");
+    std::fs::rename(work_dir.target_dir.join("file.txt"), &target_file).unwrap();
+
+    let options = PatchOptions::builder().case_insensitive_paths(true).build();
+    mpatch::apply_all_with_options(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        "line a\npatched b\nline c\nline d\nline e",
+        work_dir.read_target("FILE.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn skip_already_patched_target() {
+    let work_dir = setup_work_dir(
+        "skip_already_patched_target",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        SINGLE_FILE_DIFF,
+    );
+    // The target file has already been patched
+    let patched = "line a\npatched b\nline c\nline d\nline e\n";
+    std::fs::write(work_dir.target_dir.join("file.txt"), patched).unwrap();
+    let rejects_file = work_dir.root.join("rejects.txt");
+
+    let patch_paths = PatchPaths::new(
+        work_dir.source_dir.clone(),
+        work_dir.target_dir.clone(),
+        work_dir.diff_file.clone(),
+        Some(rejects_file.clone()),
+    );
+    let options = PatchOptions::builder().skip_already_applied(true).build();
+    mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+    // The file has not been rewritten, which would have dropped its final newline
+    assert_eq!(patched, work_dir.read_target("file.txt"));
+    assert!(!rejects_file.exists());
+    work_dir.remove();
+}
+
+#[test]
+fn detect_stale_source_file() {
+    use std::fs;
+
+    let work_dir = setup_work_dir(
+        "detect_stale_source_file",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        SINGLE_FILE_DIFF,
+    );
+    let source_file = work_dir.source_dir.join("file.txt");
+    let source_hash = FileArtifact::read(&source_file).unwrap().content_hash();
+    let apply = |options: &PatchOptions| {
+        mpatch::apply_all_with_options(
+            work_dir.patch_paths(),
+            1,
+            LCSMatcher,
+            KeepAllFilter,
+            options,
+        )
+    };
+
+    // The source file has been modified since the diff was created
+    fs::write(&source_file, "line a\nline b\nline c\n").unwrap();
+    let options = PatchOptions::builder()
+        .expected_source_hash("file.txt", source_hash.clone())
+        .build();
+    let error = apply(&options).unwrap_err();
+    assert_eq!(ErrorKind::PatchError, *error.kind());
+    assert!(error.message().contains(&source_hash));
+    assert_eq!(SINGLE_FILE_CONTENT, work_dir.read_target("file.txt"));
+
+    // Source files without an expected hash are not checked
+    let other_options = PatchOptions::builder()
+        .expected_source_hash("other.txt", source_hash.clone())
+        .dryrun(true)
+        .build();
+    apply(&other_options).unwrap();
+
+    fs::write(&source_file, SINGLE_FILE_CONTENT).unwrap();
+    apply(&options).unwrap();
+    assert_eq!(
+        "line a\npatched b\nline c\nline d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn abort_when_exceeding_edit_budget() {
+    // Each of the two file diffs replaces a line with two edits
+    let work_dir = setup_work_dir(
+        "abort_when_exceeding_edit_budget",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        DUPLICATE_TARGETS_DIFF,
+    );
+
+    let options = PatchOptions::builder().max_total_edits(3).build();
+    let error = mpatch::apply_all_with_options(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::PatchError, *error.kind());
+    assert!(error.message().starts_with(
+        "the patch exceeds the maximum of 3 edits: 2 edits have been applied to 1 file(s)"
+    ));
+    // Only the first file diff has been applied
+    assert_eq!(
+        "line a\npatched b\nline c\nline d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn patch_without_source_variant() {
+    // Like the classic patch tool, the changes are located by the context lines of the diff
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/missing_variant/version-0"),
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        PathBuf::from(MIXED_DIFF),
+        None,
+    );
+    let matcher = ContextMatcher::new(VersionDiff::read(MIXED_DIFF).unwrap());
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results =
+        mpatch::apply_all_collect(patch_paths, 1, matcher, KeepAllFilter, &options).unwrap();
+    assert_eq!(1, results.len());
+
+    let outcome = results[0].1.as_ref().unwrap();
+    assert!(outcome.is_clean());
+    let expected = FileArtifact::read("tests/samples/source_variant/version-1/mixed.c").unwrap();
+    assert_eq!(expected.lines(), outcome.patched_file().lines());
+}
+
+#[test]
+fn create_missing_target_root() {
+    let work_dir = setup_work_dir("create_missing_target_root", &[], "");
+    let target_dir = work_dir.root.join("missing");
+    let patch_paths = || {
+        PatchPaths::new(
+            PathBuf::from("tests/samples/source_variant/version-0"),
+            target_dir.clone(),
+            PathBuf::from(CREATION_DIFF),
+            None,
+        )
+    };
+
+    // By default, a missing target directory is an error
+    let error = mpatch::apply_all_with_options(
+        patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &PatchOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::IOError, *error.kind());
+    assert!(!target_dir.exists());
+
+    let options = PatchOptions::builder().create_target_root(true).build();
+    mpatch::apply_all_with_options(patch_paths(), 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+    let read = |path| std::fs::read_to_string(target_dir.join(path)).unwrap();
+    assert_eq!("#include <stdio.h>\nint created;", read("created.c"));
+    assert_eq!("int created();", read("nested/created.h"));
+    work_dir.remove();
+}
+
+#[test]
+fn sort_file_diffs_by_target_path() {
+    // Reverse the order of the file diffs
+    let diff = VersionDiff::read(CREATION_DIFF).unwrap();
+    let file_diffs: Vec<String> = diff.into_iter().map(|d| d.to_string()).rev().collect();
+    // All files exist already, so that their creation is rejected
+    let work_dir = setup_work_dir(
+        "sort_file_diffs_by_target_path",
+        &[("created.c", ""), ("nested/created.h", "")],
+        &file_diffs.join("\n"),
+    );
+
+    let rejects_file = work_dir.root.join("rejects.txt");
+    let header_order = |sort_files| {
+        let patch_paths = PatchPaths::new(
+            PathBuf::from("tests/samples/source_variant/version-0"),
+            work_dir.target_dir.clone(),
+            work_dir.diff_file.clone(),
+            Some(rejects_file.clone()),
+        );
+        let options = PatchOptions::builder()
+            .dryrun(true)
+            .sort_files(sort_files)
+            .build();
+        mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options)
+            .unwrap();
+        let rejects = std::fs::read_to_string(&rejects_file).unwrap();
+        let headers: Vec<String> = rejects
+            .lines()
+            .filter(|l| l.starts_with("diff "))
+            .map(String::from)
+            .collect();
+        headers
+    };
+
+    let unsorted = header_order(false);
+    assert_eq!(2, unsorted.len());
+    assert!(unsorted[0].ends_with("version-1/nested/created.h"));
+    let sorted = header_order(true);
+    assert_eq!(2, sorted.len());
+    assert!(sorted[0].ends_with("version-1/created.c"));
+    assert!(sorted[1].ends_with("version-1/nested/created.h"));
+    work_dir.remove();
+}
+
+/// A matcher that checks how far the application of patches lags behind their alignment.
+struct QueueDepthProbe {
+    target_dir: PathBuf,
+    max_in_flight: usize,
+    aligned_files: usize,
+}
+
+impl Matcher for QueueDepthProbe {
+    fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> mpatch::Matching {
+        // At most max_in_flight patches wait in the queue and the worker applies one patch; all
+        // earlier files must have been written already
+        if let Some(applied) = self.aligned_files.checked_sub(self.max_in_flight + 2) {
+            let applied_file = self.target_dir.join(format!("file_{applied}.txt"));
+            let content = std::fs::read_to_string(applied_file).unwrap();
+            assert!(content.contains(&format!("patched {applied}")));
+        }
+        self.aligned_files += 1;
+        LCSMatcher.match_files(source, target)
+    }
+}
+
+#[test]
+fn apply_many_files_with_bounded_queue() {
+    const FILE_COUNT: usize = 500;
+    const MAX_IN_FLIGHT: usize = 4;
+
+    let file_names: Vec<String> = (0..FILE_COUNT).map(|i| format!("file_{i}.txt")).collect();
+    let files: Vec<(&str, &str)> = file_names
+        .iter()
+        .map(|name| (name.as_str(), "line a\nline b\nline c\n"))
+        .collect();
+    let mut diff = String::new();
+    for (i, file_name) in file_names.iter().enumerate() {
+        diff.push_str(&format!(
+            "diff -Naur version-0/{file_name} version-1/{file_name}\n\
+             --- version-0/{file_name}\n\
+             +++ version-1/{file_name}\n\
+             @@ -1,3 +1,3 @@\n line a\n-line b\n+patched {i}\n line c\n"
+        ));
+    }
+    let work_dir = setup_work_dir("apply_many_files_with_bounded_queue", &files, &diff);
+
+    let matcher = QueueDepthProbe {
+        target_dir: work_dir.target_dir.clone(),
+        max_in_flight: MAX_IN_FLIGHT,
+        aligned_files: 0,
+    };
+    let options = PatchOptions::builder().max_in_flight(MAX_IN_FLIGHT).build();
+    mpatch::apply_all_with_options(work_dir.patch_paths(), 1, matcher, KeepAllFilter, &options)
+        .unwrap();
+
+    for (i, file_name) in file_names.iter().enumerate() {
+        let patched = FileArtifact::read(work_dir.target_dir.join(file_name)).unwrap();
+        assert_eq!(
+            [
+                "line a".to_string(),
+                format!("patched {i}"),
+                "line c".to_string()
+            ],
+            patched.lines()
+        );
+    }
+    work_dir.remove();
+}
+
+#[test]
+fn reject_missing_target_root_for_modifications() {
+    let options = PatchOptions {
+        dryrun: true,
+        create_target_root: true,
+        ..Default::default()
+    };
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        PathBuf::from("tests/samples/missing_variant/version-0"),
+        PathBuf::from(MIXED_DIFF),
+        None,
+    );
+    let error = mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options)
+        .unwrap_err();
+    assert_eq!(ErrorKind::IOError, *error.kind());
+}
+
+#[test]
+fn choose_best_matching_source_variant() {
+    let patch_paths = |alternative_source_dirs| {
+        PatchPaths::new(
+            // The first source variant does not contain the patched file
+            PathBuf::from("tests/collect/source_variant/version-0"),
+            PathBuf::from("tests/samples/target_variant/version-0"),
+            PathBuf::from(MIXED_DIFF),
+            None,
+        )
+        .with_alternative_source_dirs(alternative_source_dirs)
+    };
+
+    // Without the second source variant, the removed lines cannot be matched
+    let options = PatchOptions::builder().dryrun(true).build();
+    let results =
+        mpatch::apply_all_collect(patch_paths(vec![]), 1, LCSMatcher, KeepAllFilter, &options)
+            .unwrap();
+    assert!(results[0].1.as_ref().unwrap().had_rejects());
+
+    let results = mpatch::apply_all_collect(
+        patch_paths(vec![PathBuf::from(MIXED_SOURCE_DIR)]),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    let outcome = results[0].1.as_ref().unwrap();
+    assert!(outcome.is_clean());
+    let expected = FileArtifact::read(EXPECTED_MIXED_RESULT).unwrap();
+    assert!(outcome.patched_file().compare(&expected).is_empty());
+}
+
+#[test]
+fn preview_rejects_of_patch() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        PathBuf::from("tests/samples/target_variant/version-0"),
+        PathBuf::from(NON_EXISTANT_DIFF),
+        None,
+    );
+    let previews = mpatch::preview_rejects(patch_paths, 1, LCSMatcher, KeepAllFilter).unwrap();
+    assert_eq!(1, previews.len());
+
+    let (path, rejects) = &previews[0];
+    assert_eq!(&PathBuf::from(NON_EXISTANT_TARGET), path);
+    assert_eq!(1, rejects.len());
+    assert_eq!(1, rejects[0].change_id());
+    assert_eq!("  // Ask the user for input", rejects[0].line());
+    assert_eq!(Some(RejectReason::NoMatch), rejects[0].reject_reason());
+
+    // The preview yields the same rejects as a dryrun
+    let aligned_patch =
+        get_aligned_patch(NON_EXISTANT_SOURCE, NON_EXISTANT_TARGET, NON_EXISTANT_DIFF);
+    assert_eq!(
+        rejects.as_slice(),
+        apply_patch(aligned_patch, true).unwrap().rejected_changes()
+    );
+}
+
+#[test]
+fn convert_patch_back_into_diff() {
+    let source = FileArtifact::read(MIXED_SOURCE).unwrap();
+    let file_diff = VersionDiff::read(MIXED_DIFF).unwrap().file_diffs()[0].clone();
+    let patch = FilePatch::from(file_diff.clone());
+
+    let reconstructed = patch.to_file_diff(&source, 3);
+    assert_eq!(file_diff.normalize().hunks(), reconstructed.hunks());
+    assert_eq!(source.path(), reconstructed.source_file_header().path());
+    // The round trip preserves the changes; only their order is normalized
+    assert_eq!(
+        FilePatch::from(file_diff.normalize()),
+        FilePatch::from(reconstructed)
+    );
+}
+
+#[test]
+fn dump_matchings_of_multi_file_diff() {
+    let work_dir = setup_work_dir("dump_matchings_of_multi_file_diff", &[], "");
+    let dump_dir = work_dir.root.join("matchings");
+
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        PathBuf::from("tests/samples/target_variant/version-0"),
+        PathBuf::from("tests/samples/source_variant/patch.diff"),
+        None,
+    );
+    let options = PatchOptions {
+        dryrun: true,
+        dump_matching_dir: Some(dump_dir.clone()),
+        ..Default::default()
+    };
+    mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+
+    for file in [
+        "additive.c",
+        "invariant.c",
+        "main.c",
+        "mixed.c",
+        "remove_non_existant.c",
+        "substractive.c",
+        "appending.c",
+    ] {
+        let dump = std::fs::read_to_string(dump_dir.join(format!("{file}.csv"))).unwrap();
+        assert!(dump.starts_with("source_line,target_line\n"));
+    }
+    work_dir.remove();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn stream_rejects_as_ndjson() {
+    use mpatch::RejectSink;
+    use std::fs;
+
+    let target = fs::read_to_string(NON_EXISTANT_TARGET).unwrap();
+    let work_dir = setup_work_dir(
+        "stream_rejects_as_ndjson",
+        &[("remove_non_existant.c", &target)],
+        "",
+    );
+    let ndjson_path = work_dir.root.join("rejects.ndjson");
+    // Stale rejects of an earlier run are discarded
+    fs::write(&ndjson_path, "{}\n").unwrap();
+
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        work_dir.target_dir.clone(),
+        PathBuf::from(NON_EXISTANT_DIFF),
+        None,
+    );
+    let options = PatchOptions::builder()
+        .dryrun(true)
+        .reject_sink(RejectSink::Ndjson(ndjson_path.clone()))
+        .build();
+    mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+
+    let stream = fs::read_to_string(&ndjson_path).unwrap();
+    let rejects: Vec<serde_json::Value> = stream
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        vec![serde_json::json!({
+            "file": work_dir.target_dir.join("remove_non_existant.c").to_string_lossy(),
+            "change_id": 1,
+            "change_type": "Remove",
+            "line": 5,
+            "content": "  // Ask the user for input",
+            "reason": "NoMatch"
+        })],
+        rejects
+    );
+    work_dir.remove();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn write_rejects_as_json_sidecar() {
+    use mpatch::RejectSink;
+    use std::fs;
+
+    // The sidecar is written next to the target file; thus, we patch a copy of the target
+    let target = fs::read_to_string(NON_EXISTANT_TARGET).unwrap();
+    let work_dir = setup_work_dir(
+        "write_rejects_as_json_sidecar",
+        &[("remove_non_existant.c", &target)],
+        "",
+    );
+
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        work_dir.target_dir.clone(),
+        PathBuf::from(NON_EXISTANT_DIFF),
+        None,
+    );
+    let options = PatchOptions {
+        dryrun: true,
+        reject_sink: Some(RejectSink::JsonSidecar),
+        ..Default::default()
+    };
+    mpatch::apply_all_with_options(patch_paths, 1, LCSMatcher, KeepAllFilter, &options).unwrap();
+
+    let sidecar = work_dir.read_target("remove_non_existant.c.rej.json");
+    let rejects: serde_json::Value = serde_json::from_str(&sidecar).unwrap();
+    assert_eq!(
+        serde_json::json!([{
+            "change_id": 1,
+            "change_type": "Remove",
+            "line": 5,
+            "content": "  // Ask the user for input",
+            "reason": "NoMatch"
+        }]),
+        rejects
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn aggregate_stats_across_files() {
+    let patch_paths = PatchPaths::new(
+        PathBuf::from("tests/samples/source_variant/version-0"),
+        PathBuf::from("tests/samples/target_variant/version-0"),
+        PathBuf::from("tests/samples/source_variant/patch.diff"),
+        None,
+    );
+    let stats = mpatch::apply_all(patch_paths, 1, true, LCSMatcher, KeepAllFilter).unwrap();
+    assert_eq!(7, stats.patched_files());
+    assert_eq!(22, stats.added_lines());
+    // The remove in remove_non_existant.c has no counterpart in the target
+    assert_eq!(16, stats.removed_lines());
+    assert_eq!(1, stats.rejected_changes());
+    assert_eq!(38, stats.applied_changes());
+}
+
+#[test]
+fn reapply_reject_diff_with_context() {
+    use mpatch::application::apply_patch_with_options;
+    use mpatch::WhitespaceMode;
+    use std::fs;
+
+    let work_dir = setup_work_dir(
+        "reapply_reject_diff_with_context",
+        &[("main.c", "int a;\nint b;\nint c;\nint d;\n")],
+        "",
+    );
+    let target_path = work_dir.target_dir.join("main.c");
+    let diff = "diff -Naur version-0/main.c version-1/main.c
+--- version-0/main.c
++++ version-1/main.c
+@@ -1,4 +1,6 @@
+ int a;
++int x; 
+ int b;
+ int c;
++int y;
+ int d;";
+    let file_diff = VersionDiff::try_from(diff.to_string())
+        .unwrap()
+        .into_iter()
+        .next()
+        .unwrap();
+
+    // The add with trailing whitespace is rejected, the other add is applied
+    let patch = FilePatch::from(file_diff);
+    let target = FileArtifact::read(&target_path).unwrap();
+    let matching = LCSMatcher.match_files(target.clone(), target);
+    let filtered_patch = KeepAllFilter
+        .apply_filter(patch.clone(), &matching)
+        .unwrap();
+    let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+    let options = PatchOptions::builder()
+        .whitespace(WhitespaceMode::Error)
+        .build();
+    let outcome = apply_patch_with_options(aligned_patch, &options).unwrap();
+    assert_eq!(1, outcome.rejected_changes().len());
+
+    // The reject hunk contains the context and the applied add as context
+    let reject_diff = patch
+        .reject_diff(Path::new("main.c"), outcome.rejected_changes())
+        .unwrap();
+    assert_eq!(
+        "diff -Naur main.c main.c
+--- main.c
++++ main.c
+@@ -1,5 +1,6 @@
+ int a;
++int x; 
+ int b;
+ int c;
+ int y;
+ int d;",
+        reject_diff.to_string()
+    );
+    let rejects_file = work_dir.target_dir.join("main.c.rej");
+    fs::write(&rejects_file, reject_diff.to_string()).unwrap();
+
+    // The rejects file is located in the patched file by its context
+    let matcher = ContextMatcher::new(VersionDiff::read(&rejects_file).unwrap());
+    let patch_paths = PatchPaths::new(
+        work_dir.target_dir.clone(),
+        work_dir.target_dir.clone(),
+        rejects_file,
+        None,
+    );
+    let stats = mpatch::apply_all(patch_paths, 0, false, matcher, KeepAllFilter).unwrap();
+    assert_eq!(0, stats.rejected_changes());
+    assert_eq!(
+        "int a;\nint x; \nint b;\nint c;\nint y;\nint d;",
+        work_dir.read_target("main.c")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn rewrite_target_paths() {
+    let work_dir = setup_work_dir(
+        "rewrite_target_paths",
+        &[("src/foo.c", "int a;\n")],
+        "diff -Naur version-0/src/foo.c version-1/src/foo.c
+--- version-0/src/foo.c
++++ version-1/src/foo.c
+@@ -1 +1,2 @@
+ int a;
++int b;
+",
+    );
+    // The target variant contains the patched file in a vendor directory
+    let vendor_dir = work_dir.target_dir.join("vendor");
+    std::fs::create_dir_all(&vendor_dir).unwrap();
+    std::fs::rename(work_dir.target_dir.join("src"), vendor_dir.join("src")).unwrap();
+
+    let target_root = work_dir.target_dir.clone();
+    let patch_paths = work_dir
+        .patch_paths()
+        .with_target_path_rewrite(move |path| {
+            vendor_dir.join(path.strip_prefix(&target_root).unwrap())
+        });
+    let stats = mpatch::apply_all(patch_paths, 1, false, LCSMatcher, KeepAllFilter).unwrap();
+    assert_eq!(1, stats.applied_changes());
+    assert_eq!("int a;\nint b;", work_dir.read_target("vendor/src/foo.c"));
+    assert!(!work_dir.target_dir.join("src/foo.c").exists());
+    work_dir.remove();
+}
+
+#[test]
+fn skip_whitespace_only_hunks() {
+    let work_dir = setup_work_dir(
+        "skip_whitespace_only_hunks",
+        &[("file.txt", "line a\nline b \nline c\nline d\nline e\n")],
+        "diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -1,3 +1,3 @@
+ line a
+-line b 
++line b
+ line c
+@@ -4,2 +4,2 @@
+-line d
++patched d
+ line e
+",
+    );
+    let diff = VersionDiff::read(&work_dir.diff_file).unwrap();
+    let hunks = diff.file_diffs()[0].hunks();
+    assert!(hunks[0].is_whitespace_only());
+    assert!(!hunks[1].is_whitespace_only());
+
+    let options = PatchOptions::builder().skip_whitespace_hunks(true).build();
+    let stats = mpatch::apply_all_with_options(
+        work_dir.patch_paths(),
+        1,
+        LCSMatcher,
+        KeepAllFilter,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(2, stats.applied_changes());
+    assert_eq!(
+        "line a\nline b \nline c\npatched d\nline e",
+        work_dir.read_target("file.txt")
+    );
+    work_dir.remove();
+}
+
+#[test]
+fn show_rejects_as_diff() {
+    let aligned_patch =
+        get_aligned_patch(NON_EXISTANT_SOURCE, NON_EXISTANT_TARGET, NON_EXISTANT_DIFF);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+    assert_eq!(1, outcome.rejected_changes().len());
+    // The rejected remove is placed after the applied remove that precedes it in the patch
+    let diff = outcome.rejects_as_diff().unwrap();
+    let path = NON_EXISTANT_TARGET;
+    assert_eq!(
+        format!(
+            "diff -Naur {path} {path}
+--- {path}
++++ {path}
+@@ -1,7 +1,6 @@
+ #include <stdio.h>
+ int main() {{
+   int number;
+-  // Ask the user for input
+   scanf(\"%d\", &number);
+   // Check if the user has entered a negative integer
+   if (number < 0) {{"
+        ),
+        diff.to_string()
+    );
+
+    let aligned_patch = get_aligned_patch(INVARIANT_SOURCE, INVARIANT_TARGET, INVARIANT_DIFF);
+    let outcome = apply_patch(aligned_patch, true).unwrap();
+    assert!(outcome.rejects_as_diff().is_none());
+}
+
+#[test]
+fn cancel_patch_application_between_files() {
+    use mpatch::CancelFlag;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Sets the flag once the second file is matched
+    struct CancellingMatcher(Arc<AtomicBool>, usize);
+
+    impl Matcher for CancellingMatcher {
+        fn match_files(&mut self, source: FileArtifact, target: FileArtifact) -> mpatch::Matching {
+            self.1 += 1;
+            if self.1 == 2 {
+                self.0.store(true, Ordering::SeqCst);
+            }
+            LCSMatcher.match_files(source, target)
+        }
+    }
+
+    const FILE_NAMES: [&str; 3] = ["first.txt", "second.txt", "third.txt"];
+    let mut diff = String::new();
+    for name in FILE_NAMES {
+        diff.push_str(&format!(
+            "diff -Naur version-0/{name} version-1/{name}
+--- version-0/{name}
++++ version-1/{name}
+@@ -1 +1,2 @@
+ line a
++line b
+"
+        ));
+    }
+    let files = FILE_NAMES.map(|name| (name, "line a\n"));
+    let work_dir = setup_work_dir("cancel_patch_application_between_files", &files, &diff);
+    for name in FILE_NAMES {
+        // The target differs from the source, so that it is matched
+        fs::write(work_dir.target_dir.join(name), "header\nline a\n").unwrap();
+    }
+
+    let flag = Arc::new(AtomicBool::new(false));
+    let options = PatchOptions::builder().cancel_flag(flag.clone()).build();
+    let matcher = CancellingMatcher(flag.clone(), 0);
+    let error =
+        mpatch::apply_all_with_options(work_dir.patch_paths(), 1, matcher, KeepAllFilter, &options)
+            .unwrap_err();
+    assert_eq!(&ErrorKind::Cancelled, error.kind());
+    assert!(CancelFlag::from(flag).is_cancelled());
+
+    // Only the first file has been patched
+    assert_eq!("header\nline a\nline b", work_dir.read_target("first.txt"));
+    assert_eq!("header\nline a\n", work_dir.read_target("second.txt"));
+    assert_eq!("header\nline a\n", work_dir.read_target("third.txt"));
+    work_dir.remove();
+}
+
+#[test]
+fn apply_git_diff_with_created_and_removed_files() {
+    // Created with `git diff --cached` after adding created.txt and removing removed.txt
+    const GIT_DIFF: &str = "diff --git a/created.txt b/created.txt
+new file mode 100644
+index 0000000..a3a1f63
+--- /dev/null
++++ b/created.txt
+@@ -0,0 +1,2 @@
++new line 1
++new line 2
+diff --git a/removed.txt b/removed.txt
+deleted file mode 100644
+index 06fcdd7..0000000
+--- a/removed.txt
++++ /dev/null
+@@ -1,2 +0,0 @@
+-first line
+-second line
+";
+
+    let version_diff = VersionDiff::try_from(GIT_DIFF.to_string()).unwrap();
+    let file_diffs = version_diff.file_diffs();
+    assert!(file_diffs[0].is_creation());
+    assert_eq!(
+        Path::new("b/created.txt"),
+        file_diffs[0].changed_file_path()
+    );
+    assert!(file_diffs[1].is_removal());
+    assert_eq!(
+        Path::new("a/removed.txt"),
+        file_diffs[1].changed_file_path()
+    );
+    assert_eq!(GIT_DIFF.trim_end(), version_diff.to_string().trim_end());
+
+    let work_dir = setup_work_dir(
+        "apply_git_diff_with_created_and_removed_files",
+        &[("removed.txt", "first line\nsecond line\n")],
+        GIT_DIFF,
+    );
+
+    let stats =
+        mpatch::apply_all(work_dir.patch_paths(), 1, false, LCSMatcher, KeepAllFilter).unwrap();
+    assert_eq!(0, stats.rejected_changes());
+    assert_eq!(
+        "new line 1\nnew line 2",
+        work_dir.read_target("created.txt")
+    );
+    assert!(!work_dir.target_dir.join("removed.txt").exists());
+    assert!(!work_dir.target_dir.join("dev").exists());
+    work_dir.remove();
+}
+
+#[test]
+#[cfg(unix)]
+fn apply_git_mode_change() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Created with `git diff` after making script.sh executable
+    const GIT_DIFF: &str = "diff --git a/script.sh b/script.sh
+old mode 100644
+new mode 100755";
+
+    let version_diff = VersionDiff::try_from(GIT_DIFF.to_string()).unwrap();
+    let file_diff = &version_diff.file_diffs()[0];
+    assert_eq!(Some(0o755), file_diff.mode_change());
+    assert!(file_diff.hunks().is_empty());
+    assert_eq!(Path::new("b/script.sh"), file_diff.changed_file_path());
+    assert_eq!(GIT_DIFF, version_diff.to_string());
+
+    let work_dir = setup_work_dir(
+        "apply_git_mode_change",
+        &[("script.sh", "echo target\n")],
+        GIT_DIFF,
+    );
+    fs::write(work_dir.source_dir.join("script.sh"), "echo source").unwrap();
+
+    mpatch::apply_all(work_dir.patch_paths(), 1, false, LCSMatcher, KeepAllFilter).unwrap();
+    let target_file = work_dir.target_dir.join("script.sh");
+    let mode = fs::metadata(&target_file).unwrap().permissions().mode();
+    assert_eq!(0o755, mode & 0o777);
+    // The content of the target, including its final newline, is not changed
+    assert_eq!("echo target\n", work_dir.read_target("script.sh"));
+    work_dir.remove();
+}
+
+#[test]
+fn apply_git_rename_diff() {
+    // Created with `git diff -M` after renaming kept.txt without changing it
+    const GIT_DIFF: &str = "diff --git a/kept.txt b/renamed.txt
+similarity index 100%
+rename from kept.txt
+rename to renamed.txt";
+
+    let version_diff = VersionDiff::try_from(GIT_DIFF.to_string()).unwrap();
+    let file_diff = version_diff.file_diffs()[0].clone();
+    assert!(file_diff.hunks().is_empty());
+    assert_eq!(Path::new("b/renamed.txt"), file_diff.changed_file_path());
+    assert_eq!(GIT_DIFF, version_diff.to_string());
+    let file_patch = FilePatch::from(file_diff);
+    assert!(file_patch.changes().is_empty());
+
+    let work_dir = setup_work_dir("apply_git_rename_diff", &[], GIT_DIFF);
+    std::fs::write(work_dir.source_dir.join("kept.txt"), "kept").unwrap();
+    std::fs::write(work_dir.target_dir.join("renamed.txt"), "kept\n").unwrap();
+
+    mpatch::apply_all(work_dir.patch_paths(), 1, false, LCSMatcher, KeepAllFilter).unwrap();
+    // The content of the target, including its final newline, is not changed
+    assert_eq!("kept\n", work_dir.read_target("renamed.txt"));
+    work_dir.remove();
+}
+
+#[test]
+fn apply_normal_diffs_to_a_longer_file() {
+    let work_dir = setup_work_dir(
+        "apply_normal_diffs_to_a_longer_file",
+        &[("file.txt", SINGLE_FILE_CONTENT)],
+        "",
+    );
+    let file_path = work_dir.target_dir.join("file.txt");
+    for (normal_diff, expected_lines) in [
+        (
+            "diff v0/file.txt v1/file.txt\n1,3d0\n< line a\n< line b\n< line c",
+            vec!["line d", "line e"],
+        ),
+        (
+            "diff v0/file.txt v1/file.txt\n0a1\n> header",
+            vec!["header", "line a", "line b", "line c", "line d", "line e"],
+        ),
+        // Lines are added after the line before the added range
+        (
+            "diff v0/file.txt v1/file.txt\n2a3\n> inserted",
+            vec!["line a", "line b", "inserted", "line c", "line d", "line e"],
+        ),
+    ] {
+        let file_diff = VersionDiff::from_normal(normal_diff.to_string())
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        // Hunks that start at line 0 do not create or remove the file in the normal format
+        assert_eq!(FileChangeType::Modify, file_diff.change_type());
+
+        let file = FileArtifact::read(&file_path).unwrap();
+        let matching = LCSMatcher.match_files(file.clone(), file);
+        let filtered_patch = KeepAllFilter
+            .apply_filter(FilePatch::from(file_diff), &matching)
+            .unwrap();
+        let aligned_patch = align_filtered_patch_to_target(filtered_patch, matching);
+        let outcome = apply_patch(aligned_patch, true).unwrap();
+        assert!(outcome.is_clean());
+        assert_eq!(expected_lines, outcome.patched_file().lines());
+    }
+    work_dir.remove();
+}
@@ -0,0 +1,41 @@
+pub mod test_utils;
+
+use std::fs;
+
+use mpatch::{FilterChoice, PatchOptions, RunConfig};
+use test_utils::{setup_work_dir, SINGLE_FILE_DIFF};
+
+#[test]
+fn run_with_config() {
+    let work_dir = setup_work_dir(
+        "run_with_config",
+        &[("file.txt", "line a\nline b\nline c\n")],
+        SINGLE_FILE_DIFF,
+    );
+    // The target lacks the context above the change
+    fs::write(work_dir.target_dir.join("file.txt"), "line b\nline c\n").unwrap();
+
+    let config = RunConfig {
+        strip: 1,
+        filter: FilterChoice::KeepAll,
+        options: PatchOptions::builder().dryrun(true).build(),
+        ..RunConfig::new(
+            &work_dir.source_dir,
+            &work_dir.target_dir,
+            &work_dir.diff_file,
+        )
+    };
+    let summary = mpatch::run(config.clone()).unwrap();
+    assert_eq!(1, summary.patched_files());
+    assert_eq!(2, summary.applied_changes());
+    assert_eq!(0, summary.rejected_changes());
+    assert_eq!("line b\nline c\n", work_dir.read_target("file.txt"));
+
+    let config = RunConfig {
+        options: PatchOptions::default(),
+        ..config
+    };
+    mpatch::run(config).unwrap();
+    assert_eq!("patched b\nline c", work_dir.read_target("file.txt"));
+    work_dir.remove();
+}
@@ -1,8 +1,25 @@
+use std::fs;
+use std::path::PathBuf;
+
 use mpatch::{
     alignment::align_patch_to_target, application::apply_patch, patch::Change, AlignedPatch,
-    FileArtifact, FilePatch, LCSMatcher, Matcher, VersionDiff,
+    FileArtifact, FilePatch, LCSMatcher, Matcher, PatchPaths, VersionDiff,
 };
 
+/// The content of `file.txt` to which `SINGLE_FILE_DIFF` applies.
+pub const SINGLE_FILE_CONTENT: &str = "line a\nline b\nline c\nline d\nline e\n";
+
+/// A diff that replaces `line b` in `file.txt` with `patched b`.
+pub const SINGLE_FILE_DIFF: &str = "diff -Naur version-0/file.txt version-1/file.txt
+--- version-0/file.txt
++++ version-1/file.txt
+@@ -1,3 +1,3 @@
+ line a
+-line b
++patched b
+ line c
+";
+
 pub fn run_alignment_test(source: &str, target: &str, diff: &str, expected_patch: &str) {
     let source = FileArtifact::read(source).unwrap();
     let target = FileArtifact::read(target).unwrap();
@@ -85,3 +102,58 @@ pub fn get_aligned_patch(source: &str, target: &str, diff: &str) -> AlignedPatch
     let patch = read_patch(diff);
     align_patch_to_target(patch, matching)
 }
+
+/// A temporary directory with a source variant, a target variant, and a diff file for tests that
+/// patch files on disk.
+pub struct WorkDir {
+    pub root: PathBuf,
+    pub source_dir: PathBuf,
+    pub target_dir: PathBuf,
+    pub diff_file: PathBuf,
+}
+
+impl WorkDir {
+    /// Returns the paths for applying the diff file to the target variant without a rejects file.
+    pub fn patch_paths(&self) -> PatchPaths {
+        PatchPaths::new(
+            self.source_dir.clone(),
+            self.target_dir.clone(),
+            self.diff_file.clone(),
+            None,
+        )
+    }
+
+    /// Returns the content of the file with the given path in the target variant.
+    pub fn read_target(&self, path: &str) -> String {
+        fs::read_to_string(self.target_dir.join(path)).unwrap()
+    }
+
+    /// Removes the work directory with all of its content.
+    pub fn remove(self) {
+        fs::remove_dir_all(&self.root).unwrap();
+    }
+}
+
+/// Creates an empty work directory with the given name in the temporary directory. Each of the
+/// given files (i.e., a path and its content) is written to the source and the target variant,
+/// and the diff is written to the diff file.
+pub fn setup_work_dir(name: &str, files: &[(&str, &str)], diff: &str) -> WorkDir {
+    let root = std::env::temp_dir().join(format!("mpatch_{name}"));
+    let _ = fs::remove_dir_all(&root);
+    let work_dir = WorkDir {
+        source_dir: root.join("source"),
+        target_dir: root.join("target"),
+        diff_file: root.join("patch.diff"),
+        root,
+    };
+    for dir in [&work_dir.source_dir, &work_dir.target_dir] {
+        fs::create_dir_all(dir).unwrap();
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+    fs::write(&work_dir.diff_file, diff).unwrap();
+    work_dir
+}
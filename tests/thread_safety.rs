@@ -0,0 +1,20 @@
+use mpatch::patch::{Change, FilteredPatch};
+use mpatch::{
+    AlignedPatch, FileArtifact, FileDiff, FilePatch, Matching, PatchOutcome, VersionDiff,
+};
+
+/// Fails to compile if the type cannot be shared between threads.
+fn _assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn core_types_are_send_and_sync() {
+    _assert_send_sync::<VersionDiff>();
+    _assert_send_sync::<FileDiff>();
+    _assert_send_sync::<FileArtifact>();
+    _assert_send_sync::<Change>();
+    _assert_send_sync::<FilePatch>();
+    _assert_send_sync::<FilteredPatch>();
+    _assert_send_sync::<Matching>();
+    _assert_send_sync::<AlignedPatch>();
+    _assert_send_sync::<PatchOutcome>();
+}